@@ -0,0 +1,228 @@
+use crate::{
+    magic_buf_alloc_at, magic_buf_free_at, magic_buf_reserve, magic_buf_unreserve, MagicBuffer,
+    MagicBufferError,
+};
+use std::{
+    marker::PhantomData,
+    ops::Range,
+    sync::{Arc, Mutex},
+};
+
+/// A [`MagicArena`] reserves a large contiguous region of virtual address space once
+/// and sub-allocates [`MagicBuffer`]s from it.
+///
+/// Every [`MagicBuffer`] reserves `2 * len` contiguous bytes of address space. Services
+/// that allocate and free many buffers of different sizes can fragment the address space
+/// over time. An arena amortizes the reservation and keeps all of its buffers within a
+/// single region. Buffers allocated from an arena behave like any other [`MagicBuffer`];
+/// dropping them returns their range to the arena, and the reservation itself is released
+/// once the arena and all of its buffers have been dropped.
+///
+/// This is currently only available on Linux.
+///
+/// # Examples
+/// ```
+/// # use magic_buffer::*;
+/// # fn main() -> Result<(), MagicBufferError> {
+/// let len = MagicBuffer::min_len();
+/// let arena = MagicArena::new(16 * len)?;
+/// let mut buf = arena.alloc(len)?;
+/// buf[0] = b'a';
+/// assert_eq!(b'a', buf[len]);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct MagicArena {
+    inner: Arc<ArenaInner>,
+}
+
+#[derive(Debug)]
+pub(crate) struct ArenaInner {
+    addr: *mut u8,
+    len: usize,
+    free: Mutex<Vec<Range<usize>>>,
+}
+
+// SAFETY: The reservation is not tied to a thread and all bookkeeping
+// is protected by a mutex.
+unsafe impl Send for ArenaInner {}
+
+// SAFETY: See above.
+unsafe impl Sync for ArenaInner {}
+
+#[allow(clippy::len_without_is_empty)]
+impl MagicArena {
+    /// Reserves `len` bytes of virtual address space for a new [`MagicArena`].
+    ///
+    /// `len` must be a multiple of [`MagicBuffer::min_len`]. No physical memory is
+    /// committed until buffers are allocated from the arena. Note that each buffer
+    /// takes up twice its `len` of the arena.
+    ///
+    /// ## Errors
+    /// Will return a [`MagicBufferError`] if the reservation fails.
+    pub fn new(len: usize) -> Result<Self, MagicBufferError> {
+        let min_len = MagicBuffer::min_len();
+        if len == 0 || len & (min_len - 1) != 0 {
+            return Err(MagicBufferError::InvalidLen {
+                msg: format!("arena len must be a non-zero multiple of {}", min_len),
+            });
+        }
+
+        Ok(Self {
+            inner: Arc::new(ArenaInner {
+                addr: unsafe { magic_buf_reserve(len) }?,
+                len,
+                free: Mutex::new(vec![Range { start: 0, end: len }]),
+            }),
+        })
+    }
+
+    /// Returns the len of the address space reserved by this [`MagicArena`].
+    pub fn len(&self) -> usize {
+        self.inner.len
+    }
+
+    /// Allocates a new [`MagicBuffer`] of the specified `len` from this arena.
+    ///
+    /// `len` follows the same rules as for [`MagicBuffer::new`].
+    ///
+    /// ## Errors
+    /// Will return a [`MagicBufferError`] if `len` is invalid, or if the arena does not
+    /// have a contiguous free region of `2 * len` bytes left.
+    pub fn alloc(&self, len: usize) -> Result<MagicBuffer, MagicBufferError> {
+        ArenaInner::alloc(&self.inner, len)
+    }
+}
+
+impl ArenaInner {
+    pub(crate) fn alloc(self: &Arc<Self>, len: usize) -> Result<MagicBuffer, MagicBufferError> {
+        MagicBuffer::validate_len(len)?;
+
+        let offset = self
+            .take(2 * len)
+            .ok_or(MagicBufferError::OOM { code: None })?;
+        let addr = unsafe { self.addr.add(offset) };
+        let backing = match unsafe { magic_buf_alloc_at(addr, len) } {
+            Ok(backing) => backing,
+            Err(err) => {
+                self.give(offset..offset + 2 * len);
+                return Err(err);
+            }
+        };
+
+        Ok(MagicBuffer {
+            addr,
+            mask: len - 1,
+            capacity: len,
+            len,
+            backing: Some(backing),
+            arena: Some(self.clone()),
+            huge_page_len: None,
+            options: None,
+            write_pos: 0,
+            read_pos: 0,
+            base_offset: 0,
+            _marker: PhantomData,
+        })
+    }
+
+    pub(crate) unsafe fn free(&self, addr: *mut u8, len: usize) {
+        magic_buf_free_at(addr, len);
+        let offset = addr.offset_from(self.addr) as usize;
+        self.give(offset..offset + 2 * len);
+    }
+
+    fn take(&self, len: usize) -> Option<usize> {
+        let mut free = self.free.lock().unwrap();
+        let idx = free.iter().position(|r| r.len() >= len)?;
+        let offset = free[idx].start;
+        free[idx].start += len;
+        if free[idx].is_empty() {
+            free.remove(idx);
+        }
+        Some(offset)
+    }
+
+    fn give(&self, range: Range<usize>) {
+        let mut free = self.free.lock().unwrap();
+        let idx = free.partition_point(|r| r.start < range.start);
+        free.insert(idx, range);
+
+        // coalesce with the following and preceding free ranges
+        if idx + 1 < free.len() && free[idx].end == free[idx + 1].start {
+            free[idx].end = free.remove(idx + 1).end;
+        }
+        if idx > 0 && free[idx - 1].end == free[idx].start {
+            free[idx - 1].end = free.remove(idx).end;
+        }
+    }
+}
+
+impl Drop for ArenaInner {
+    fn drop(&mut self) {
+        unsafe { magic_buf_unreserve(self.addr, self.len) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VALID_BUF_LEN: usize = 1 << 16;
+
+    #[test]
+    fn allocates_within_arena() {
+        let arena = MagicArena::new(8 * VALID_BUF_LEN).expect("should reserve arena");
+        let a = arena.alloc(VALID_BUF_LEN).expect("should allocate buffer");
+        let b = arena
+            .alloc(2 * VALID_BUF_LEN)
+            .expect("should allocate buffer");
+
+        let start = arena.inner.addr as usize;
+        let end = start + arena.len();
+        for buf in [&a, &b] {
+            let addr = buf.as_ptr(0) as usize;
+            assert!(addr >= start && addr + 2 * buf.len() <= end);
+        }
+    }
+
+    #[test]
+    fn buffers_wrap_around() {
+        let arena = MagicArena::new(8 * VALID_BUF_LEN).expect("should reserve arena");
+        let mut a = arena.alloc(VALID_BUF_LEN).expect("should allocate buffer");
+        let mut b = arena.alloc(VALID_BUF_LEN).expect("should allocate buffer");
+        a[0] = b'a';
+        b[0] = b'b';
+        assert_eq!(b'a', a[VALID_BUF_LEN]);
+        assert_eq!(b'b', b[VALID_BUF_LEN]);
+    }
+
+    #[test]
+    fn reuses_freed_ranges() {
+        let arena = MagicArena::new(4 * VALID_BUF_LEN).expect("should reserve arena");
+        let a = arena.alloc(VALID_BUF_LEN).expect("should allocate buffer");
+        let b = arena.alloc(VALID_BUF_LEN).expect("should allocate buffer");
+        arena
+            .alloc(VALID_BUF_LEN)
+            .expect_err("arena should be exhausted");
+
+        drop(a);
+        drop(b);
+        let mut c = arena
+            .alloc(2 * VALID_BUF_LEN)
+            .expect("should allocate coalesced range");
+        assert!(c.iter().all(|v| *v == 0));
+        c[1] = b'c';
+        assert_eq!(b'c', c[2 * VALID_BUF_LEN + 1]);
+    }
+
+    #[test]
+    fn buffers_outlive_arena() {
+        let arena = MagicArena::new(4 * VALID_BUF_LEN).expect("should reserve arena");
+        let mut buf = arena.alloc(VALID_BUF_LEN).expect("should allocate buffer");
+        drop(arena);
+        buf[0] = b'a';
+        assert_eq!(b'a', buf[VALID_BUF_LEN]);
+    }
+}
@@ -0,0 +1,280 @@
+// FreeBSD, NetBSD and DragonFly implementation, following the same approach as the
+// Linux module: a shared memory object mapped twice into a reserved address range.
+
+use crate::{MagicBufferError, MagicBufferOptions};
+
+use libc::{
+    c_int, close, fstat, ftruncate, getrlimit, mlock, mmap, mprotect, msync, munlock, munmap,
+    off_t, rlimit, shm_open, shm_unlink, size_t, stat, sysconf, _SC_PAGESIZE, EAGAIN, ENOMEM,
+    MAP_ANON, MAP_FAILED, MAP_FIXED, MAP_PRIVATE, MAP_SHARED, MS_ASYNC, MS_SYNC, O_CLOEXEC,
+    O_CREAT, O_RDWR, PROT_NONE, PROT_READ, PROT_WRITE, RLIMIT_MEMLOCK, RLIM_INFINITY,
+};
+use std::{
+    ffi::CStr,
+    fs::File,
+    io,
+    mem::MaybeUninit,
+    os::fd::{AsRawFd, FromRawFd, OwnedFd},
+    ptr,
+};
+
+/// The buffer keeps its shared memory object open, like the memfd on Linux.
+pub(super) type MagicBufBacking = OwnedFd;
+
+pub(super) unsafe fn magic_buf_min_len() -> usize {
+    sysconf(_SC_PAGESIZE) as _
+}
+
+/// Returns the len of the inaccessible guard region after the views, one page if enabled.
+unsafe fn magic_buf_guard_len(guard: bool) -> usize {
+    if guard {
+        magic_buf_min_len()
+    } else {
+        0
+    }
+}
+
+#[cfg(target_os = "freebsd")]
+unsafe fn magic_buf_shm_open() -> c_int {
+    // anonymous shared memory objects have no name and are freed with the last fd
+    shm_open(libc::SHM_ANON, O_RDWR | O_CLOEXEC, 0o600)
+}
+
+#[cfg(not(target_os = "freebsd"))]
+unsafe fn magic_buf_shm_open() -> c_int {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let name = format!(
+        "/magic_buffer.{}.{}\0",
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    );
+
+    let fd = shm_open(
+        name.as_ptr() as _,
+        O_RDWR | O_CLOEXEC | libc::O_CREAT | libc::O_EXCL,
+        0o600,
+    );
+    // unlink the name right away, the object lives on until the fd is closed
+    if fd != -1 {
+        assert_eq!(0, libc::shm_unlink(name.as_ptr() as _));
+    }
+    fd
+}
+
+unsafe fn magic_buf_create_backing(len: usize) -> Result<OwnedFd, MagicBufferError> {
+    let fd = magic_buf_shm_open();
+    if fd == -1 {
+        return Err(MagicBufferError::last_os_oom());
+    }
+
+    if ftruncate(fd, len as off_t) == -1 {
+        let err = MagicBufferError::last_os_oom();
+        assert_eq!(0, close(fd));
+        return Err(err);
+    }
+
+    Ok(OwnedFd::from_raw_fd(fd))
+}
+
+pub(super) unsafe fn magic_buf_alloc(
+    options: &MagicBufferOptions,
+) -> Result<(*mut u8, MagicBufBacking, Option<usize>), MagicBufferError> {
+    if options.huge_pages.is_some() && options.strict {
+        return Err(MagicBufferError::Unsupported {
+            msg: "huge pages are only supported on linux".to_string(),
+        });
+    }
+
+    let fd = magic_buf_create_backing(options.len)?;
+    let addr = magic_buf_map_fd(
+        fd.as_raw_fd(),
+        options.len,
+        options.mirror_count,
+        magic_buf_guard_len(options.guard_page),
+        options.address_hint.unwrap_or(0) as *mut u8,
+        options.fixed_address,
+    )?;
+    Ok((addr, fd, None))
+}
+
+pub(super) unsafe fn magic_buf_open_shared(
+    name: &CStr,
+    len: usize,
+) -> Result<(*mut u8, MagicBufBacking), MagicBufferError> {
+    let fd = shm_open(name.as_ptr(), O_CREAT | O_RDWR | O_CLOEXEC, 0o600);
+    if fd == -1 {
+        return Err(MagicBufferError::last_os_oom());
+    }
+
+    let fd = OwnedFd::from_raw_fd(fd);
+    magic_buf_size_shared(fd.as_raw_fd(), len)?;
+    let addr = magic_buf_map_fd(fd.as_raw_fd(), len, 2, 0, ptr::null_mut(), false)?;
+    Ok((addr, fd))
+}
+
+/// Sizes a newly created shared memory object to `len`, or checks that an existing
+/// one has been created with the same `len`.
+unsafe fn magic_buf_size_shared(fd: c_int, len: usize) -> Result<(), MagicBufferError> {
+    let mut st = MaybeUninit::<stat>::uninit();
+    if fstat(fd, st.as_mut_ptr()) == -1 {
+        return Err(MagicBufferError::last_os_oom());
+    }
+
+    let size = st.assume_init().st_size as usize;
+    if size == 0 {
+        if ftruncate(fd, len as off_t) == -1 {
+            return Err(MagicBufferError::last_os_oom());
+        }
+    } else if size != len {
+        return Err(MagicBufferError::InvalidLen {
+            msg: format!("len must match the len of the shared buffer, {}", size),
+        });
+    }
+
+    Ok(())
+}
+
+pub(super) fn magic_buf_unlink_shared(name: &CStr) -> io::Result<()> {
+    if unsafe { shm_unlink(name.as_ptr()) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+pub(super) unsafe fn magic_buf_map_file(
+    file: File,
+    len: usize,
+) -> Result<(*mut u8, MagicBufBacking), MagicBufferError> {
+    let fd = OwnedFd::from(file);
+    let addr = magic_buf_map_fd(fd.as_raw_fd(), len, 2, 0, ptr::null_mut(), false)?;
+    Ok((addr, fd))
+}
+
+unsafe fn magic_buf_map_fd(
+    fd: c_int,
+    len: usize,
+    mirrors: usize,
+    guard_len: usize,
+    hint: *mut u8,
+    fixed: bool,
+) -> Result<*mut u8, MagicBufferError> {
+    // reserve the address space for all views first, so that the mirrors
+    // can't race with other mappings, the rest of the reservation stays
+    // inaccessible and serves as guard page
+    let addr = mmap(
+        hint as _,
+        len * mirrors + guard_len,
+        PROT_NONE,
+        MAP_PRIVATE | MAP_ANON,
+        -1,
+        0,
+    );
+
+    if addr == MAP_FAILED {
+        return Err(MagicBufferError::last_os_oom());
+    }
+
+    // the address is only a hint, existing mappings are never replaced
+    let addr = addr as *mut u8;
+    if fixed && !hint.is_null() && addr != hint {
+        assert_eq!(0, munmap(addr as _, (len * mirrors + guard_len) as size_t));
+        return Err(MagicBufferError::AddressUnavailable);
+    }
+
+    for view in (0..mirrors).map(|i| addr.add(i * len)) {
+        let ptr = mmap(
+            view as _,
+            len,
+            PROT_READ | PROT_WRITE,
+            MAP_SHARED | MAP_FIXED,
+            fd,
+            0,
+        );
+
+        if ptr == MAP_FAILED {
+            let err = MagicBufferError::last_os_oom();
+            assert_eq!(0, munmap(addr as _, (len * mirrors + guard_len) as size_t));
+            return Err(err);
+        }
+    }
+
+    Ok(addr)
+}
+
+pub(super) unsafe fn magic_buf_free(addr: *mut u8, len: usize, mirrors: usize, guard: bool) {
+    let mapping_len = len * mirrors + magic_buf_guard_len(guard);
+    assert_eq!(0, munmap(addr as _, mapping_len as size_t));
+}
+
+pub(super) unsafe fn magic_buf_flush(addr: *mut u8, len: usize, sync: bool) -> io::Result<()> {
+    let flags = if sync { MS_SYNC } else { MS_ASYNC };
+    if msync(addr as _, len, flags) == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+/// Makes all views of the buffer read-only, or readable and writable again.
+pub(super) unsafe fn magic_buf_protect(
+    addr: *mut u8,
+    len: usize,
+    mirrors: usize,
+    writable: bool,
+) -> Result<(), MagicBufferError> {
+    let prot = if writable {
+        PROT_READ | PROT_WRITE
+    } else {
+        PROT_READ
+    };
+    if mprotect(addr as _, len * mirrors, prot) == -1 {
+        return Err(MagicBufferError::last_os_oom());
+    }
+
+    Ok(())
+}
+
+pub(super) unsafe fn magic_buf_lock(addr: *const u8, len: usize) -> Result<(), MagicBufferError> {
+    if mlock(addr as _, len) == -1 {
+        let source = io::Error::last_os_error();
+        if matches!(source.raw_os_error(), Some(EAGAIN | ENOMEM)) {
+            if let Some(limit) = magic_buf_lock_limit() {
+                return Err(MagicBufferError::LockLimitExceeded {
+                    requested: len,
+                    limit,
+                });
+            }
+        }
+
+        return Err(MagicBufferError::LockFailed { source });
+    }
+
+    Ok(())
+}
+
+unsafe fn magic_buf_lock_limit() -> Option<usize> {
+    let mut rlim = MaybeUninit::<rlimit>::uninit();
+    if getrlimit(RLIMIT_MEMLOCK, rlim.as_mut_ptr()) == -1 {
+        return None;
+    }
+
+    let rlim = rlim.assume_init();
+    if rlim.rlim_cur == RLIM_INFINITY {
+        return None;
+    }
+
+    Some(rlim.rlim_cur as usize)
+}
+
+pub(super) unsafe fn magic_buf_unlock(addr: *const u8, len: usize) -> Result<(), MagicBufferError> {
+    if munlock(addr as _, len) == -1 {
+        return Err(MagicBufferError::LockFailed {
+            source: io::Error::last_os_error(),
+        });
+    }
+
+    Ok(())
+}
@@ -0,0 +1,121 @@
+use crate::MagicBuffer;
+use bytes::{buf::UninitSlice, Buf, BufMut};
+
+/// A [`MagicBytes`] adapts a [`MagicBuffer`] to the [`Buf`] and [`BufMut`] traits of
+/// the `bytes` crate.
+///
+/// Bytes are appended at the write position through [`BufMut`] and consumed from the
+/// read position through [`Buf`]. Unlike a `VecDeque`, which hands out two chunks
+/// around the wrap point, [`Buf::chunk`] and [`BufMut::chunk_mut`] always return the
+/// full readable or writable region as a single chunk, thanks to the mirrored mapping.
+/// Writes never overwrite bytes that have not been read yet.
+///
+/// # Examples
+/// ```
+/// # use magic_buffer::*;
+/// use bytes::{Buf, BufMut};
+///
+/// # fn main() -> Result<(), MagicBufferError> {
+/// let mut buf = MagicBytes::new(MagicBuffer::new(MagicBuffer::min_len())?);
+/// buf.put_slice(b"hello");
+/// assert_eq!(b"hello", buf.chunk());
+/// assert_eq!(b"hel", &buf.copy_to_bytes(3)[..]);
+/// assert_eq!(2, buf.remaining());
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct MagicBytes {
+    buf: MagicBuffer,
+}
+
+impl MagicBytes {
+    /// Wraps `buf`, continuing at its current [`MagicBuffer::read_pos`] and
+    /// [`MagicBuffer::write_pos`].
+    pub fn new(buf: MagicBuffer) -> Self {
+        Self { buf }
+    }
+
+    /// Returns a reference to the underlying [`MagicBuffer`].
+    pub fn get_ref(&self) -> &MagicBuffer {
+        &self.buf
+    }
+
+    /// Unwraps the underlying [`MagicBuffer`].
+    pub fn into_inner(self) -> MagicBuffer {
+        self.buf
+    }
+}
+
+impl Buf for MagicBytes {
+    fn remaining(&self) -> usize {
+        self.buf.write_pos.wrapping_sub(self.buf.read_pos)
+    }
+
+    fn chunk(&self) -> &[u8] {
+        let read_pos = self.buf.view_offset(self.buf.read_pos);
+        unsafe { self.buf.view_slice(read_pos, self.remaining()) }
+    }
+
+    fn advance(&mut self, cnt: usize) {
+        assert!(
+            cnt <= self.remaining(),
+            "cannot advance past the written bytes"
+        );
+        self.buf.read_pos = self.buf.read_pos.wrapping_add(cnt);
+    }
+}
+
+unsafe impl BufMut for MagicBytes {
+    fn remaining_mut(&self) -> usize {
+        self.buf.capacity - self.remaining()
+    }
+
+    unsafe fn advance_mut(&mut self, cnt: usize) {
+        assert!(
+            cnt <= self.remaining_mut(),
+            "cannot advance past the free bytes"
+        );
+        self.buf.write_pos = self.buf.write_pos.wrapping_add(cnt);
+    }
+
+    fn chunk_mut(&mut self) -> &mut UninitSlice {
+        let write_pos = self.buf.view_offset(self.buf.write_pos);
+        let len = self.remaining_mut();
+        let ptr = self.buf.view_ptr(write_pos);
+        self.buf.mark_written(write_pos, len);
+        unsafe { UninitSlice::from_raw_parts_mut(ptr, len) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn copy_to_bytes_across_wrap() {
+        let len = MagicBuffer::min_len();
+        let mut buf = MagicBytes::new(MagicBuffer::new(len).expect("should allocate buffer"));
+        buf.put_bytes(0, len - 4);
+        buf.advance(len - 4);
+
+        buf.put_slice(b"abcdefgh");
+        assert_eq!(len - 8, buf.remaining_mut());
+        assert_eq!(8, buf.chunk().len());
+        assert_eq!(len - 8, buf.chunk_mut().len());
+
+        let bytes = buf.copy_to_bytes(8);
+        assert_eq!(b"abcdefgh", &bytes[..]);
+        assert_eq!(0, buf.remaining());
+        assert_eq!(b"efgh", &buf.get_ref()[0..4]);
+    }
+
+    #[test]
+    fn put_stops_at_unread_bytes() {
+        let len = MagicBuffer::min_len();
+        let mut buf = MagicBytes::new(MagicBuffer::new(len).expect("should allocate buffer"));
+        buf.put_bytes(1, len);
+        assert!(!buf.has_remaining_mut());
+        assert_eq!(len, buf.chunk().len());
+    }
+}
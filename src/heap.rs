@@ -0,0 +1,325 @@
+// Heap backed fallback for targets without support for mirrored mappings. The OS can't
+// keep the two halves in sync, so the copy-based write APIs mirror every write manually.
+// Slices and pointers handed out for writing are recorded instead, and mirrored before
+// the buffer is accessed the next time.
+
+use crate::{MagicBufferError, MagicBufferOptions};
+
+use std::{
+    alloc::{alloc_zeroed, dealloc, Layout},
+    ffi::CStr,
+    fs::File,
+    hint, io, ptr,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// There is no backing object, the memory is owned by the global allocator.
+pub(super) type MagicBufBacking = ();
+
+const PAGE_LEN: usize = 4096;
+
+/// The mirror has to be kept in sync by copying, so atomic stores need to be repeated
+/// on the other half.
+pub(super) const MAGIC_BUF_EMULATED_MIRROR: bool = true;
+
+/// The region last handed out for writing, which the mirror has to be synced with
+/// before the buffer is accessed again. It lives in the page in front of the buffer,
+/// which keeps the buffer itself agnostic of the fallback.
+struct WrittenRegion {
+    state: AtomicUsize,
+    offset: AtomicUsize,
+    len: AtomicUsize,
+}
+
+const CLEAN: usize = 0;
+const WRITTEN: usize = 1;
+const SYNCING: usize = 2;
+
+pub(super) unsafe fn magic_buf_min_len() -> usize {
+    PAGE_LEN
+}
+
+fn magic_buf_layout(len: usize) -> Layout {
+    Layout::from_size_align(PAGE_LEN + len * 2, PAGE_LEN).expect("len is validated")
+}
+
+unsafe fn magic_buf_written_region<'a>(addr: *mut u8) -> &'a WrittenRegion {
+    &*(addr.sub(PAGE_LEN) as *const WrittenRegion)
+}
+
+pub(super) unsafe fn magic_buf_alloc(
+    options: &MagicBufferOptions,
+) -> Result<(*mut u8, MagicBufBacking, Option<usize>), MagicBufferError> {
+    if options.huge_pages.is_some() && options.strict {
+        return Err(MagicBufferError::Unsupported {
+            msg: "huge pages are not supported by the heap fallback".to_string(),
+        });
+    }
+
+    if options.mirror_count != 2 {
+        return Err(MagicBufferError::Unsupported {
+            msg: "more than two mirrors are not supported by the heap fallback".to_string(),
+        });
+    }
+
+    if options.address_hint.is_some() && options.fixed_address {
+        return Err(MagicBufferError::Unsupported {
+            msg: "fixed addresses are not supported by the heap fallback".to_string(),
+        });
+    }
+
+    if options.guard_page {
+        return Err(MagicBufferError::Unsupported {
+            msg: "guard pages are not supported by the heap fallback".to_string(),
+        });
+    }
+
+    // the zeroed header is a clean WrittenRegion
+    let base = alloc_zeroed(magic_buf_layout(options.len));
+    if base.is_null() {
+        return Err(MagicBufferError::OOM { code: None });
+    }
+
+    Ok((base.add(PAGE_LEN), (), None))
+}
+
+pub(super) unsafe fn magic_buf_open_shared(
+    _name: &CStr,
+    _len: usize,
+) -> Result<(*mut u8, MagicBufBacking), MagicBufferError> {
+    Err(MagicBufferError::Unsupported {
+        msg: "shared buffers are not supported by the heap fallback".to_string(),
+    })
+}
+
+pub(super) fn magic_buf_unlink_shared(_name: &CStr) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "shared buffers are not supported by the heap fallback",
+    ))
+}
+
+pub(super) unsafe fn magic_buf_map_file(
+    _file: File,
+    _len: usize,
+) -> Result<(*mut u8, MagicBufBacking), MagicBufferError> {
+    Err(MagicBufferError::Unsupported {
+        msg: "file backed buffers are not supported by the heap fallback".to_string(),
+    })
+}
+
+pub(super) unsafe fn magic_buf_protect(
+    _addr: *mut u8,
+    _len: usize,
+    _mirrors: usize,
+    writable: bool,
+) -> Result<(), MagicBufferError> {
+    if !writable {
+        return Err(MagicBufferError::Unsupported {
+            msg: "read-only buffers are not supported by the heap fallback".to_string(),
+        });
+    }
+
+    Ok(())
+}
+
+pub(super) unsafe fn magic_buf_flush(_addr: *mut u8, _len: usize, _sync: bool) -> io::Result<()> {
+    // heap memory is never backed by a file
+    Ok(())
+}
+
+pub(super) unsafe fn magic_buf_free(addr: *mut u8, len: usize, _mirrors: usize, _guard: bool) {
+    dealloc(addr.sub(PAGE_LEN), magic_buf_layout(len));
+}
+
+pub(super) unsafe fn magic_buf_lock(_addr: *const u8, _len: usize) -> Result<(), MagicBufferError> {
+    Err(MagicBufferError::Unsupported {
+        msg: "locking is not supported by the heap fallback".to_string(),
+    })
+}
+
+pub(super) unsafe fn magic_buf_unlock(
+    _addr: *const u8,
+    _len: usize,
+) -> Result<(), MagicBufferError> {
+    Err(MagicBufferError::Unsupported {
+        msg: "locking is not supported by the heap fallback".to_string(),
+    })
+}
+
+/// Copies `len` bytes written at `offset`, which may lie in either half of the
+/// `2 * buf_len` allocation, into the other half.
+pub(super) unsafe fn magic_buf_sync_mirror(
+    addr: *mut u8,
+    buf_len: usize,
+    offset: usize,
+    len: usize,
+) {
+    let end = offset + len;
+    if offset < buf_len {
+        let front_end = end.min(buf_len);
+        ptr::copy_nonoverlapping(
+            addr.add(offset),
+            addr.add(offset + buf_len),
+            front_end - offset,
+        );
+    }
+
+    if end > buf_len {
+        let back_start = offset.max(buf_len);
+        ptr::copy_nonoverlapping(
+            addr.add(back_start),
+            addr.add(back_start - buf_len),
+            end - back_start,
+        );
+    }
+}
+
+/// Records that the `len` bytes at `offset`, at most `buf_len`, are handed out for
+/// writing, after syncing the mirror with the previously recorded region. The caller
+/// must have exclusive access to the buffer.
+pub(super) unsafe fn magic_buf_mark_written(
+    addr: *mut u8,
+    buf_len: usize,
+    offset: usize,
+    len: usize,
+) {
+    if addr.is_null() || len == 0 {
+        return;
+    }
+
+    magic_buf_sync_written(addr, buf_len);
+    let region = magic_buf_written_region(addr);
+    region.offset.store(offset, Ordering::Relaxed);
+    region.len.store(len, Ordering::Relaxed);
+    region.state.store(WRITTEN, Ordering::Release);
+}
+
+/// Syncs the mirror with the region recorded by [`magic_buf_mark_written`], if any.
+/// Concurrent callers wait for the one doing the copy, so that none of them reads the
+/// mirror while it is being written.
+pub(super) unsafe fn magic_buf_sync_written(addr: *mut u8, buf_len: usize) {
+    if addr.is_null() {
+        return;
+    }
+
+    let region = magic_buf_written_region(addr);
+    loop {
+        match region.state.compare_exchange_weak(
+            WRITTEN,
+            SYNCING,
+            Ordering::Acquire,
+            Ordering::Acquire,
+        ) {
+            Ok(_) => {
+                let offset = region.offset.load(Ordering::Relaxed);
+                let len = region.len.load(Ordering::Relaxed);
+                magic_buf_sync_mirror(addr, buf_len, offset, len);
+                region.state.store(CLEAN, Ordering::Release);
+                return;
+            }
+            Err(CLEAN) => return,
+            Err(_) => hint::spin_loop(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    struct HeapBuf {
+        addr: *mut u8,
+        len: usize,
+    }
+
+    impl HeapBuf {
+        fn new() -> Self {
+            let len = PAGE_LEN;
+            let (addr, _, _) = unsafe { magic_buf_alloc(&MagicBufferOptions::new(len)) }
+                .expect("should allocate buffer");
+            Self { addr, len }
+        }
+
+        fn get(&self, offset: usize) -> u8 {
+            assert!(offset < 2 * self.len);
+            unsafe { self.addr.add(offset).read() }
+        }
+
+        fn set(&self, offset: usize, val: u8) {
+            assert!(offset < 2 * self.len);
+            unsafe { self.addr.add(offset).write(val) }
+        }
+    }
+
+    impl Drop for HeapBuf {
+        fn drop(&mut self) {
+            unsafe { magic_buf_free(self.addr, self.len, 2, false) }
+        }
+    }
+
+    unsafe impl Sync for HeapBuf {}
+
+    #[test]
+    fn sync_mirror_across_halves() {
+        let buf = HeapBuf::new();
+        let len = buf.len;
+        buf.set(len - 1, 1u8);
+        buf.set(len, 2u8);
+        unsafe { magic_buf_sync_mirror(buf.addr, len, len - 1, 2) };
+        assert_eq!(1u8, buf.get(2 * len - 1));
+        assert_eq!(2u8, buf.get(0));
+    }
+
+    #[test]
+    fn written_region_is_synced_once() {
+        let buf = HeapBuf::new();
+        let len = buf.len;
+        unsafe { magic_buf_mark_written(buf.addr, len, len - 1, 2) };
+        buf.set(len - 1, 1u8);
+        buf.set(len, 2u8);
+        assert_eq!(0u8, buf.get(0));
+
+        unsafe { magic_buf_sync_written(buf.addr, len) };
+        assert_eq!(1u8, buf.get(2 * len - 1));
+        assert_eq!(2u8, buf.get(0));
+
+        // a clean region is not copied again
+        buf.set(len - 1, 3u8);
+        unsafe { magic_buf_sync_written(buf.addr, len) };
+        assert_eq!(1u8, buf.get(2 * len - 1));
+    }
+
+    #[test]
+    fn marking_syncs_the_previous_region() {
+        let buf = HeapBuf::new();
+        let len = buf.len;
+        unsafe { magic_buf_mark_written(buf.addr, len, 0, 1) };
+        buf.set(0, 1u8);
+        unsafe { magic_buf_mark_written(buf.addr, len, 1, 1) };
+        buf.set(1, 2u8);
+        assert_eq!(1u8, buf.get(len));
+        assert_eq!(0u8, buf.get(len + 1));
+
+        unsafe { magic_buf_sync_written(buf.addr, len) };
+        assert_eq!(2u8, buf.get(len + 1));
+    }
+
+    #[test]
+    fn concurrent_syncs_wait_for_the_copy() {
+        let buf = HeapBuf::new();
+        let len = buf.len;
+        unsafe { magic_buf_mark_written(buf.addr, len, 0, len) };
+        unsafe { ptr::write_bytes(buf.addr, 7u8, len) };
+
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    unsafe { magic_buf_sync_written(buf.addr, len) };
+                    assert!((len..2 * len).all(|i| buf.get(i) == 7u8));
+                });
+            }
+        });
+    }
+}
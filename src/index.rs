@@ -0,0 +1,69 @@
+use crate::{MagicBuffer, Pod};
+use std::ops::Range;
+
+mod private {
+    pub trait Sealed {}
+
+    impl Sealed for usize {}
+    impl Sealed for std::ops::Range<usize> {}
+}
+
+/// A helper trait for the non-panicking [`MagicBuffer::get`] and
+/// [`MagicBuffer::get_mut`], similar to [`std::slice::SliceIndex`].
+///
+/// This trait is sealed and implemented for `usize` and [`Range<usize>`].
+pub trait MagicBufferIndex<T>: private::Sealed {
+    /// The output type returned by the methods.
+    type Output: ?Sized;
+
+    /// Returns a shared reference to the output at this location, if in bounds.
+    fn get(self, buf: &MagicBuffer<T>) -> Option<&Self::Output>;
+
+    /// Returns a mutable reference to the output at this location, if in bounds.
+    fn get_mut(self, buf: &mut MagicBuffer<T>) -> Option<&mut Self::Output>;
+}
+
+/// Every index wraps around the buffer, so a single element is always in bounds, unless
+/// it lies beyond the `len` of a truncated or empty buffer.
+impl<T: Pod> MagicBufferIndex<T> for usize {
+    type Output = T;
+
+    fn get(self, buf: &MagicBuffer<T>) -> Option<&Self::Output> {
+        if buf.fast_mod(self) >= buf.len() {
+            return None;
+        }
+
+        Some(&buf[self])
+    }
+
+    fn get_mut(self, buf: &mut MagicBuffer<T>) -> Option<&mut Self::Output> {
+        if buf.fast_mod(self) >= buf.len() {
+            return None;
+        }
+
+        Some(&mut buf[self])
+    }
+}
+
+/// A range is out of bounds if `start > end` or it is larger than the buffer, or larger
+/// than `(mirror_count - 1) * len` for buffers with more than two mirrors. Ranges wrap
+/// around a full buffer, but must not extend beyond the `len` of a truncated one.
+impl<T: Pod> MagicBufferIndex<T> for Range<usize> {
+    type Output = [T];
+
+    fn get(self, buf: &MagicBuffer<T>) -> Option<&Self::Output> {
+        if self.start > self.end || !buf.in_bounds(self.start, self.end - self.start) {
+            return None;
+        }
+
+        Some(unsafe { buf.view_slice(buf.view_offset(self.start), self.end - self.start) })
+    }
+
+    fn get_mut(self, buf: &mut MagicBuffer<T>) -> Option<&mut Self::Output> {
+        if self.start > self.end || !buf.in_bounds(self.start, self.end - self.start) {
+            return None;
+        }
+
+        Some(unsafe { buf.view_slice_mut(buf.view_offset(self.start), self.end - self.start) })
+    }
+}
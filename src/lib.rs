@@ -1,10 +1,21 @@
 #![doc = include_str!("../README.md")]
 
 use std::{
+    ffi::CString,
+    fmt,
+    fs::OpenOptions,
+    hash::{Hash, Hasher},
+    io::{self, IoSlice, IoSliceMut},
+    marker::PhantomData,
+    mem::{size_of, MaybeUninit},
     ops::{
-        Deref, DerefMut, Index, IndexMut, Range, RangeFrom, RangeFull, RangeTo, RangeToInclusive,
+        Deref, DerefMut, Index, IndexMut, Range, RangeFrom, RangeFull, RangeInclusive, RangeTo,
+        RangeToInclusive,
     },
-    ptr::{slice_from_raw_parts, slice_from_raw_parts_mut},
+    path::Path,
+    ptr::{self, slice_from_raw_parts, slice_from_raw_parts_mut},
+    slice,
+    sync::atomic::{self, AtomicU32, AtomicU64, Ordering},
 };
 use thiserror::Error;
 
@@ -14,10 +25,10 @@ mod windows;
 #[cfg(target_family = "windows")]
 use windows::*;
 
-#[cfg(target_os = "linux")]
+#[cfg(any(target_os = "linux", target_os = "android"))]
 mod linux;
 
-#[cfg(target_os = "linux")]
+#[cfg(any(target_os = "linux", target_os = "android"))]
 use linux::*;
 
 #[cfg(any(target_os = "macos", target_os = "ios"))]
@@ -26,35 +37,330 @@ mod macos;
 #[cfg(any(target_os = "macos", target_os = "ios"))]
 use macos::*;
 
+#[cfg(any(target_os = "freebsd", target_os = "netbsd", target_os = "dragonfly"))]
+mod bsd;
+
+#[cfg(any(target_os = "freebsd", target_os = "netbsd", target_os = "dragonfly"))]
+use bsd::*;
+
+// the fallback is compiled for tests on every target, so that its unit tests run
+#[cfg(any(
+    test,
+    all(
+        feature = "heap-fallback",
+        not(any(
+            target_family = "windows",
+            target_os = "linux",
+            target_os = "android",
+            target_os = "macos",
+            target_os = "ios",
+            target_os = "freebsd",
+            target_os = "netbsd",
+            target_os = "dragonfly"
+        ))
+    )
+))]
+#[cfg_attr(test, allow(dead_code))]
+mod heap;
+
+#[cfg(all(
+    feature = "heap-fallback",
+    not(any(
+        target_family = "windows",
+        target_os = "linux",
+        target_os = "android",
+        target_os = "macos",
+        target_os = "ios",
+        target_os = "freebsd",
+        target_os = "netbsd",
+        target_os = "dragonfly"
+    ))
+))]
+use heap::*;
+
+#[cfg(not(any(
+    feature = "heap-fallback",
+    target_family = "windows",
+    target_os = "linux",
+    target_os = "android",
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd",
+    target_os = "netbsd",
+    target_os = "dragonfly"
+)))]
+compile_error!("magic-buffer does not support this target, enable the `heap-fallback` feature");
+
+/// The OS keeps both halves of the mapping in sync on all supported platforms.
+#[cfg(not(all(
+    feature = "heap-fallback",
+    not(any(
+        target_family = "windows",
+        target_os = "linux",
+        target_os = "android",
+        target_os = "macos",
+        target_os = "ios",
+        target_os = "freebsd",
+        target_os = "netbsd",
+        target_os = "dragonfly"
+    ))
+)))]
+mod native_mirror {
+    pub(super) const MAGIC_BUF_EMULATED_MIRROR: bool = false;
+
+    #[inline(always)]
+    pub(super) unsafe fn magic_buf_sync_mirror(
+        _addr: *mut u8,
+        _buf_len: usize,
+        _offset: usize,
+        _len: usize,
+    ) {
+    }
+
+    #[inline(always)]
+    pub(super) unsafe fn magic_buf_mark_written(
+        _addr: *mut u8,
+        _buf_len: usize,
+        _offset: usize,
+        _len: usize,
+    ) {
+    }
+
+    #[inline(always)]
+    pub(super) unsafe fn magic_buf_sync_written(_addr: *mut u8, _buf_len: usize) {}
+}
+
+#[cfg(not(all(
+    feature = "heap-fallback",
+    not(any(
+        target_family = "windows",
+        target_os = "linux",
+        target_os = "android",
+        target_os = "macos",
+        target_os = "ios",
+        target_os = "freebsd",
+        target_os = "netbsd",
+        target_os = "dragonfly"
+    ))
+)))]
+use native_mirror::*;
+
+/// Issues a software prefetch for the cache line containing `ptr`. This is a no-op on
+/// architectures without a stable prefetch instruction.
+#[inline(always)]
+fn prefetch(ptr: *const u8, write: bool) {
+    #[cfg(any(
+        target_arch = "x86_64",
+        all(target_arch = "x86", target_feature = "sse")
+    ))]
+    {
+        #[cfg(target_arch = "x86")]
+        use std::arch::x86::{_mm_prefetch, _MM_HINT_T0};
+        #[cfg(target_arch = "x86_64")]
+        use std::arch::x86_64::{_mm_prefetch, _MM_HINT_T0};
+
+        // there is no stable hint for writes, prefetching into L1 serves them as well
+        let _ = write;
+        unsafe { _mm_prefetch::<_MM_HINT_T0>(ptr as *const i8) };
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    unsafe {
+        if write {
+            std::arch::asm!("prfm pstl1keep, [{0}]", in(reg) ptr, options(nostack, readonly, preserves_flags));
+        } else {
+            std::arch::asm!("prfm pldl1keep, [{0}]", in(reg) ptr, options(nostack, readonly, preserves_flags));
+        }
+    }
+
+    #[cfg(not(any(
+        target_arch = "x86_64",
+        all(target_arch = "x86", target_feature = "sse"),
+        target_arch = "aarch64"
+    )))]
+    let _ = (ptr, write);
+}
+
+/// The mask of buffers whose len is not a power of two, which wrap around with a modulo.
+const NO_MASK: usize = usize::MAX;
+
+/// Returns the mask to wrap offsets around a buffer of `len` elements.
+pub(crate) fn len_mask(len: usize) -> usize {
+    if len.is_power_of_two() {
+        len - 1
+    } else {
+        NO_MASK
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod arena;
+
+#[cfg(feature = "bytes")]
+mod buf;
+#[cfg(feature = "bytes")]
+pub use buf::MagicBytes;
+
+#[cfg(feature = "checksum")]
+mod checksum;
+
+mod index;
+pub use index::MagicBufferIndex;
+
+mod options;
+pub use options::{ForkBehavior, HugePageSize, MagicBufferOptions};
+
+mod pod;
+pub use pod::Pod;
+
+mod queue;
+pub use queue::MagicRingQueue;
+
+mod read_only;
+pub use read_only::ReadOnlyMagicBuffer;
+
+mod send_ptr;
+pub use send_ptr::SendPtr;
+
+#[cfg(feature = "serde")]
+mod serialize;
+
+mod spsc;
+pub use spsc::{Consumer, Producer, SpscBuffer};
+
+mod view;
+pub use view::TypedView;
+
+#[cfg(target_os = "linux")]
+pub use arena::MagicArena;
+
 /// The [`MagicBufferError`] error indicates an allocation failure that may be due
 /// to resource exhaustion or to something wrong with the given input arguments
 /// to [`MagicBuffer::new`].
 #[derive(Debug, Error)]
 pub enum MagicBufferError {
-    /// There is not enough memory available.
-    #[error("out of memory")]
-    OOM,
+    /// There is not enough memory available, or the operating system failed to create
+    /// the mapping for another reason.
+    #[error("out of memory{}", code.map(|code| format!(", os error {code}")).unwrap_or_default())]
+    OOM {
+        /// The raw error code reported by the operating system, if any. This is `errno`
+        /// on Linux and the BSDs, the `kern_return_t` on macOS and `GetLastError()` on
+        /// Windows.
+        code: Option<i32>,
+    },
     /// The specified buffer length is invalid. See [`MagicBuffer::new`] for more information.
     #[error("invalid buffer len, {msg}")]
     InvalidLen {
         /// Details on why the `len` is invalid.
         msg: String,
     },
+    /// Locking the buffer in memory failed.
+    #[error("failed to lock memory, {source}")]
+    LockFailed {
+        /// The error reported by the operating system.
+        source: std::io::Error,
+    },
+    /// Binding the buffer to a NUMA node failed, see [`MagicBufferOptions::numa_node`].
+    #[error("failed to bind memory to numa node, {source}")]
+    BindFailed {
+        /// The error reported by the operating system.
+        source: std::io::Error,
+    },
+    /// Locking the buffer in memory failed, because it would exceed the `RLIMIT_MEMLOCK`
+    /// resource limit of the process. Raise the limit, e.g. via `ulimit -l`, or lock
+    /// a smaller region.
+    #[error("locking {requested} bytes exceeds the memlock limit of {limit} bytes")]
+    LockLimitExceeded {
+        /// The number of bytes that were requested to be locked.
+        requested: usize,
+        /// The current `RLIMIT_MEMLOCK` soft limit in bytes.
+        limit: usize,
+    },
+    /// The specified name is invalid. See [`MagicBuffer::open_shared`] for more information.
+    #[error("invalid name, {msg}")]
+    InvalidName {
+        /// Details on why the name is invalid.
+        msg: String,
+    },
+    /// Opening or sizing a file failed. See [`MagicBuffer::from_file`] for more information.
+    #[error("i/o error, {source}")]
+    Io {
+        /// The error reported by the operating system.
+        source: std::io::Error,
+    },
+    /// The specified offset is invalid. See [`MagicBuffer::from_fd`] for more information.
+    #[error("invalid offset, {msg}")]
+    InvalidOffset {
+        /// Details on why the `offset` is invalid.
+        msg: String,
+    },
+    /// The requested range does not fit into the buffer.
+    #[error("out of bounds, {len} exceeds the buffer len {buf_len}")]
+    OutOfBounds {
+        /// The len of the requested range.
+        len: usize,
+        /// The len of the buffer.
+        buf_len: usize,
+    },
+    /// The reader fell behind by more than the buffer len, and the writer overwrote
+    /// bytes that were never read.
+    #[error("reader was lapped, {lost} bytes were overwritten before being read")]
+    ReaderLapped {
+        /// The number of bytes that were lost.
+        lost: usize,
+    },
+    /// The requested base address is already in use or can't be mapped.
+    #[error("the requested address is unavailable")]
+    AddressUnavailable,
+    /// The requested operation is not supported by the platform or the backing object.
+    #[error("unsupported, {msg}")]
+    Unsupported {
+        /// Details on what is not supported.
+        msg: String,
+    },
+}
+
+impl MagicBufferError {
+    /// Returns a [`MagicBufferError::OOM`] with the last OS error of the calling thread.
+    pub(crate) fn last_os_oom() -> Self {
+        Self::OOM {
+            code: io::Error::last_os_error().raw_os_error(),
+        }
+    }
 }
 
-#[derive(Debug)]
-pub struct MagicBuffer {
+pub struct MagicBuffer<T = u8> {
     addr: *mut u8,
+    /// The number of elements in the mapping, which offsets wrap around.
+    capacity: usize,
+    /// The number of elements at the front of the buffer that hold meaningful data, see
+    /// [`MagicBuffer::len`]. Never larger than `capacity`.
     len: usize,
     mask: usize,
+    backing: Option<MagicBufBacking>,
+    #[cfg(target_os = "linux")]
+    arena: Option<std::sync::Arc<arena::ArenaInner>>,
+    /// The file of a file backed buffer, needed to flush it to disk on Windows.
+    #[cfg(target_family = "windows")]
+    file: Option<std::fs::File>,
+    huge_page_len: Option<usize>,
+    /// The options the buffer was allocated with, used to allocate clones the same way.
+    options: Option<MagicBufferOptions>,
+    write_pos: usize,
+    read_pos: usize,
+    /// The offset in the first view that logical index 0 maps to, see
+    /// [`MagicBuffer::rotate_left`].
+    base_offset: usize,
+    _marker: PhantomData<T>,
 }
 
 // SAFETY: Memory mappings are not tied to a thread, so they can be sent
 // across thread boundaries safely.
-unsafe impl Send for MagicBuffer {}
+unsafe impl<T: Send> Send for MagicBuffer<T> {}
 
-// SAFETY: There is no interior mutability.
-unsafe impl Sync for MagicBuffer {}
+// SAFETY: There is no interior mutability, except for the atomic stores
+// whose callers must uphold the contract documented on them.
+unsafe impl<T: Sync> Sync for MagicBuffer<T> {}
 
 /// [`MagicBuffer`] provides a ring buffer implementation that
 /// can deref into a contiguous slice from any offset wrapping
@@ -64,6 +370,8 @@ unsafe impl Sync for MagicBuffer {}
 /// The underlying buffer is mapped twice into virtual memory where
 /// the second mapping is adjacent to the first one. The logic
 /// for wrapping around the buffer is pushed down to the hardware.
+/// Buffers that need longer contiguous slices can be mapped more
+/// than twice, see [`MagicBufferOptions::mirror_count`].
 ///
 /// # Examples
 /// ```
@@ -76,7 +384,14 @@ unsafe impl Sync for MagicBuffer {}
 /// # Ok(())
 /// # }
 /// ```
-#[allow(clippy::len_without_is_empty)]
+///
+/// # Address Stability
+/// The contents of a [`MagicBuffer`] live in the virtual memory mapping, not in the
+/// struct itself. Moving a [`MagicBuffer`] (into a `Box`, a `Vec`, another thread, ...)
+/// never moves the mapping, so pointers and slices into the buffer stay valid for as
+/// long as the buffer is alive. This makes it sound to store a [`MagicBuffer`] next to
+/// pointers into it, e.g. in a `Pin<Box<_>>` self-referential struct. The mapping only
+/// goes away when the buffer is dropped.
 impl MagicBuffer {
     /// Allocates a new [`MagicBuffer`] of the specified `len`.
     ///
@@ -84,6 +399,8 @@ impl MagicBuffer {
     /// of the operating system's allocation granularity. This is
     /// usually the page size - most commonly 4KiB. On Windows
     /// the allocation granularity is 64KiB (see [here](https://devblogs.microsoft.com/oldnewthing/20031008-00/?p=42223)).
+    /// It also must not exceed [`MagicBuffer::max_len`]. Use
+    /// [`MagicBuffer::next_valid_len`] to round a requested len up to a valid one.
     ///
     /// ## Errors
     /// Will return a [`MagicBufferError`] if the allocation fails.
@@ -96,418 +413,5407 @@ impl MagicBuffer {
     /// ## Panics
     /// Will panic if it fails to cleanup in case of an error.
     pub fn new(len: usize) -> Result<Self, MagicBufferError> {
-        if len == 0 {
+        let buf = MagicBufferOptions::new(len).build()?;
+
+        // fresh mappings are zeroed by the OS, sample the edges to catch platforms
+        // where that does not hold without faulting in every page
+        debug_assert!(
+            buf.first() == Some(&0) && buf.last() == Some(&0),
+            "fresh mapping is not zeroed"
+        );
+
+        Ok(buf)
+    }
+
+    /// Allocates a new [`MagicBuffer`] of the specified `len`, validating `len` with
+    /// [`MagicBuffer::validate_len`] before allocating.
+    ///
+    /// This behaves like [`MagicBuffer::new`], but keeps the two kinds of failures apart:
+    /// the outer `Result` is the validation of `len`, the inner one the allocation, which
+    /// only fails if the operating system can't create the mapping.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// # use magic_buffer::{MagicBuffer, MagicBufferError};
+    /// # fn main() -> Result<(), MagicBufferError> {
+    /// let buf = MagicBuffer::checked_new(MagicBuffer::min_len())??;
+    /// assert_eq!(MagicBuffer::min_len(), buf.len());
+    /// assert!(MagicBuffer::checked_new(3).is_err());
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// ## Errors
+    /// Will return [`MagicBufferError::InvalidLen`] if `len` is invalid, and a
+    /// [`MagicBufferError`] in the inner `Result` if the allocation fails.
+    pub fn checked_new(len: usize) -> Result<Result<Self, MagicBufferError>, MagicBufferError> {
+        Self::validate_len(len)?;
+        Ok(unsafe { Self::new_unchecked(len) })
+    }
+
+    /// Allocates a new [`MagicBuffer`] of the specified `len` without validating `len`,
+    /// for callers that have already checked it with [`MagicBuffer::validate_len`].
+    ///
+    /// ## Examples
+    /// ```rust
+    /// # use magic_buffer::MagicBuffer;
+    /// let len = MagicBuffer::min_len();
+    /// MagicBuffer::validate_len(len).unwrap();
+    /// let buf = unsafe { MagicBuffer::new_unchecked(len) }.unwrap();
+    /// assert_eq!(len, buf.len());
+    /// ```
+    ///
+    /// ## Safety
+    /// `len` must be valid, i.e. [`MagicBuffer::validate_len`] must succeed for it. The
+    /// wrap-around logic relies on it, and an invalid `len` leads to out of bounds
+    /// accesses.
+    ///
+    /// ## Errors
+    /// Will return [`MagicBufferError::OOM`] if the allocation fails.
+    pub unsafe fn new_unchecked(len: usize) -> Result<Self, MagicBufferError> {
+        debug_assert!(Self::validate_len(len).is_ok(), "len is invalid");
+        Self::from_valid_options(&MagicBufferOptions::new(len))
+    }
+
+    /// Allocates a new [`MagicBuffer`] of exactly `len` bytes, where `len` only has to be
+    /// a multiple of [`MagicBuffer::min_len`] and doesn't need to be a power of two.
+    ///
+    /// This avoids rounding e.g. a 48KiB buffer up to 64KiB. The tradeoff is speed:
+    /// buffers with a power of two len wrap offsets around with a bitmask, while all
+    /// other buffers need an integer division for every index, slice and copy, which is
+    /// considerably slower. Prefer [`MagicBuffer::new`] where the extra memory doesn't
+    /// matter. Buffers with a power of two len take the fast path either way.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// # use magic_buffer::MagicBuffer;
+    /// let len = 3 * MagicBuffer::min_len();
+    /// let mut buf = MagicBuffer::with_exact_capacity(len).unwrap();
+    /// buf[len - 1] = b'a';
+    /// assert_eq!(len, buf.len());
+    /// assert_eq!(b'a', buf[2 * len - 1]);
+    /// ```
+    ///
+    /// ## Errors
+    /// Will return a [`MagicBufferError`] if `len` is invalid or the allocation fails.
+    pub fn with_exact_capacity(len: usize) -> Result<Self, MagicBufferError> {
+        let mut options = MagicBufferOptions::new(len);
+        options.exact_len = true;
+        options.build()
+    }
+
+    /// Allocates a new [`MagicBuffer`] holding a copy of `data` at its front.
+    ///
+    /// The buffer is allocated with the smallest valid `len` that fits `data`. The bytes
+    /// after `data` stay zeroed, as they are in any freshly allocated buffer.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// # use magic_buffer::MagicBuffer;
+    /// let buf = MagicBuffer::from_slice(b"abcd").unwrap();
+    /// assert_eq!(MagicBuffer::min_len(), buf.len());
+    /// assert_eq!(b"abcd\0", &buf[0..5]);
+    /// ```
+    ///
+    /// ## Errors
+    /// Will return a [`MagicBufferError`] if `data` is too large or the allocation fails.
+    pub fn from_slice(data: &[u8]) -> Result<Self, MagicBufferError> {
+        if data.len() > Self::max_len() {
             return Err(MagicBufferError::InvalidLen {
-                msg: "len must be greater than 0".to_string(),
+                msg: format!("len must not exceed {}", Self::max_len()),
             });
         }
 
-        if !len.is_power_of_two() {
+        let mut buf = Self::new(Self::next_valid_len(data.len()))?;
+        buf.copy_from_slice(data);
+        Ok(buf)
+    }
+
+    /// Checks whether `len` is a valid len for a [`MagicBuffer`] without allocating,
+    /// e.g. to validate a user-entered len up front. See [`MagicBuffer::new`] for the
+    /// rules `len` has to follow.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// # use magic_buffer::MagicBuffer;
+    /// assert!(MagicBuffer::validate_len(MagicBuffer::min_len()).is_ok());
+    /// assert!(MagicBuffer::validate_len(MagicBuffer::min_len() + 1).is_err());
+    /// ```
+    ///
+    /// ## Errors
+    /// Will return [`MagicBufferError::InvalidLen`] if `len` is invalid.
+    pub fn validate_len(len: usize) -> Result<(), MagicBufferError> {
+        if len != 0 && !len.is_power_of_two() {
             return Err(MagicBufferError::InvalidLen {
                 msg: "len must be power of two".to_string(),
             });
         }
 
+        Self::validate_exact_len(len)
+    }
+
+    /// Checks `len` like [`MagicBuffer::validate_len`], but also accepts lens that are
+    /// not a power of two, see [`MagicBuffer::with_exact_capacity`].
+    fn validate_exact_len(len: usize) -> Result<(), MagicBufferError> {
+        if len == 0 {
+            return Err(MagicBufferError::InvalidLen {
+                msg: "len must be greater than 0".to_string(),
+            });
+        }
+
         let min_len = Self::min_len();
-        if len % min_len != 0 {
+        if len & (min_len - 1) != 0 {
             return Err(MagicBufferError::InvalidLen {
                 msg: format!("len must be page aligned, {}", min_len),
             });
         }
 
-        Ok(Self {
-            addr: unsafe { magic_buf_alloc(len) }?,
-            mask: len - 1,
-            len,
-        })
-    }
-
-    /// Returns the minimum buffer len that can be allocated.
-    ///
-    /// This is usually the page size - most commonly 4KiB. On Windows
-    /// the allocation granularity is 64KiB (see [here](https://devblogs.microsoft.com/oldnewthing/20031008-00/?p=42223)).
-    pub fn min_len() -> usize {
-        unsafe { magic_buf_min_len() }
-    }
+        let max_len = Self::max_len();
+        if len > max_len {
+            return Err(MagicBufferError::InvalidLen {
+                msg: format!("len must not exceed {}", max_len),
+            });
+        }
 
-    /// Returns the length of this [`MagicBuffer`].
-    pub fn len(&self) -> usize {
-        self.len
+        Ok(())
     }
 
-    /// Returns an unsafe pointer to the [`MagicBuffer`]. The `offset` species the first
-    /// element the pointer points to. The pointer can be used to address up to `len` elements.
+    /// Opens the shared [`MagicBuffer`] called `name`, creating it if it doesn't exist yet.
     ///
-    /// The caller must ensure that the [`MagicBuffer`] outlives the pointer this function returns,
-    /// or else it will end up pointing to garbage.
+    /// Every process that opens the same `name` maps the same memory, and sees the writes
+    /// of all others. The first process creates the buffer with the given `len`, all
+    /// later ones must pass the same `len`. On Linux, macOS and the BSDs the buffer is a
+    /// POSIX shared memory object, and `name` should start with a `/`, e.g.
+    /// `/my_ring`. On Windows it is a named file mapping, e.g. `Local\my_ring`.
     ///
-    /// The caller must also ensure that the memory the pointer (non-transitively) points to is
-    /// never written to (except inside an UnsafeCell) using this pointer or any pointer derived
-    /// from it. If you need to mutate the contents of the slice, use [`as_mut_ptr`](MagicBuffer::as_mut_ptr).
+    /// Dropping the buffer never removes the shared object, as other processes may still
+    /// use it. On Windows it disappears with the last handle to it, elsewhere it lives
+    /// until [`MagicBuffer::unlink_shared`] is called.
     ///
     /// ## Examples
     /// ```rust
     /// # use magic_buffer::MagicBuffer;
-    /// let x = MagicBuffer::new(MagicBuffer::min_len()).unwrap();
-    /// let x_ptr = x.as_ptr(1);
-    ///
-    /// unsafe {
-    ///     for i in 0..x.len() {
-    ///         assert_eq!(*x_ptr.add(i), 0);
-    ///     }
-    /// }
+    /// let name = format!("/magic_buffer_doc_{}", std::process::id());
+    /// let len = MagicBuffer::min_len();
+    /// let mut a = MagicBuffer::open_shared(&name, len).unwrap();
+    /// let b = MagicBuffer::open_shared(&name, len).unwrap();
+    /// a[0] = 1u8;
+    /// assert_eq!(1u8, b[0]);
+    /// MagicBuffer::unlink_shared(&name).unwrap();
     /// ```
-    pub fn as_ptr(&self, offset: usize) -> *const u8 {
-        unsafe { self.addr.add(self.fast_mod(offset)).cast_const() }
+    ///
+    /// ## Errors
+    /// Will return [`MagicBufferError::InvalidName`] if `name` contains a null byte,
+    /// [`MagicBufferError::InvalidLen`] if `len` is invalid or doesn't match the len of
+    /// an existing buffer on Linux, macOS and the BSDs, and another [`MagicBufferError`]
+    /// if opening or mapping the shared object fails.
+    pub fn open_shared(name: &str, len: usize) -> Result<Self, MagicBufferError> {
+        Self::validate_len(len)?;
+        let name = CString::new(name).map_err(|_| MagicBufferError::InvalidName {
+            msg: "name must not contain null bytes".to_string(),
+        })?;
+
+        let (addr, backing) = unsafe { magic_buf_open_shared(&name, len) }?;
+        Ok(Self {
+            addr,
+            mask: len - 1,
+            capacity: len,
+            len,
+            backing: Some(backing),
+            #[cfg(target_os = "linux")]
+            arena: None,
+            #[cfg(target_family = "windows")]
+            file: None,
+            huge_page_len: None,
+            options: None,
+            write_pos: 0,
+            read_pos: 0,
+            base_offset: 0,
+            _marker: PhantomData,
+        })
     }
 
-    /// Returns an unsafe mutable pointer to the [`MagicBuffer`]. The `offset` species the first
-    /// element the mutable pointer points to. The mutable pointer can be used to address up
-    /// to `len` elements.
+    /// Removes the name of a shared buffer opened with [`MagicBuffer::open_shared`], so
+    /// that the next `open_shared` creates a new buffer. Buffers that are still open keep
+    /// their mapping until they are dropped. This is a no-op on Windows.
     ///
-    /// The caller must ensure that the [`MagicBuffer`] outlives the pointer this function returns,
-    /// or else it will end up pointing to garbage.
+    /// ## Errors
+    /// Will return an [`io::Error`] if `name` is invalid or doesn't exist.
+    pub fn unlink_shared(name: &str) -> io::Result<()> {
+        let name =
+            CString::new(name).map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+        magic_buf_unlink_shared(&name)
+    }
+
+    /// Opens the file at `path` as a persistent [`MagicBuffer`] of `len` bytes, creating
+    /// the file if it doesn't exist.
+    ///
+    /// The file is truncated or extended to `len` bytes and mapped twice with shared
+    /// mappings, so writes to the buffer end up in the file. Opening the same file with
+    /// the same `len` again, e.g. after a restart, shows the previous contents. Only the
+    /// `len` bytes of the buffer are persisted, the mirror is just a second view of them.
+    /// The operating system writes modified pages back to the file eventually, use
+    /// [`MagicBuffer::flush`] to control when the contents are durable.
     ///
     /// ## Examples
     /// ```rust
     /// # use magic_buffer::MagicBuffer;
-    /// let mut x = MagicBuffer::new(MagicBuffer::min_len()).unwrap();
-    /// let x_ptr = x.as_mut_ptr(1);
+    /// let path = std::env::temp_dir().join(format!("magic_buffer_doc_{}", std::process::id()));
+    /// let len = MagicBuffer::min_len();
+    /// let mut buf = MagicBuffer::from_file(&path, len).unwrap();
+    /// buf[0] = 1u8;
+    /// drop(buf);
     ///
-    /// unsafe {
-    ///     for i in 0..x.len() {
-    ///         *x_ptr.add(i) = (i % 256) as u8;
-    ///     }
-    /// }
+    /// let buf = MagicBuffer::from_file(&path, len).unwrap();
+    /// assert_eq!(1u8, buf[0]);
+    /// # std::fs::remove_file(&path).unwrap();
     /// ```
-    pub fn as_mut_ptr(&mut self, offset: usize) -> *mut u8 {
-        unsafe { self.addr.add(self.fast_mod(offset)) }
-    }
+    ///
+    /// ## Errors
+    /// Will return [`MagicBufferError::Io`] if the file can't be opened or resized, and
+    /// another [`MagicBufferError`] if `len` is invalid or the mapping fails.
+    pub fn from_file(path: impl AsRef<Path>, len: usize) -> Result<Self, MagicBufferError> {
+        Self::validate_len(len)?;
 
-    #[inline(always)]
-    unsafe fn as_slice(&self, offset: usize, len: usize) -> &[u8] {
-        &*(slice_from_raw_parts(self.addr.add(offset), len))
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)
+            .map_err(|source| MagicBufferError::Io { source })?;
+        file.set_len(len as u64)
+            .map_err(|source| MagicBufferError::Io { source })?;
+
+        // the mapping doesn't keep a handle that can flush the file itself on Windows
+        #[cfg(target_family = "windows")]
+        let flush_file = file
+            .try_clone()
+            .map_err(|source| MagicBufferError::Io { source })?;
+
+        let (addr, backing) = unsafe { magic_buf_map_file(file, len) }?;
+        Ok(Self {
+            addr,
+            mask: len - 1,
+            capacity: len,
+            len,
+            backing: Some(backing),
+            #[cfg(target_os = "linux")]
+            arena: None,
+            #[cfg(target_family = "windows")]
+            file: Some(flush_file),
+            huge_page_len: None,
+            options: None,
+            write_pos: 0,
+            read_pos: 0,
+            base_offset: 0,
+            _marker: PhantomData,
+        })
     }
 
-    #[inline(always)]
-    unsafe fn as_slice_mut(&mut self, offset: usize, len: usize) -> &mut [u8] {
-        &mut *(slice_from_raw_parts_mut(self.addr.add(offset), len))
+    /// Allocates a new [`MagicBuffer`] suitable for registration as an `io_uring` fixed
+    /// buffer or as DPDK packet memory.
+    ///
+    /// The base address, i.e. `as_ptr(0)`, is aligned to 2MiB and the buffer is backed by
+    /// 2MiB huge pages when the system has some available, falling back to regular pages
+    /// otherwise. `len` must be a power of two and a multiple of 2MiB.
+    ///
+    /// Note that registering the buffer with `io_uring_register_buffers` pins its pages in
+    /// memory, which counts against `RLIMIT_MEMLOCK` on older kernels. Registering the full
+    /// `2 * len` mirrored range allows fixed reads and writes to wrap around the buffer.
+    /// This is only available on Linux.
+    ///
+    /// ## Errors
+    /// Will return a [`MagicBufferError`] if `len` is invalid or the allocation fails.
+    #[cfg(target_os = "linux")]
+    pub fn new_for_io_uring(len: usize) -> Result<Self, MagicBufferError> {
+        MagicBufferOptions::new(len)
+            .huge_pages(HugePageSize::Size2MiB)
+            .build()
     }
 
-    #[inline(always)]
-    fn fast_mod(&self, v: usize) -> usize {
-        v & self.mask
+    /// Maps `len` bytes of the file descriptor `fd`, starting at `offset`, as a new
+    /// [`MagicBuffer`].
+    ///
+    /// This allows to get the wrap-around behavior over memory that is owned by someone
+    /// else, e.g. a `dma-buf` exported by a GPU driver, a device under `/dev`, a shared
+    /// memory object, or a regular file. The range `[offset, offset + len)` is mapped
+    /// twice with `MAP_SHARED`, so writes are visible to everyone else mapping the same
+    /// object and the `fd` must support shared mappings. `len` follows the same rules as
+    /// for [`MagicBuffer::new`], and `offset` must be a multiple of [`MagicBuffer::min_len`].
+    ///
+    /// The caller is responsible for `fd` referring to an object that is at least
+    /// `offset + len` bytes large. The mapping keeps its own reference to the object, so
+    /// `fd` can be closed once this function returns. This is only available on Linux.
+    ///
+    /// ## Errors
+    /// Will return a [`MagicBufferError`] if the arguments are invalid or the mapping fails.
+    #[cfg(target_os = "linux")]
+    pub fn from_fd(
+        fd: impl std::os::fd::AsFd,
+        offset: u64,
+        len: usize,
+    ) -> Result<Self, MagicBufferError> {
+        Self::map_fd(fd.as_fd(), offset, len, false)
     }
-}
 
-impl Drop for MagicBuffer {
-    fn drop(&mut self) {
-        unsafe { magic_buf_free(self.addr, self.len) }
+    /// Maps `len` bytes of the file descriptor `fd`, starting at `offset`, as a new
+    /// [`MagicBuffer`] with `MAP_SYNC` semantics.
+    ///
+    /// This is meant for persistent memory, i.e. a file on a DAX capable filesystem
+    /// such as ext4 or xfs mounted with `-o dax`. Both views are mapped with
+    /// `MAP_SHARED_VALIDATE | MAP_SYNC`, which guarantees that the file's metadata is
+    /// durable whenever a write fault completes, so data written through the buffer
+    /// only needs to be flushed from the CPU caches (e.g. via `clwb`) to be persistent,
+    /// without calling `msync`. Otherwise this behaves like [`MagicBuffer::from_fd`].
+    /// This is only available on Linux.
+    ///
+    /// ## Errors
+    /// Will return [`MagicBufferError::Unsupported`] if the kernel or the backing
+    /// object does not support `MAP_SYNC`, and another [`MagicBufferError`] if the
+    /// arguments are invalid or the mapping fails.
+    #[cfg(target_os = "linux")]
+    pub fn from_fd_sync(
+        fd: impl std::os::fd::AsFd,
+        offset: u64,
+        len: usize,
+    ) -> Result<Self, MagicBufferError> {
+        Self::map_fd(fd.as_fd(), offset, len, true)
     }
-}
 
-impl Deref for MagicBuffer {
-    type Target = [u8];
+    #[cfg(target_os = "linux")]
+    fn map_fd(
+        fd: std::os::fd::BorrowedFd,
+        offset: u64,
+        len: usize,
+        sync: bool,
+    ) -> Result<Self, MagicBufferError> {
+        use std::os::fd::AsRawFd;
 
-    fn deref(&self) -> &Self::Target {
-        unsafe { self.as_slice(0, self.len) }
-    }
-}
+        Self::validate_len(len)?;
 
-impl DerefMut for MagicBuffer {
-    fn deref_mut(&mut self) -> &mut Self::Target {
-        unsafe { self.as_slice_mut(0, self.len) }
-    }
-}
+        let min_len = Self::min_len();
+        if offset & (min_len as u64 - 1) != 0 {
+            return Err(MagicBufferError::InvalidOffset {
+                msg: format!("offset must be page aligned, {}", min_len),
+            });
+        }
 
-impl Index<usize> for MagicBuffer {
-    type Output = u8;
+        let offset = offset
+            .try_into()
+            .map_err(|_| MagicBufferError::InvalidOffset {
+                msg: "offset is too large".to_string(),
+            })?;
 
-    fn index(&self, index: usize) -> &Self::Output {
-        unsafe { &*self.addr.add(self.fast_mod(index)) }
+        let backing = fd
+            .try_clone_to_owned()
+            .map_err(|err| MagicBufferError::OOM {
+                code: err.raw_os_error(),
+            })?;
+        Ok(Self {
+            addr: unsafe { magic_buf_map_fd(fd.as_raw_fd(), offset, len, 2, sync) }?,
+            mask: len - 1,
+            capacity: len,
+            len,
+            backing: Some(backing),
+            arena: None,
+            #[cfg(target_family = "windows")]
+            file: None,
+            huge_page_len: None,
+            options: None,
+            write_pos: 0,
+            read_pos: 0,
+            base_offset: 0,
+            _marker: PhantomData,
+        })
     }
-}
 
-impl IndexMut<usize> for MagicBuffer {
-    fn index_mut(&mut self, index: usize) -> &mut Self::Output {
-        unsafe { &mut *self.addr.add(self.fast_mod(index)) }
+    /// Unmaps the buffer and returns the object backing it, so that its contents
+    /// can be handed off to another process.
+    ///
+    /// Every [`MagicBuffer`] keeps its backing object open for as long as it lives. On
+    /// Linux this is the file descriptor of the memfd (or of the file passed to
+    /// [`MagicBuffer::from_fd`]), which a successor process can map again with
+    /// [`MagicBuffer::from_fd`]. On Windows this is the handle of the file mapping.
+    /// This allows zero-downtime restarts that preserve the contents of the buffer.
+    /// Returns `None` for the empty buffer, which has no backing object.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// # use magic_buffer::MagicBuffer;
+    /// let len = MagicBuffer::min_len();
+    /// let mut buf = MagicBuffer::new(len).unwrap();
+    /// buf[0] = b'a';
+    ///
+    /// # #[cfg(target_os = "linux")]
+    /// # {
+    /// let fd = buf.into_backing().unwrap();
+    /// let buf = MagicBuffer::from_fd(&fd, 0, len).unwrap();
+    /// assert_eq!(b'a', buf[len]);
+    /// # }
+    /// ```
+    #[cfg(any(target_os = "linux", target_family = "windows"))]
+    pub fn into_backing(mut self) -> Option<MagicBufBacking> {
+        self.backing.take()
     }
-}
 
-macro_rules! index_impl {
-    ($from:ty, $to:ty) => {
-        impl Index<$from> for MagicBuffer {
-            type Output = u8;
+    /// Returns the minimum buffer len that can be allocated.
+    ///
+    /// This is usually the page size - most commonly 4KiB. On Windows
+    /// the allocation granularity is 64KiB (see [here](https://devblogs.microsoft.com/oldnewthing/20031008-00/?p=42223)).
+    pub fn min_len() -> usize {
+        unsafe { magic_buf_min_len() }
+    }
 
-            fn index(&self, index: $from) -> &Self::Output {
-                &self[index as $to]
-            }
+    /// Returns the smallest len `>= requested` that [`MagicBuffer::new`] accepts on the
+    /// current platform, i.e. the next power of two that is also a multiple of
+    /// [`MagicBuffer::min_len`].
+    ///
+    /// ## Examples
+    /// ```rust
+    /// # use magic_buffer::MagicBuffer;
+    /// let len = MagicBuffer::next_valid_len(1000);
+    /// assert_eq!(MagicBuffer::min_len(), len);
+    /// assert!(MagicBuffer::new(len).is_ok());
+    /// ```
+    ///
+    /// ## Panics
+    /// Will panic if `requested` is larger than [`MagicBuffer::max_len`].
+    pub fn next_valid_len(requested: usize) -> usize {
+        if requested > Self::max_len() {
+            panic!("len must not exceed {}", Self::max_len());
         }
 
-        impl IndexMut<$from> for MagicBuffer {
-            fn index_mut(&mut self, index: $from) -> &mut Self::Output {
-                &mut self[index as $to]
-            }
-        }
-    };
+        // the page size is a power of two on all supported platforms, so any larger
+        // power of two is also a multiple of it
+        requested.max(Self::min_len()).next_power_of_two()
+    }
+
+    /// Returns the largest buffer len that can be requested.
+    ///
+    /// This is the largest power of two for which the mirrored mapping of twice the len
+    /// still fits into `isize::MAX` bytes. It is an upper bound derived from the pointer
+    /// width, not a guarantee that an allocation of this size succeeds, which is still
+    /// limited by the available address space and memory.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// # use magic_buffer::MagicBuffer;
+    /// assert!(MagicBuffer::max_len().is_power_of_two());
+    /// assert!(MagicBuffer::new(MagicBuffer::max_len() * 2).is_err());
+    /// ```
+    pub fn max_len() -> usize {
+        1 << (usize::BITS - 3)
+    }
+
+    /// Returns the amount of physical memory backing this [`MagicBuffer`], which is
+    /// its capacity.
+    ///
+    /// The buffer reserves [`MagicBuffer::mapped_len`], i.e. twice its capacity, of
+    /// virtual address space, but all views map the very same pages. A buffer with a
+    /// capacity of `n` bytes therefore never uses more than `n` bytes of RAM, and only
+    /// as much of that as has actually been touched.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// # use magic_buffer::MagicBuffer;
+    /// let buf = MagicBuffer::new(MagicBuffer::min_len()).unwrap();
+    /// assert_eq!(buf.capacity(), buf.physical_footprint());
+    /// assert_eq!(2 * buf.physical_footprint(), buf.mapped_len());
+    /// ```
+    pub fn physical_footprint(&self) -> usize {
+        self.capacity
+    }
+
+    /// Returns the amount of virtual address space mapped by this [`MagicBuffer`],
+    /// which is its capacity times the [`mirror_count`](MagicBuffer::mirror_count),
+    /// i.e. twice its capacity by default. See [`MagicBuffer::physical_footprint`] for
+    /// the physical memory backing it.
+    pub fn mapped_len(&self) -> usize {
+        self.mirror_count() * self.capacity
+    }
+
+    /// Returns a pointer to the buffer at `offset`, just like [`MagicBuffer::as_ptr`],
+    /// but wrapped in a [`SendPtr`] so that it can be moved to other threads.
+    ///
+    /// The caller must ensure that the [`MagicBuffer`] outlives every use of the pointer,
+    /// e.g. by joining the threads it was sent to before dropping the buffer.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// # use magic_buffer::MagicBuffer;
+    /// let mut buf = MagicBuffer::new(MagicBuffer::min_len()).unwrap();
+    /// buf[0] = 1u8;
+    /// let ptr = buf.as_send_ptr(0);
+    /// let value = std::thread::spawn(move || unsafe { *ptr.get() })
+    ///     .join()
+    ///     .unwrap();
+    /// assert_eq!(1, value);
+    /// ```
+    pub fn as_send_ptr(&self, offset: usize) -> SendPtr<*const u8> {
+        SendPtr::new(self.as_ptr(offset))
+    }
+
+    /// Returns a mutable pointer to the buffer at `offset`, just like
+    /// [`MagicBuffer::as_mut_ptr`], but wrapped in a [`SendPtr`] so that it can be moved
+    /// to other threads.
+    ///
+    /// The caller must ensure that the [`MagicBuffer`] outlives every use of the pointer,
+    /// and that writes through it don't race with any other access.
+    pub fn as_send_mut_ptr(&mut self, offset: usize) -> SendPtr<*mut u8> {
+        SendPtr::new(self.as_mut_ptr(offset))
+    }
+
+    /// Consumes the buffer and leaks its mapping, returning a mutable slice over its
+    /// `len` bytes that lives for the rest of the program.
+    ///
+    /// Just like `Vec::leak`, this is meant for data that lives as long as the process,
+    /// such as a process-wide cache. The mapping, its backing object, and any arena it
+    /// was allocated from are never freed. The returned slice only covers the logical
+    /// buffer; the mirror stays mapped behind it, but is not part of the slice.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// # use magic_buffer::MagicBuffer;
+    /// let buf = MagicBuffer::new(MagicBuffer::min_len()).unwrap();
+    /// let bytes: &'static mut [u8] = buf.leak();
+    /// bytes[0] = 1;
+    /// ```
+    pub fn leak(self) -> &'static mut [u8] {
+        let mut buf = std::mem::ManuallyDrop::new(self);
+        let slice = buf.as_mut_bytes();
+        unsafe { &mut *(slice as *mut [u8]) }
+    }
+
+    /// Returns the entire buffer as a byte slice. This is equivalent to `&*buf`, but
+    /// avoids relying on deref coercion in generic contexts.
+    pub fn as_bytes(&self) -> &[u8] {
+        unsafe { self.view_slice(self.view_offset(0), self.len) }
+    }
+
+    /// Returns the entire buffer as a mutable byte slice. This is equivalent to
+    /// `&mut *buf`, but avoids relying on deref coercion in generic contexts.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// # use magic_buffer::MagicBuffer;
+    /// let mut buf = MagicBuffer::new(MagicBuffer::min_len()).unwrap();
+    /// buf.as_mut_bytes()[0] = 1;
+    /// assert_eq!(1, buf.as_bytes()[0]);
+    /// ```
+    pub fn as_mut_bytes(&mut self) -> &mut [u8] {
+        unsafe { self.view_slice_mut(self.view_offset(0), self.len) }
+    }
+
+    /// Shrinks the buffer to the smallest valid len that still holds `high_water`
+    /// bytes, and returns whether it shrank.
+    ///
+    /// This is a policy hook for buffer pools that track how many bytes of a buffer
+    /// are actually in use. The first `high_water` bytes are copied into a new, smaller
+    /// mapping with the same options, which replaces the current one. Bytes beyond that
+    /// are discarded. Buffers allocated with [`MagicBuffer::with_exact_capacity`] shrink
+    /// to the next multiple of the page size, all others to the next valid len. If the
+    /// buffer is already as small as possible, can't be resized like a buffer mapping a
+    /// file, or allocating the smaller mapping fails, the buffer is left untouched and
+    /// `false` is returned.
+    ///
+    /// Shrinking moves the buffer to a new mapping, so pointers obtained before are
+    /// invalidated.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// # use magic_buffer::MagicBuffer;
+    /// let min_len = MagicBuffer::min_len();
+    /// let mut buf = MagicBuffer::new(4 * min_len).unwrap();
+    /// assert!(buf.shrink_to_fit_if_idle(min_len + 1));
+    /// assert_eq!(2 * min_len, buf.len());
+    /// assert!(!buf.shrink_to_fit_if_idle(min_len + 1));
+    /// ```
+    pub fn shrink_to_fit_if_idle(&mut self, high_water: usize) -> bool {
+        if high_water >= self.capacity {
+            return false;
+        }
+
+        let options = self.options.as_ref();
+        let page_len = options
+            .and_then(|options| options.huge_pages)
+            .map_or_else(Self::min_len, HugePageSize::bytes);
+        let len = if options.is_some_and(|options| options.exact_len) {
+            // can't overflow, high_water is below the capacity which is page aligned
+            (high_water.max(1) + page_len - 1) & !(page_len - 1)
+        } else {
+            Self::next_valid_len(high_water).max(page_len)
+        };
+        len < self.capacity && self.reallocate(len).is_ok()
+    }
+
+    /// Resizes the buffer to `new_len`, keeping its first `len` bytes at the front. If the
+    /// buffer shrinks, only the first `new_len` bytes are kept. The bytes after the
+    /// copied ones are zeroed.
+    ///
+    /// The mirrored mapping can't be extended in place portably, so this allocates a new
+    /// mapping with the same options, copies the contents over and frees the old one.
+    /// Pointers and slices obtained before resizing are invalidated, and the read and
+    /// write positions are reset to 0. Only buffers that own their memory can be resized,
+    /// i.e. buffers built from [`MagicBufferOptions`] or allocated from an arena. Buffers
+    /// mapping a file or a shared object are never moved to a different backing.
+    ///
+    /// `new_len` follows the same rules as for [`MagicBuffer::new`], or for
+    /// [`MagicBuffer::with_exact_capacity`] if the buffer has been allocated that way.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// # use magic_buffer::MagicBuffer;
+    /// let len = MagicBuffer::min_len();
+    /// let mut buf = MagicBuffer::new(len).unwrap();
+    /// buf[len - 1] = b'a';
+    /// buf.resize(4 * len).unwrap();
+    /// assert_eq!(4 * len, buf.len());
+    /// assert_eq!(b"a\0", &buf[len - 1..len + 1]);
+    /// ```
+    ///
+    /// ## Errors
+    /// Will return [`MagicBufferError::Unsupported`] if the buffer maps a file or a shared
+    /// object, and another [`MagicBufferError`] if `new_len` is invalid or the allocation
+    /// fails. The buffer is left untouched in that case.
+    pub fn resize(&mut self, new_len: usize) -> Result<(), MagicBufferError> {
+        if self.options.as_ref().is_some_and(|o| o.exact_len) {
+            Self::validate_exact_len(new_len)?;
+        } else {
+            Self::validate_len(new_len)?;
+        }
+        if new_len == self.capacity {
+            return Ok(());
+        }
+
+        self.reallocate(new_len)
+    }
+
+    fn reallocate(&mut self, len: usize) -> Result<(), MagicBufferError> {
+        let mut buf = self.alloc_like(len)?;
+        let copy_len = len.min(self.len);
+        buf[0..copy_len].copy_from_slice(&self[0..copy_len]);
+        if self.len < self.capacity {
+            buf.len = copy_len;
+        }
+        std::mem::swap(self, &mut buf);
+        Ok(())
+    }
+
+    /// Allocates a new buffer of `len` the same way `self` has been allocated, i.e. from
+    /// the same arena or with the same options. Buffers without options map memory owned
+    /// by someone else, e.g. a file, which can't be replaced by an anonymous mapping.
+    fn alloc_like(&self, len: usize) -> Result<Self, MagicBufferError> {
+        #[cfg(target_os = "linux")]
+        if let Some(arena) = &self.arena {
+            return arena.alloc(len);
+        }
+
+        match &self.options {
+            Some(options) => {
+                // the new mapping can't be placed at the address of the old one
+                let mut options = options.clone();
+                options.len = len;
+                options.address_hint = None;
+                Self::from_options(&options)
+            }
+            // the empty buffer has no backing yet, so any is fine
+            None if self.addr.is_null() => Self::new(len),
+            None => Err(MagicBufferError::Unsupported {
+                msg: "only anonymous buffers can be reallocated".to_string(),
+            }),
+        }
+    }
+
+    /// Locks the pages covering `len` bytes starting at `offset` into physical memory,
+    /// preventing them from being paged out.
+    ///
+    /// This uses `mlock` on Linux and macOS, and `VirtualLock` on Windows. Only the
+    /// given region is locked, which keeps a hot part of a large buffer resident
+    /// without exceeding tight `RLIMIT_MEMLOCK` budgets. `len` is clamped to the
+    /// len of the buffer and the region is extended to page boundaries. The region
+    /// may wrap around the end of the buffer.
+    ///
+    /// ## Errors
+    /// Will return [`MagicBufferError::LockLimitExceeded`] if locking the pages would
+    /// exceed `RLIMIT_MEMLOCK` on Linux and macOS, and another [`MagicBufferError`] if
+    /// the operating system refuses to lock the pages for a different reason.
+    pub fn lock_region(&self, offset: usize, len: usize) -> Result<(), MagicBufferError> {
+        match self.page_region(offset, len) {
+            Some((addr, len)) => unsafe { magic_buf_lock(addr, len) },
+            None => Ok(()),
+        }
+    }
+
+    /// Unlocks the pages covering `len` bytes starting at `offset` that have previously
+    /// been locked with [`lock_region`](MagicBuffer::lock_region).
+    ///
+    /// ## Errors
+    /// Will return a [`MagicBufferError`] if the operating system refuses to unlock the
+    /// pages.
+    pub fn unlock_region(&self, offset: usize, len: usize) -> Result<(), MagicBufferError> {
+        match self.page_region(offset, len) {
+            Some((addr, len)) => unsafe { magic_buf_unlock(addr, len) },
+            None => Ok(()),
+        }
+    }
+
+    fn page_region(&self, offset: usize, len: usize) -> Option<(*const u8, usize)> {
+        let len = len.min(self.capacity);
+        if len == 0 {
+            return None;
+        }
+
+        let page_mask = Self::min_len() - 1;
+        let start = self.view_offset(offset) & !page_mask;
+        let end = (self.view_offset(offset) + len + page_mask) & !page_mask;
+        Some((self.view_ptr(start), end - start))
+    }
+
+    /// Makes the mapping read-only and returns it as a [`ReadOnlyMagicBuffer`], e.g. once
+    /// the buffer has been filled from a file and is only read from then on.
+    ///
+    /// This uses `mprotect` on Unix and `VirtualProtect` on Windows for all views of the
+    /// mapping, so that any stray write faults. The protection is lifted again when the
+    /// [`ReadOnlyMagicBuffer`] is dropped.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// # use magic_buffer::MagicBuffer;
+    /// let mut buf = MagicBuffer::new(MagicBuffer::min_len()).unwrap();
+    /// buf[0] = b'a';
+    /// let buf = buf.freeze().unwrap();
+    /// assert_eq!(b'a', buf[0]);
+    /// ```
+    ///
+    /// ## Errors
+    /// Will return a [`MagicBufferError`] if the operating system refuses to change the
+    /// protection, or if the platform doesn't support it.
+    pub fn freeze(self) -> Result<ReadOnlyMagicBuffer, MagicBufferError> {
+        ReadOnlyMagicBuffer::new(self)
+    }
+
+    /// Returns a [`TypedView`] that provides typed access to the contents of the buffer.
+    ///
+    /// The view indexes and slices at the granularity of `T` and wraps around just
+    /// like the buffer does. The buffer stays borrowed and untouched, so multiple
+    /// views of different types can coexist.
+    ///
+    /// ## Panics
+    /// Will panic if the size of `T` is not a power of two, if the alignment of `T`
+    /// exceeds [`MagicBuffer::min_len`], or if the buffer has been rotated by a number of
+    /// bytes that is not a multiple of the size of `T`.
+    pub fn view<T: Pod>(&self) -> TypedView<'_, T> {
+        TypedView::new(self)
+    }
+
+    /// Zeroes the whole capacity of the buffer using up to `threads` threads.
+    ///
+    /// The mapping is partitioned into disjoint regions of roughly equal size on page
+    /// boundaries, independent of any rotation of the buffer, and each region is zeroed
+    /// by its own scoped thread with a single contiguous write. Since the regions never
+    /// overlap and the call only returns once every thread has finished, there are no
+    /// data races. This speeds up clearing very large buffers, for small ones the cost
+    /// of spawning threads dominates.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// # use magic_buffer::MagicBuffer;
+    /// let mut buf = MagicBuffer::new(MagicBuffer::min_len()).unwrap();
+    /// buf.fill(1);
+    /// buf.reset_parallel(4);
+    /// assert!(buf.verify_zeroed());
+    /// ```
+    pub fn reset_parallel(&mut self, threads: usize) {
+        let min_len = Self::min_len();
+        let threads = threads.max(1);
+        let chunk_len = (self.capacity / threads + min_len - 1) & !(min_len - 1);
+        let chunk_len = chunk_len.max(min_len);
+
+        // split the first view, which starts at a page boundary, instead of the
+        // rotated slice, so that the regions stay page aligned
+        let capacity = self.capacity;
+        std::thread::scope(|s| {
+            for chunk in unsafe { self.view_slice_mut(0, capacity) }.chunks_mut(chunk_len) {
+                s.spawn(|| chunk.fill(0));
+            }
+        });
+        unsafe { magic_buf_sync_mirror(self.addr, capacity, 0, capacity) };
+    }
+
+    /// Fills the buffer with the incrementing pattern `start, start + 1, ..., 255, 0, ...`.
+    ///
+    /// Afterwards, the byte at any offset `o` is `start + o` (wrapping), which makes
+    /// wrap-around behavior easy to assert on in tests and fuzz harnesses.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// # use magic_buffer::MagicBuffer;
+    /// let len = MagicBuffer::min_len();
+    /// let mut buf = MagicBuffer::new(len).unwrap();
+    /// buf.fill_incrementing(10);
+    /// assert_eq!(15u8, buf[5]);
+    /// assert_eq!(buf[len - 1], buf[2 * len - 1]);
+    /// ```
+    pub fn fill_incrementing(&mut self, start: u8) {
+        for (i, b) in self.iter_mut().enumerate() {
+            *b = start.wrapping_add(i as u8);
+        }
+    }
+
+    /// Checks the internal invariants of the buffer and returns whether they hold.
+    ///
+    /// This verifies that the base address is non-null and page aligned, that the len is a
+    /// valid buffer len within [`MagicBuffer::max_len`] with a matching mask, and that the
+    /// first and last bytes of both views agree, i.e. that the mirror is still mapped. The
+    /// check is cheap and is meant for debug assertions and fuzzing harnesses that want
+    /// to assert consistency after every operation. The empty buffer, which has no
+    /// mapping at all, is valid as well.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// # use magic_buffer::MagicBuffer;
+    /// let buf = MagicBuffer::new(MagicBuffer::min_len()).unwrap();
+    /// assert!(buf.validate());
+    /// ```
+    pub fn validate(&self) -> bool {
+        if self.addr.is_null() {
+            return self.capacity == 0 && self.len == 0 && self.mask == 0;
+        }
+
+        if self.len > self.capacity || self.addr as usize & (Self::min_len() - 1) != 0 {
+            return false;
+        }
+
+        if Self::validate_exact_len(self.capacity).is_err() || self.mask != len_mask(self.capacity)
+        {
+            return false;
+        }
+
+        let first = unsafe { self.view_slice(0, 1) }[0];
+        let last = unsafe { self.view_slice(self.capacity - 1, 1) }[0];
+        first == unsafe { self.view_slice(self.capacity, 1) }[0]
+            && last == unsafe { self.view_slice(2 * self.capacity - 1, 1) }[0]
+    }
+
+    /// Returns `true` if every byte of the buffer is zero.
+    ///
+    /// Newly allocated buffers are zeroed by the operating system. This method
+    /// allows to verify that assumption on the current platform. Note that it
+    /// reads the whole buffer and therefore faults in every page of the mapping.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// # use magic_buffer::MagicBuffer;
+    /// let mut buf = MagicBuffer::new(MagicBuffer::min_len()).unwrap();
+    /// assert!(buf.verify_zeroed());
+    /// buf[1] = 1u8;
+    /// assert!(!buf.verify_zeroed());
+    /// ```
+    pub fn verify_zeroed(&self) -> bool {
+        self.iter().all(|b| *b == 0)
+    }
+
+    /// Splits the buffer at `mid` into the two disjoint mutable regions `[0, mid)` and
+    /// `[mid, len)`, relative to the current start of a rotated buffer.
+    ///
+    /// This is a wrap-aware name for [`MagicBuffer::split_at_mut`], which it delegates
+    /// to. Both halves are contiguous thanks to the mirrored mapping, so they can be
+    /// handed to separate threads for data-parallel processing of a ring.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// # use magic_buffer::MagicBuffer;
+    /// let len = MagicBuffer::min_len();
+    /// let mut buf = MagicBuffer::new(len).unwrap();
+    /// buf.rotate_left(len - 4);
+    /// let (left, right) = buf.split_at_mut_wrapping(len / 4);
+    /// std::thread::scope(|s| {
+    ///     s.spawn(|| left.fill(1));
+    ///     s.spawn(|| right.fill(2));
+    /// });
+    /// assert_eq!(1u8, buf[len / 4 - 1]);
+    /// assert_eq!(2u8, buf[len / 4]);
+    /// ```
+    ///
+    /// ## Panics
+    /// Will panic if `mid > len`.
+    pub fn split_at_mut_wrapping(&mut self, mid: usize) -> (&mut [u8], &mut [u8]) {
+        self.split_at_mut(mid)
+    }
+
+    /// Returns an iterator over `capacity / chunk` disjoint mutable chunks, which
+    /// together cover the whole capacity of the buffer, regardless of its len.
+    ///
+    /// The chunks never alias each other, so they can be processed in parallel, e.g.
+    /// by feeding the iterator into `rayon`'s `par_bridge`.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// # use magic_buffer::MagicBuffer;
+    /// let len = MagicBuffer::min_len();
+    /// let mut buf = MagicBuffer::new(len).unwrap();
+    /// for (i, chunk) in buf.chunks_mut_wrapping(len / 4).enumerate() {
+    ///     chunk.fill(i as u8);
+    /// }
+    /// assert_eq!(3u8, buf[len - 1]);
+    /// ```
+    ///
+    /// ## Panics
+    /// Will panic if `chunk` is zero or does not divide the buffer capacity.
+    pub fn chunks_mut_wrapping(&mut self, chunk: usize) -> std::slice::ChunksExactMut<'_, u8> {
+        if self.capacity.checked_rem(chunk) != Some(0) {
+            panic!("chunk must divide the buffer capacity")
+        }
+
+        let base = self.view_offset(0);
+        unsafe { self.view_slice_mut(base, self.capacity) }.chunks_exact_mut(chunk)
+    }
+
+    /// Returns an iterator over `chunk` sized slices that tile the whole buffer once,
+    /// beginning at `start`.
+    ///
+    /// The `start` offset wraps around the buffer, and every chunk is a single contiguous
+    /// slice thanks to the mirrored mapping, even if it straddles the end of the buffer.
+    /// Unlike `slice::chunks` the tiling doesn't need to begin at offset 0. If `chunk`
+    /// doesn't divide the len, the last chunk is shorter.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// # use magic_buffer::MagicBuffer;
+    /// let len = MagicBuffer::min_len();
+    /// let mut buf = MagicBuffer::new(len).unwrap();
+    /// buf[0] = 1u8;
+    /// let chunks = buf.chunks_wrapping(len - 2, 4).collect::<Vec<_>>();
+    /// assert_eq!(len / 4, chunks.len());
+    /// assert_eq!(&[0u8, 0, 1, 0], chunks[0]);
+    /// ```
+    ///
+    /// ## Panics
+    /// Will panic if `chunk` is zero.
+    pub fn chunks_wrapping(&self, start: usize, chunk: usize) -> std::slice::Chunks<'_, u8> {
+        if chunk == 0 {
+            panic!("chunk must not be zero")
+        }
+
+        self[start..].chunks(chunk)
+    }
+
+    /// Splits the buffer around a `gap`, returning the region before `gap.start`
+    /// and the region after `gap.end` as two disjoint mutable slices.
+    ///
+    /// The start of the gap is masked like any other offset, so the gap may wrap
+    /// around the end of the buffer. Both returned regions are bounded by the
+    /// logical buffer `[0, len)` and never overlap the gap or each other. This is
+    /// the core operation of a gap buffer, which needs to hold on to the text before
+    /// and after the cursor at the same time.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// # use magic_buffer::MagicBuffer;
+    /// let len = MagicBuffer::min_len();
+    /// let mut buf = MagicBuffer::new(len).unwrap();
+    /// let (before, after) = buf.split_around(10..20);
+    /// before[0] = 1;
+    /// after[0] = 2;
+    /// assert_eq!(10, before.len());
+    /// assert_eq!(len - 20, after.len());
+    /// assert_eq!(2u8, buf[20]);
+    /// ```
+    ///
+    /// ## Panics
+    /// Will panic if `gap.start > gap.end` or if the gap is larger than the buffer.
+    pub fn split_around(&mut self, gap: Range<usize>) -> (&mut [u8], &mut [u8]) {
+        assert!(gap.start <= gap.end, "gap start must not exceed gap end");
+
+        let gap_len = gap.end - gap.start;
+        assert!(gap_len <= self.capacity, "out of bounds");
+        if self.addr.is_null() {
+            return (&mut [], &mut []);
+        }
+
+        let (base, start) = (self.view_offset(0), self.fast_mod(gap.start));
+        let end = start + gap_len;
+        let (before, after) = if end <= self.capacity {
+            (0..start, end..self.capacity)
+        } else {
+            // the gap wraps around, leaving nothing after it
+            (end - self.capacity..start, self.capacity..self.capacity)
+        };
+        debug_assert!(before.end <= after.start);
+
+        let (before_ptr, after_ptr) = (
+            self.view_ptr(base + before.start),
+            self.view_ptr(base + after.start),
+        );
+        self.mark_written(base, self.capacity);
+        unsafe {
+            (
+                &mut *(slice_from_raw_parts_mut(before_ptr, before.len())),
+                &mut *(slice_from_raw_parts_mut(after_ptr, after.len())),
+            )
+        }
+    }
+
+    /// Writes all of `src` into the buffer starting at `offset`, wrapping around as
+    /// often as needed. Returns the (masked) offset following the last written byte.
+    ///
+    /// `src` may be larger than the buffer, in which case later bytes overwrite earlier
+    /// ones and only the last `len` bytes of `src` survive. This is useful for streaming
+    /// data through the buffer while only keeping its tail. Since earlier bytes would be
+    /// overwritten anyway, they are skipped and the tail is copied in a single
+    /// contiguous write through the mirrored mapping.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// # use magic_buffer::MagicBuffer;
+    /// let len = MagicBuffer::min_len();
+    /// let mut buf = MagicBuffer::new(len).unwrap();
+    /// let src = vec![1u8; 3 * len + 1];
+    /// let end = buf.write_all_wrapping(0, &src);
+    /// assert_eq!(1, end);
+    /// assert!(buf.iter().all(|b| *b == 1));
+    /// ```
+    pub fn write_all_wrapping(&mut self, offset: usize, src: &[u8]) -> usize {
+        if self.capacity == 0 {
+            return 0;
+        }
+
+        let skip = src.len().saturating_sub(self.capacity);
+        let tail = &src[skip..];
+        let start = self.view_offset(offset.wrapping_add(skip));
+        unsafe {
+            ptr::copy_nonoverlapping(tail.as_ptr(), self.view_ptr(start), tail.len());
+            magic_buf_sync_mirror(self.addr, self.capacity, start, tail.len());
+        }
+
+        self.fast_mod(offset.wrapping_add(src.len()))
+    }
+
+    /// Appends `data` to the end of the buffer, i.e. at [`MagicBuffer::len`], and grows
+    /// the len accordingly.
+    ///
+    /// Once the len reaches the capacity, further bytes wrap around and overwrite the
+    /// oldest ones, like pushing onto a ring buffer. The buffer is rotated such that
+    /// index 0 keeps referring to the oldest byte, so deref'ing it always yields the
+    /// last `len` bytes in the order they were appended. If `data` is larger than the
+    /// capacity, only its last `capacity` bytes are kept. The bytes are copied in a
+    /// single contiguous write through the mirrored mapping, even if they wrap around.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// # use magic_buffer::MagicBuffer;
+    /// let len = MagicBuffer::min_len();
+    /// let mut buf = MagicBuffer::new(len).unwrap();
+    /// buf.truncate(0);
+    /// buf.extend_from_slice(b"ab");
+    /// assert_eq!(b"ab", &*buf);
+    ///
+    /// buf.extend_from_slice(&vec![1u8; len]);
+    /// assert_eq!(len, buf.len());
+    /// assert!(buf.iter().all(|b| *b == 1));
+    /// ```
+    pub fn extend_from_slice(&mut self, data: &[u8]) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let skip = data.len().saturating_sub(self.capacity);
+        let tail = &data[skip..];
+        let start = self.view_offset(self.len);
+        unsafe {
+            ptr::copy_nonoverlapping(tail.as_ptr(), self.view_ptr(start), tail.len());
+            magic_buf_sync_mirror(self.addr, self.capacity, start, tail.len());
+        }
+
+        let end = self.len + tail.len();
+        if end > self.capacity {
+            self.rotate_left(end - self.capacity);
+            self.len = self.capacity;
+        } else {
+            self.len = end;
+        }
+    }
+
+    /// Sets all bytes of the buffer to `value`, up to its capacity and regardless of its
+    /// len.
+    ///
+    /// Only `capacity` bytes are written, as the mirrored region shares the same memory.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// # use magic_buffer::MagicBuffer;
+    /// let len = MagicBuffer::min_len();
+    /// let mut buf = MagicBuffer::new(len).unwrap();
+    /// buf.fill(0xAB);
+    /// assert_eq!(0xABu8, buf[len]);
+    /// ```
+    pub fn fill(&mut self, value: u8) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        unsafe {
+            ptr::write_bytes(self.view_ptr(0), value, self.capacity);
+            magic_buf_sync_mirror(self.addr, self.capacity, 0, self.capacity);
+        }
+    }
+
+    /// Sets all bytes of the buffer to zero, up to its capacity. This is a shorthand for
+    /// `fill(0)`.
+    pub fn clear(&mut self) {
+        self.fill(0);
+    }
+
+    /// Copies all of `src` into the front of the buffer, starting at offset 0. Unlike
+    /// [`slice::copy_from_slice`], `src` may be shorter than the buffer, and the bytes
+    /// after it are left untouched.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// # use magic_buffer::MagicBuffer;
+    /// let mut buf = MagicBuffer::new(MagicBuffer::min_len()).unwrap();
+    /// buf.copy_from_slice(b"abcd");
+    /// assert_eq!(b"abcd\0", &buf[0..5]);
+    /// ```
+    ///
+    /// ## Panics
+    /// Will panic if `src` is larger than the buffer.
+    pub fn copy_from_slice(&mut self, src: &[u8]) {
+        self.copy_from_slice_at(0, src);
+    }
+
+    /// Copies all of `src` into the buffer starting at `offset`. The copied bytes may
+    /// wrap around the end of the buffer.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// # use magic_buffer::MagicBuffer;
+    /// let len = MagicBuffer::min_len();
+    /// let mut buf = MagicBuffer::new(len).unwrap();
+    /// buf.copy_from_slice_at(len - 2, b"abcd");
+    /// assert_eq!(b"cd", &buf[0..2]);
+    /// ```
+    ///
+    /// ## Panics
+    /// Will panic if `src` is larger than the buffer. See
+    /// [`MagicBuffer::try_copy_from_slice_at`] for a non-panicking variant.
+    pub fn copy_from_slice_at(&mut self, offset: usize, src: &[u8]) {
+        if let Err(err) = self.try_copy_from_slice_at(offset, src) {
+            panic!("{}", err)
+        }
+    }
+
+    /// Copies all of `src` into the buffer starting at `offset`. The copied bytes may
+    /// wrap around the end of the buffer.
+    ///
+    /// ## Errors
+    /// Will return [`MagicBufferError::OutOfBounds`] and leave the buffer untouched
+    /// if `src` is larger than the buffer.
+    pub fn try_copy_from_slice_at(
+        &mut self,
+        offset: usize,
+        src: &[u8],
+    ) -> Result<(), MagicBufferError> {
+        if src.len() > self.capacity {
+            return Err(MagicBufferError::OutOfBounds {
+                len: src.len(),
+                buf_len: self.capacity,
+            });
+        }
+
+        if src.is_empty() {
+            return Ok(());
+        }
+
+        let start = self.view_offset(offset);
+        unsafe {
+            ptr::copy_nonoverlapping(src.as_ptr(), self.view_ptr(start), src.len());
+            magic_buf_sync_mirror(self.addr, self.capacity, start, src.len());
+        }
+        Ok(())
+    }
+
+    /// Returns the bytes in `range`, or `None` if `range.start > range.end` or the
+    /// range is larger than the buffer. The range may wrap around the end of the buffer.
+    ///
+    /// This is the non-panicking counterpart of indexing with a [`Range`].
+    ///
+    /// ## Examples
+    /// ```rust
+    /// # use magic_buffer::MagicBuffer;
+    /// let len = MagicBuffer::min_len();
+    /// let buf = MagicBuffer::new(len).unwrap();
+    /// assert_eq!(Some(&[0u8; 4][..]), buf.get_range(len - 2..len + 2));
+    /// assert_eq!(None, buf.get_range(0..len + 1));
+    /// ```
+    pub fn get_range(&self, range: Range<usize>) -> Option<&[u8]> {
+        self.get(range)
+    }
+
+    /// Returns the bytes in `range` mutably, or `None` if `range.start > range.end` or
+    /// the range is larger than the buffer. The range may wrap around the end of the
+    /// buffer.
+    pub fn get_range_mut(&mut self, range: Range<usize>) -> Option<&mut [u8]> {
+        self.get_mut(range)
+    }
+
+    /// Returns the bytes in `range`, or an error describing why the range is invalid.
+    /// The range may wrap around the end of the buffer.
+    ///
+    /// Like [`get_range`](MagicBuffer::get_range), this never panics, but the error
+    /// carries the details needed to report a user-supplied range back to the user.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// # use magic_buffer::{MagicBuffer, MagicBufferError};
+    /// let len = MagicBuffer::min_len();
+    /// let buf = MagicBuffer::new(len).unwrap();
+    /// assert_eq!(4, buf.try_slice(len - 2..len + 2).unwrap().len());
+    /// let err = buf.try_slice(0..len + 1).unwrap_err();
+    /// assert!(matches!(err, MagicBufferError::OutOfBounds { .. }));
+    /// ```
+    ///
+    /// ## Errors
+    /// Will return [`MagicBufferError::InvalidOffset`] if `range.start > range.end`, and
+    /// [`MagicBufferError::OutOfBounds`] if the range is larger than the buffer.
+    pub fn try_slice(&self, range: Range<usize>) -> Result<&[u8], MagicBufferError> {
+        self.check_range(&range)?;
+        Ok(&self[range])
+    }
+
+    /// Returns the bytes in `range` mutably, or an error describing why the range is
+    /// invalid. See [`try_slice`](MagicBuffer::try_slice).
+    ///
+    /// ## Errors
+    /// Will return [`MagicBufferError::InvalidOffset`] if `range.start > range.end`, and
+    /// [`MagicBufferError::OutOfBounds`] if the range is larger than the buffer.
+    pub fn try_slice_mut(&mut self, range: Range<usize>) -> Result<&mut [u8], MagicBufferError> {
+        self.check_range(&range)?;
+        Ok(&mut self[range])
+    }
+
+    fn check_range(&self, range: &Range<usize>) -> Result<(), MagicBufferError> {
+        if range.start > range.end {
+            return Err(MagicBufferError::InvalidOffset {
+                msg: format!(
+                    "range start {} must not exceed range end {}",
+                    range.start, range.end
+                ),
+            });
+        }
+
+        let len = range.end - range.start;
+        if !self.in_bounds(range.start, len) {
+            return Err(MagicBufferError::OutOfBounds {
+                len,
+                buf_len: self.len.min(self.max_slice_len()),
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Returns the position at which the next [`io::Write::write`] to the buffer
+    /// starts.
+    ///
+    /// The position grows monotonically (wrapping around at `usize::MAX`) with every
+    /// byte written, and is masked into the buffer on every write.
+    pub fn write_pos(&self) -> usize {
+        self.write_pos
+    }
+
+    /// Returns the position at which the next [`io::Read::read`] from the buffer
+    /// starts.
+    ///
+    /// Like [`MagicBuffer::write_pos`], the position grows monotonically. The bytes in
+    /// `read_pos..write_pos` are available for reading.
+    pub fn read_pos(&self) -> usize {
+        self.read_pos
+    }
+
+    /// Writes all of `src` into the buffer starting at `offset`, which does not need to
+    /// be masked. The write may wrap around the end of the buffer, and is still a single
+    /// copy thanks to the mirrored mapping. Like all offset based copies, this covers the
+    /// whole capacity of the buffer, regardless of its len.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// # use magic_buffer::MagicBuffer;
+    /// let len = MagicBuffer::min_len();
+    /// let mut buf = MagicBuffer::new(len).unwrap();
+    /// buf.write_at(3 * len - 2, b"abcd");
+    /// assert_eq!(b"cd", &buf[0..2]);
+    /// ```
+    ///
+    /// ## Panics
+    /// Will panic if `src` is larger than the capacity of the buffer.
+    pub fn write_at(&mut self, offset: usize, src: &[u8]) {
+        self.copy_from_slice_at(offset, src);
+    }
+
+    /// Fills `dst` with the bytes starting at `offset`, which does not need to be
+    /// masked. The read may wrap around the end of the buffer. Like all offset based
+    /// copies, this covers the whole capacity of the buffer, regardless of its len.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// # use magic_buffer::MagicBuffer;
+    /// let len = MagicBuffer::min_len();
+    /// let mut buf = MagicBuffer::new(len).unwrap();
+    /// buf.write_at(len - 2, b"abcd");
+    /// let mut dst = [0u8; 4];
+    /// buf.read_at(2 * len - 2, &mut dst);
+    /// assert_eq!(b"abcd", &dst);
+    /// ```
+    ///
+    /// ## Panics
+    /// Will panic if `dst` is larger than the capacity of the buffer.
+    pub fn read_at(&self, offset: usize, dst: &mut [u8]) {
+        if dst.len() > self.capacity {
+            panic!("out of bounds")
+        }
+
+        if dst.is_empty() {
+            return;
+        }
+
+        unsafe {
+            ptr::copy_nonoverlapping(
+                self.view_ptr(self.view_offset(offset)),
+                dst.as_mut_ptr(),
+                dst.len(),
+            );
+        }
+    }
+
+    /// Copies `len` bytes from `src_offset` to `dst_offset` within the buffer. Both
+    /// regions may wrap around the end of the buffer and may overlap, the result is the
+    /// same as if the source was copied to a temporary buffer first.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// # use magic_buffer::MagicBuffer;
+    /// let len = MagicBuffer::min_len();
+    /// let mut buf = MagicBuffer::new(len).unwrap();
+    /// buf.write_at(len - 2, b"abcd");
+    /// buf.copy_within(len - 2, 0, 4);
+    /// assert_eq!(b"ababcd", &buf[len - 2..len + 4]);
+    /// ```
+    ///
+    /// ## Panics
+    /// Will panic if `len` is larger than the buffer.
+    pub fn copy_within(&mut self, src_offset: usize, dst_offset: usize, len: usize) {
+        if len > self.capacity {
+            panic!("out of bounds")
+        }
+
+        if len == 0 {
+            return;
+        }
+
+        // the distance from the source to the destination going forward in the ring
+        let delta =
+            self.fast_mod(self.fast_mod(dst_offset) + self.capacity - self.fast_mod(src_offset));
+        if delta < len && delta + len > self.capacity {
+            // the destination overlaps both ends of the source, which no single move
+            // can resolve
+            let mut tmp = vec![0u8; len];
+            self.read_at(src_offset, &mut tmp);
+            self.write_at(dst_offset, &tmp);
+            return;
+        }
+
+        // place both regions within one buffer len wide window of the mirrored mapping,
+        // so that they overlap in the address space iff they overlap in the ring, and
+        // `ptr::copy` picks the right direction
+        let (src, dst) = if delta + len <= self.capacity {
+            let src = self.view_offset(src_offset);
+            (src, src + delta)
+        } else {
+            let dst = self.view_offset(dst_offset);
+            (dst + self.capacity - delta, dst)
+        };
+
+        unsafe {
+            ptr::copy(self.view_ptr(src), self.view_ptr(dst), len);
+            magic_buf_sync_mirror(self.addr, self.capacity, dst, len);
+        }
+    }
+
+    /// Copies `N` bytes starting at `offset` into an array and returns it. The bytes
+    /// may wrap around the end of the buffer.
+    ///
+    /// This is handy for parsing fixed-size fields, as the result pairs nicely with
+    /// `from_le_bytes` / `from_be_bytes` and does not borrow from the buffer.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// # use magic_buffer::MagicBuffer;
+    /// let len = MagicBuffer::min_len();
+    /// let mut buf = MagicBuffer::new(len).unwrap();
+    /// buf.write_all_wrapping(len - 2, &0x01020304u32.to_be_bytes());
+    /// assert_eq!(0x01020304, u32::from_be_bytes(buf.read_array(len - 2)));
+    /// ```
+    ///
+    /// ## Panics
+    /// Will panic if `N` is larger than the buffer.
+    pub fn read_array<const N: usize>(&self, offset: usize) -> [u8; N] {
+        if N > self.capacity {
+            panic!("out of bounds")
+        }
+
+        let mut out = [0u8; N];
+        unsafe {
+            ptr::copy_nonoverlapping(self.view_ptr(self.view_offset(offset)), out.as_mut_ptr(), N);
+        }
+        out
+    }
+
+    /// Folds `f` over `len` bytes starting at `offset`, beginning with `init`.
+    ///
+    /// The region may wrap around the end of the buffer. Thanks to the mirrored mapping
+    /// it is still a single contiguous slice, so this is one tight loop instead of two
+    /// folds stitched together at the wrap point. The region may cover the whole
+    /// capacity of the buffer, regardless of its len.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// # use magic_buffer::MagicBuffer;
+    /// let len = MagicBuffer::min_len();
+    /// let mut buf = MagicBuffer::new(len).unwrap();
+    /// buf.fill(1);
+    /// let sum = buf.checksum(len - 8, 16, 0, |acc, b| acc + b as u32);
+    /// assert_eq!(16, sum);
+    /// ```
+    ///
+    /// ## Panics
+    /// Will panic if `len` is larger than the capacity of the buffer.
+    pub fn checksum(
+        &self,
+        offset: usize,
+        len: usize,
+        init: u32,
+        f: impl Fn(u32, u8) -> u32,
+    ) -> u32 {
+        if len > self.capacity {
+            panic!("out of bounds")
+        }
+
+        let region = unsafe { self.view_slice(self.view_offset(offset), len) };
+        region.iter().fold(init, |acc, b| f(acc, *b))
+    }
+
+    /// Returns the CRC-32 (IEEE) of `len` bytes starting at `offset`.
+    ///
+    /// Requires the `checksum` feature.
+    ///
+    /// ## Panics
+    /// Will panic if `len` is larger than the buffer.
+    #[cfg(feature = "checksum")]
+    pub fn crc32(&self, offset: usize, len: usize) -> u32 {
+        if len > self.capacity {
+            panic!("out of bounds")
+        }
+
+        checksum::crc32(unsafe { self.view_slice(self.view_offset(offset), len) })
+    }
+
+    /// Returns the Adler-32 of `len` bytes starting at `offset`.
+    ///
+    /// Requires the `checksum` feature.
+    ///
+    /// ## Panics
+    /// Will panic if `len` is larger than the buffer.
+    #[cfg(feature = "checksum")]
+    pub fn adler32(&self, offset: usize, len: usize) -> u32 {
+        if len > self.capacity {
+            panic!("out of bounds")
+        }
+
+        checksum::adler32(unsafe { self.view_slice(self.view_offset(offset), len) })
+    }
+
+    /// Fills the whole buffer with random bytes drawn from `rng`.
+    ///
+    /// Requires the `rand` feature.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// # use magic_buffer::MagicBuffer;
+    /// use rand::rngs::mock::StepRng;
+    ///
+    /// let mut buf = MagicBuffer::new(MagicBuffer::min_len()).unwrap();
+    /// buf.fill_random(&mut StepRng::new(1, 1));
+    /// assert_eq!(1u8, buf[0]);
+    /// ```
+    #[cfg(feature = "rand")]
+    pub fn fill_random(&mut self, rng: &mut impl rand::RngCore) {
+        rng.fill_bytes(&mut self[..]);
+    }
+
+    /// Returns an [`IoSlice`] over `len` bytes starting at `offset` for use with
+    /// vectored I/O such as [`Write::write_vectored`](std::io::Write::write_vectored).
+    ///
+    /// A regular ring buffer needs two [`IoSlice`]s to describe a region that wraps
+    /// around its end. Thanks to the mirrored mapping any region of up to `len` bytes
+    /// is contiguous, so a single [`IoSlice`] is always sufficient.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// # use magic_buffer::MagicBuffer;
+    /// # use std::io::Write;
+    /// let len = MagicBuffer::min_len();
+    /// let buf = MagicBuffer::new(len).unwrap();
+    /// let mut out = Vec::new();
+    /// out.write_vectored(&[buf.as_io_slice(len - 2, 4)]).unwrap();
+    /// assert_eq!(4, out.len());
+    /// ```
+    ///
+    /// ## Panics
+    /// Will panic if `len` is larger than the buffer.
+    pub fn as_io_slice(&self, offset: usize, len: usize) -> IoSlice<'_> {
+        if len > self.capacity {
+            panic!("out of bounds")
+        }
+
+        IoSlice::new(unsafe { self.view_slice(self.view_offset(offset), len) })
+    }
+
+    /// Returns an [`IoSliceMut`] over `len` bytes starting at `offset` for use with
+    /// vectored I/O such as [`Read::read_vectored`](std::io::Read::read_vectored).
+    ///
+    /// Like [`as_io_slice`](MagicBuffer::as_io_slice), a single [`IoSliceMut`] covers
+    /// any region of up to `len` bytes, even if it wraps around the end of the buffer.
+    ///
+    /// ## Panics
+    /// Will panic if `len` is larger than the buffer.
+    pub fn as_io_slice_mut(&mut self, offset: usize, len: usize) -> IoSliceMut<'_> {
+        if len > self.capacity {
+            panic!("out of bounds")
+        }
+
+        IoSliceMut::new(unsafe { self.view_slice_mut(self.view_offset(offset), len) })
+    }
+
+    /// Returns a single [`IoSlice`] over the readable bytes between the cursor positions
+    /// `read_pos` and `write_pos`, e.g. to send them with one `write_vectored` or socket
+    /// send call. The positions are supplied by the caller and don't need to be masked.
+    ///
+    /// The span is contiguous even if it wraps around the end of the buffer, and can be
+    /// at most `len` bytes long.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// # use magic_buffer::MagicBuffer;
+    /// let len = MagicBuffer::min_len();
+    /// let mut buf = MagicBuffer::new(len).unwrap();
+    /// buf.write_at(len - 2, b"abcd");
+    /// assert_eq!(b"abcd", &*buf.readable_ioslice(len - 2, len + 2));
+    /// ```
+    ///
+    /// ## Panics
+    /// Will panic if `read_pos` is more than `len` bytes behind `write_pos`.
+    pub fn readable_ioslice(&self, read_pos: usize, write_pos: usize) -> IoSlice<'_> {
+        self.as_io_slice(read_pos, write_pos.wrapping_sub(read_pos))
+    }
+
+    /// Returns a single [`IoSliceMut`] over `len` writable bytes starting at the cursor
+    /// position `write_pos`, e.g. to receive into with one `read_vectored` or socket
+    /// receive call. The position is supplied by the caller and doesn't need to be masked.
+    ///
+    /// The span is contiguous even if it wraps around the end of the buffer, and can be
+    /// at most `len` bytes long.
+    ///
+    /// ## Panics
+    /// Will panic if `len` is larger than the buffer.
+    pub fn writable_ioslice_mut(&mut self, write_pos: usize, len: usize) -> IoSliceMut<'_> {
+        self.as_io_slice_mut(write_pos, len)
+    }
+
+    /// Returns `len` bytes starting at `offset` as uninitialized bytes, to express that
+    /// the region is about to be overwritten, e.g. by a `recv` or a DMA transfer. The
+    /// `offset` wraps around the buffer like indices do.
+    ///
+    /// The buffer is always initialized, the mapping is zeroed when it's created. Use
+    /// [`MagicBuffer::assume_init_slice`] to turn the written region back into bytes.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// # use magic_buffer::MagicBuffer;
+    /// # use std::mem::MaybeUninit;
+    /// let len = MagicBuffer::min_len();
+    /// let mut buf = MagicBuffer::new(len).unwrap();
+    /// let uninit = unsafe { buf.as_uninit_mut(len - 1, 2) };
+    /// uninit[0] = MaybeUninit::new(b'a');
+    /// uninit[1] = MaybeUninit::new(b'b');
+    /// assert_eq!(b"ab", unsafe { MagicBuffer::assume_init_slice(uninit) });
+    /// assert_eq!(b'b', buf[0]);
+    /// ```
+    ///
+    /// ## Safety
+    /// The caller must not write [`MaybeUninit::uninit`] into the returned slice, as the
+    /// buffer is read as initialized bytes afterwards.
+    ///
+    /// ## Panics
+    /// Will panic if `len` is larger than the buffer.
+    pub unsafe fn as_uninit_mut(&mut self, offset: usize, len: usize) -> &mut [MaybeUninit<u8>] {
+        if len > self.max_slice_len() {
+            panic!("out of bounds")
+        }
+
+        let slice = self.view_slice_mut(self.view_offset(offset), len);
+        &mut *(slice as *mut [u8] as *mut [MaybeUninit<u8>])
+    }
+
+    /// Converts a slice returned by [`as_uninit_mut`](MagicBuffer::as_uninit_mut) back
+    /// into bytes once it has been written.
+    ///
+    /// ## Safety
+    /// Every element of `slice` must be initialized.
+    pub unsafe fn assume_init_slice(slice: &mut [MaybeUninit<u8>]) -> &mut [u8] {
+        &mut *(slice as *mut [MaybeUninit<u8>] as *mut [u8])
+    }
+
+    /// Atomically loads the `u32` at `offset`, e.g. a sequence number or flag written by
+    /// another thread or process through [`store_u32`](MagicBuffer::store_u32). The
+    /// `offset` wraps around the buffer like indices do.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// # use magic_buffer::MagicBuffer;
+    /// # use std::sync::atomic::Ordering;
+    /// let buf = MagicBuffer::new(MagicBuffer::min_len()).unwrap();
+    /// unsafe { buf.store_u32(8, 42, Ordering::Release) };
+    /// assert_eq!(42, buf.load_u32(8, Ordering::Acquire));
+    /// ```
+    ///
+    /// ## Panics
+    /// Will panic if the buffer is empty, if `offset` is not aligned to 4 bytes, or if
+    /// `ordering` is `Release` or `AcqRel`.
+    pub fn load_u32(&self, offset: usize, ordering: Ordering) -> u32 {
+        unsafe { self.atomic_u32(offset) }.load(ordering)
+    }
+
+    /// Atomically stores `val` as `u32` at `offset`. The `offset` wraps around the
+    /// buffer like indices do.
+    ///
+    /// Unlike all other writes, this only takes `&self`, so that a buffer shared
+    /// between threads, or mapped into several processes, can be used to publish
+    /// values without a lock.
+    ///
+    /// ## Safety
+    /// The 4 bytes at `offset` must only be accessed atomically while the buffer is
+    /// shared, i.e. with [`load_u32`](MagicBuffer::load_u32) and `store_u32`. Reading
+    /// them through a slice or an index at the same time as a store is a data race.
+    ///
+    /// ## Panics
+    /// Will panic if the buffer is empty, if `offset` is not aligned to 4 bytes, or if
+    /// `ordering` is `Acquire` or `AcqRel`.
+    pub unsafe fn store_u32(&self, offset: usize, val: u32, ordering: Ordering) {
+        if MAGIC_BUF_EMULATED_MIRROR {
+            let twin = self
+                .atomic_ptr(offset, size_of::<AtomicU32>())
+                .add(self.capacity);
+            (*(twin as *const AtomicU32)).store(val, ordering);
+        }
+        self.atomic_u32(offset).store(val, ordering)
+    }
+
+    /// Atomically loads the `u64` at `offset`. See [`load_u32`](MagicBuffer::load_u32).
+    ///
+    /// ## Panics
+    /// Will panic if the buffer is empty, if `offset` is not aligned to 8 bytes, or if
+    /// `ordering` is `Release` or `AcqRel`.
+    pub fn load_u64(&self, offset: usize, ordering: Ordering) -> u64 {
+        unsafe { self.atomic_u64(offset) }.load(ordering)
+    }
+
+    /// Atomically stores `val` as `u64` at `offset`. See
+    /// [`store_u32`](MagicBuffer::store_u32).
+    ///
+    /// ## Safety
+    /// The 8 bytes at `offset` must only be accessed atomically while the buffer is
+    /// shared, i.e. with [`load_u64`](MagicBuffer::load_u64) and `store_u64`.
+    ///
+    /// ## Panics
+    /// Will panic if the buffer is empty, if `offset` is not aligned to 8 bytes, or if
+    /// `ordering` is `Acquire` or `AcqRel`.
+    pub unsafe fn store_u64(&self, offset: usize, val: u64, ordering: Ordering) {
+        if MAGIC_BUF_EMULATED_MIRROR {
+            let twin = self
+                .atomic_ptr(offset, size_of::<AtomicU64>())
+                .add(self.capacity);
+            (*(twin as *const AtomicU64)).store(val, ordering);
+        }
+        self.atomic_u64(offset).store(val, ordering)
+    }
+
+    /// Reinterprets the bytes at `offset` as an [`AtomicU32`]. The buffer len is a
+    /// multiple of the page size, so an aligned value never straddles the wrap.
+    #[inline(always)]
+    unsafe fn atomic_u32(&self, offset: usize) -> &AtomicU32 {
+        &*(self.atomic_ptr(offset, size_of::<AtomicU32>()) as *const AtomicU32)
+    }
+
+    #[inline(always)]
+    unsafe fn atomic_u64(&self, offset: usize) -> &AtomicU64 {
+        &*(self.atomic_ptr(offset, size_of::<AtomicU64>()) as *const AtomicU64)
+    }
+
+    #[inline(always)]
+    fn atomic_ptr(&self, offset: usize, align: usize) -> *mut u8 {
+        if self.addr.is_null() {
+            panic!("out of bounds")
+        }
+
+        let offset = self.view_offset(offset);
+        if offset & (align - 1) != 0 {
+            panic!("offset must be aligned to {} bytes", align);
+        }
+
+        self.view_ptr(offset)
+    }
+
+    /// Clears the soft-dirty bits of all pages of the current process, so that
+    /// [`dirty_pages`](MagicBuffer::dirty_pages) only reports pages written afterwards.
+    ///
+    /// This is a best-effort mechanism for incremental snapshots that is only
+    /// available on Linux. It writes to `/proc/self/clear_refs`, which requires
+    /// the appropriate permissions, and resets the tracking for the whole process,
+    /// not just for this buffer.
+    #[cfg(target_os = "linux")]
+    pub fn reset_dirty_tracking(&self) -> std::io::Result<()> {
+        magic_buf_clear_soft_dirty()
+    }
+
+    /// Returns the offsets of all pages of this buffer that have been written since the
+    /// last call to [`reset_dirty_tracking`](MagicBuffer::reset_dirty_tracking).
+    ///
+    /// Writes through either half of the mirrored mapping are reported against the
+    /// offset in the first half. The information is read from `/proc/self/pagemap`,
+    /// which requires the appropriate permissions. Kernels built without
+    /// `CONFIG_MEM_SOFT_DIRTY` never report any dirty pages. This is only available
+    /// on Linux.
+    #[cfg(target_os = "linux")]
+    pub fn dirty_pages(&self) -> std::io::Result<Vec<usize>> {
+        unsafe { magic_buf_soft_dirty_pages(self.addr, self.capacity) }
+    }
+}
+
+impl<T: Pod> MagicBuffer<T> {
+    /// Allocates a new [`MagicBuffer`] of `len` elements of type `T`.
+    ///
+    /// A typed buffer behaves just like a byte buffer, except that lens, offsets and
+    /// indices are measured in elements, and it derefs into a slice of `T`. The byte len
+    /// of the buffer, i.e. `len * size_of::<T>()`, must follow the same rules as `len`
+    /// for [`MagicBuffer::new`], which requires the size of `T` to be a power of two.
+    /// The buffer starts out zeroed, which [`Pod`] guarantees to be a valid `T`.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// # use magic_buffer::MagicBuffer;
+    /// let len = MagicBuffer::min_len() / 4;
+    /// let mut buf = MagicBuffer::<f32>::with_len(len).unwrap();
+    /// buf[len] = 1.5;
+    /// assert_eq!(1.5f32, buf[0]);
+    /// assert_eq!(len, buf.len());
+    /// ```
+    ///
+    /// ## Errors
+    /// Will return a [`MagicBufferError`] if the size of `T` is not a power of two, the
+    /// resulting byte len is invalid, or the allocation fails.
+    pub fn with_len(len: usize) -> Result<Self, MagicBufferError> {
+        if !size_of::<T>().is_power_of_two() {
+            return Err(MagicBufferError::InvalidLen {
+                msg: "element size must be power of two".to_string(),
+            });
+        }
+
+        let byte_len =
+            len.checked_mul(size_of::<T>())
+                .ok_or_else(|| MagicBufferError::InvalidLen {
+                    msg: format!("len must not exceed {}", MagicBuffer::max_len()),
+                })?;
+
+        Self::from_options(&MagicBufferOptions::new(byte_len))
+    }
+
+    /// Allocates a new [`MagicBuffer`] from the resolved `options`, whose `len` is in bytes.
+    fn from_options(options: &MagicBufferOptions) -> Result<Self, MagicBufferError> {
+        let byte_len = options.len;
+
+        if options.exact_len {
+            MagicBuffer::validate_exact_len(byte_len)?;
+        } else {
+            MagicBuffer::validate_len(byte_len)?;
+        }
+
+        if options.mirror_count < 2 {
+            return Err(MagicBufferError::InvalidLen {
+                msg: "mirror count must be at least 2".to_string(),
+            });
+        }
+        if byte_len.checked_mul(options.mirror_count).is_none() {
+            return Err(MagicBufferError::InvalidLen {
+                msg: "len times mirror count must not overflow".to_string(),
+            });
+        }
+
+        if let Some(huge_pages) = options.huge_pages {
+            if byte_len & (huge_pages.bytes() - 1) != 0 {
+                return Err(MagicBufferError::InvalidLen {
+                    msg: format!("len must be huge page aligned, {}", huge_pages.bytes()),
+                });
+            }
+        }
+
+        if let Some(addr) = options.address_hint {
+            let page_len = options
+                .huge_pages
+                .map_or_else(MagicBuffer::min_len, HugePageSize::bytes);
+            if addr & (page_len - 1) != 0 {
+                return Err(MagicBufferError::InvalidOffset {
+                    msg: format!("address hint must be a multiple of {}", page_len),
+                });
+            }
+        }
+
+        unsafe { Self::from_valid_options(options) }
+    }
+
+    /// Allocates a new [`MagicBuffer`] from `options` that have already been validated.
+    unsafe fn from_valid_options(options: &MagicBufferOptions) -> Result<Self, MagicBufferError> {
+        let byte_len = options.len;
+
+        #[cfg(feature = "tracing")]
+        let _span = tracing::trace_span!("magic_buffer_alloc", len = byte_len).entered();
+
+        let (addr, backing, huge_page_len) = unsafe { magic_buf_alloc(options) }?;
+        debug_assert_eq!(
+            0,
+            addr as usize & (huge_page_len.unwrap_or_else(MagicBuffer::min_len) - 1),
+            "mapping is not page aligned"
+        );
+        if options.lock {
+            // lock all views, on Windows they are locked independently
+            let mapping_len = byte_len * options.mirror_count;
+            if let Err(err) = unsafe { magic_buf_lock(addr, mapping_len) } {
+                unsafe { magic_buf_free(addr, byte_len, options.mirror_count, options.guard_page) };
+                return Err(err);
+            }
+        }
+
+        #[cfg(feature = "tracing")]
+        tracing::trace!(len = byte_len, id = addr_id(addr), "allocated magic buffer");
+
+        let len = byte_len / size_of::<T>();
+        Ok(Self {
+            addr,
+            mask: len_mask(len),
+            capacity: len,
+            len,
+            backing: Some(backing),
+            #[cfg(target_os = "linux")]
+            arena: None,
+            #[cfg(target_family = "windows")]
+            file: None,
+            huge_page_len,
+            options: Some(options.clone()),
+            write_pos: 0,
+            read_pos: 0,
+            base_offset: 0,
+            _marker: PhantomData,
+        })
+    }
+
+    /// Creates an empty [`MagicBuffer`] without allocating any memory, e.g. as a
+    /// placeholder for a buffer that is allocated lazily.
+    ///
+    /// The empty buffer has no mapping. Its len and capacity are 0 and it derefs into
+    /// an empty slice, so any non-empty slice or index into it is out of bounds. It can
+    /// be replaced with an allocated buffer, or grown with [`MagicBuffer::resize`].
+    ///
+    /// ## Examples
+    /// ```rust
+    /// # use magic_buffer::MagicBuffer;
+    /// let mut buf = MagicBuffer::empty();
+    /// assert_eq!(0, buf.len());
+    /// assert!(buf[..].is_empty());
+    ///
+    /// buf.resize(MagicBuffer::min_len()).unwrap();
+    /// assert_eq!(MagicBuffer::min_len(), buf.len());
+    /// ```
+    pub fn empty() -> Self {
+        Self {
+            addr: ptr::null_mut(),
+            // a zero mask maps every offset to 0 without dividing by the len
+            mask: 0,
+            capacity: 0,
+            len: 0,
+            backing: None,
+            #[cfg(target_os = "linux")]
+            arena: None,
+            #[cfg(target_family = "windows")]
+            file: None,
+            huge_page_len: None,
+            options: None,
+            write_pos: 0,
+            read_pos: 0,
+            base_offset: 0,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Returns the size of the pages backing this [`MagicBuffer`].
+    ///
+    /// This is the huge page size if the buffer is backed by huge pages, see
+    /// [`MagicBufferOptions::huge_pages`], and [`MagicBuffer::min_len`] otherwise.
+    pub fn page_size(&self) -> usize {
+        self.huge_page_len.unwrap_or_else(MagicBuffer::min_len)
+    }
+
+    /// Returns the guaranteed alignment of the start of the buffer, i.e. of
+    /// `as_ptr(0)`, in bytes.
+    ///
+    /// The mapping is page aligned, so this is the [`MagicBuffer::page_size`] unless the
+    /// buffer has been rotated, which moves the start to the lowest set bit of the
+    /// rotation in bytes. Offsets that are multiples of the alignment of a type can be
+    /// cast to pointers to that type, e.g. for SIMD loads.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// # use magic_buffer::MagicBuffer;
+    /// let mut buf = MagicBuffer::new(MagicBuffer::min_len()).unwrap();
+    /// assert_eq!(0, buf.as_ptr(0) as usize % buf.alignment());
+    ///
+    /// buf.rotate_left(4);
+    /// assert_eq!(4, buf.alignment());
+    /// ```
+    pub fn alignment(&self) -> usize {
+        let offset = self.base_offset * size_of::<T>();
+        if offset == 0 {
+            return self.page_size();
+        }
+
+        (1 << offset.trailing_zeros()).min(self.page_size())
+    }
+
+    /// Writes modified pages of a file backed buffer to disk and waits for the writes to
+    /// complete, see [`MagicBuffer::from_file`].
+    ///
+    /// Both views share the same pages, so the `len` elements of the buffer are flushed
+    /// once. For buffers that are not backed by a file this is a cheap no-op.
+    ///
+    /// ## Errors
+    /// Will return an [`io::Error`] if the operating system fails to flush the pages.
+    pub fn flush(&self) -> io::Result<()> {
+        unsafe { magic_buf_flush(self.addr, self.capacity * size_of::<T>(), true) }?;
+
+        #[cfg(target_family = "windows")]
+        if let Some(file) = &self.file {
+            file.sync_data()?;
+        }
+
+        Ok(())
+    }
+
+    /// Starts writing modified pages of a file backed buffer to disk without waiting for
+    /// the writes to complete. See [`MagicBuffer::flush`].
+    ///
+    /// ## Errors
+    /// Will return an [`io::Error`] if the operating system fails to schedule the writes.
+    pub fn flush_async(&self) -> io::Result<()> {
+        unsafe { magic_buf_flush(self.addr, self.capacity * size_of::<T>(), false) }
+    }
+
+    /// Returns an element or a range of elements, or `None` if the index is out of
+    /// bounds. This is the non-panicking counterpart of indexing the buffer.
+    ///
+    /// Indices wrap around the buffer, so a single element is never out of bounds and
+    /// `get(usize)` always returns `Some`. Note that this differs from [`slice::get`]. A
+    /// [`Range`] is out of bounds if `start > end` or it is larger than the buffer.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// # use magic_buffer::MagicBuffer;
+    /// let len = MagicBuffer::min_len();
+    /// let mut buf = MagicBuffer::new(len).unwrap();
+    /// buf[0] = 1u8;
+    /// assert_eq!(Some(&1u8), buf.get(len));
+    /// assert_eq!(Some(&[0u8, 1u8][..]), buf.get(len - 1..len + 1));
+    /// assert_eq!(None, buf.get(0..len + 1));
+    /// ```
+    pub fn get<I: MagicBufferIndex<T>>(&self, index: I) -> Option<&I::Output> {
+        index.get(self)
+    }
+
+    /// Returns a mutable reference to an element or a range of elements, or `None` if
+    /// the index is out of bounds. See [`MagicBuffer::get`] for details.
+    pub fn get_mut<I: MagicBufferIndex<T>>(&mut self, index: I) -> Option<&mut I::Output> {
+        index.get_mut(self)
+    }
+
+    /// Returns the `len` elements of the buffer as a slice, starting at offset 0. This is
+    /// equivalent to `&*buf`, but makes the intent obvious where deref coercion doesn't
+    /// apply, e.g. in generic contexts.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// # use magic_buffer::MagicBuffer;
+    /// let buf = MagicBuffer::new(MagicBuffer::min_len()).unwrap();
+    /// assert_eq!(MagicBuffer::min_len(), buf.as_slice().len());
+    /// ```
+    pub fn as_slice(&self) -> &[T] {
+        unsafe { self.view_slice(self.view_offset(0), self.len) }
+    }
+
+    /// Returns the `len` elements of the buffer as a mutable slice, starting at offset 0.
+    /// This is equivalent to `&mut *buf`.
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        unsafe { self.view_slice_mut(self.view_offset(0), self.len) }
+    }
+
+    /// Splits the buffer at `mid` into the two disjoint mutable slices `[0, mid)` and
+    /// `[mid, len)`, e.g. for double buffering.
+    ///
+    /// Both slices lie within the first view of the mapping, so they never alias each
+    /// other even though the mirror maps the same memory again. This behaves like
+    /// `slice::split_at_mut` on the deref'd buffer.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// # use magic_buffer::MagicBuffer;
+    /// let len = MagicBuffer::min_len();
+    /// let mut buf = MagicBuffer::new(len).unwrap();
+    /// let (front, back) = buf.split_at_mut(len / 2);
+    /// front[0] = 1u8;
+    /// back[0] = 2u8;
+    /// assert_eq!(1u8, buf[0]);
+    /// assert_eq!(2u8, buf[len / 2]);
+    /// ```
+    ///
+    /// ## Panics
+    /// Will panic if `mid > len`.
+    pub fn split_at_mut(&mut self, mid: usize) -> (&mut [T], &mut [T]) {
+        if mid > self.len {
+            panic!("out of bounds")
+        }
+
+        if self.addr.is_null() {
+            return (&mut [], &mut []);
+        }
+
+        // SAFETY: [0, mid) and [mid, len) are disjoint ranges within one len wide window
+        // of the mapping, and the mutable borrow of `self` keeps any other access out for
+        // their lifetime
+        let base = self.view_offset(0);
+        let (front, back) = (self.view_ptr(base), self.view_ptr(base + mid));
+        self.mark_written(base, self.len);
+        unsafe {
+            (
+                &mut *(slice_from_raw_parts_mut(front, mid)),
+                &mut *(slice_from_raw_parts_mut(back, self.len - mid)),
+            )
+        }
+    }
+
+    /// Rotates the contents of the buffer in place, such that the element at `n` becomes
+    /// the first element. `n` wraps around the buffer like indices do.
+    ///
+    /// Unlike [`slice::rotate_left`], this doesn't move any elements. It only shifts the
+    /// offset in the mapping that index 0 refers to, which takes constant time. All
+    /// indexing, slicing and deref'ing of the buffer honors the rotation, and the mirror
+    /// keeps the rotated contents contiguous. Pointers and slices obtained before stay
+    /// valid, but refer to the old positions.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// # use magic_buffer::MagicBuffer;
+    /// let len = MagicBuffer::min_len();
+    /// let mut buf = MagicBuffer::new(len).unwrap();
+    /// buf[3] = b'a';
+    /// buf.rotate_left(3);
+    /// assert_eq!(b'a', buf[0]);
+    /// assert_eq!(len, buf[..].len());
+    /// ```
+    pub fn rotate_left(&mut self, n: usize) {
+        self.base_offset = self.view_offset(n);
+    }
+
+    /// Rotates the contents of the buffer in place, such that the first element moves to
+    /// `n`. See [`MagicBuffer::rotate_left`].
+    pub fn rotate_right(&mut self, n: usize) {
+        self.base_offset = self.fast_mod(self.base_offset + self.capacity - self.fast_mod(n));
+    }
+
+    /// Copies the `len` elements of the buffer into a new [`Vec`]. The mirror is the same
+    /// memory and is not copied.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// # use magic_buffer::MagicBuffer;
+    /// let mut buf = MagicBuffer::new(MagicBuffer::min_len()).unwrap();
+    /// buf[0] = 1u8;
+    /// let v = buf.to_vec();
+    /// assert_eq!(MagicBuffer::min_len(), v.len());
+    /// assert_eq!(1u8, v[0]);
+    /// ```
+    pub fn to_vec(&self) -> Vec<T> {
+        self.as_slice().to_vec()
+    }
+
+    /// Returns an iterator over the `len` elements of the buffer, starting at offset 0.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// # use magic_buffer::MagicBuffer;
+    /// let buf = MagicBuffer::new(MagicBuffer::min_len()).unwrap();
+    /// assert_eq!(MagicBuffer::min_len(), buf.iter().count());
+    /// ```
+    pub fn iter(&self) -> slice::Iter<'_, T> {
+        (**self).iter()
+    }
+
+    /// Returns a mutable iterator over the `len` elements of the buffer, starting at
+    /// offset 0.
+    pub fn iter_mut(&mut self) -> slice::IterMut<'_, T> {
+        (**self).iter_mut()
+    }
+
+    /// Returns the length of this [`MagicBuffer`] in elements, i.e. the number of elements
+    /// at the front of the buffer that hold meaningful data.
+    ///
+    /// This equals [`MagicBuffer::capacity`] for a new buffer, and only shrinks through
+    /// [`MagicBuffer::truncate`] or [`MagicBuffer::set_len`]. Deref'ing and slicing the
+    /// buffer only yields up to `len` elements, while offsets keep wrapping around the
+    /// full capacity.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the len of the buffer is 0, e.g. for a buffer created with
+    /// [`MagicBuffer::empty`].
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the capacity of this [`MagicBuffer`] in elements, i.e. the len of the
+    /// mapping that offsets wrap around.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// # use magic_buffer::MagicBuffer;
+    /// let len = MagicBuffer::min_len();
+    /// let mut buf = MagicBuffer::new(len).unwrap();
+    /// buf.truncate(10);
+    /// assert_eq!(10, buf.len());
+    /// assert_eq!(len, buf.capacity());
+    /// ```
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Forces the len of the buffer to `n` elements, without touching the contents.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// # use magic_buffer::MagicBuffer;
+    /// let len = MagicBuffer::min_len();
+    /// let mut buf = MagicBuffer::new(len).unwrap();
+    /// buf.truncate(0);
+    /// unsafe { buf.set_len(2) };
+    /// assert_eq!(b"\0\0", &buf[..]);
+    /// ```
+    ///
+    /// ## Safety
+    /// The elements up to `n` become visible through deref'ing and slicing the buffer
+    /// with whatever they last held, which may be stale data the caller considers
+    /// discarded. The caller must make sure that is acceptable.
+    ///
+    /// ## Panics
+    /// Will panic if `n` is larger than the capacity.
+    pub unsafe fn set_len(&mut self, n: usize) {
+        if n > self.capacity {
+            panic!("out of bounds")
+        }
+
+        self.len = n;
+    }
+
+    /// Shortens the buffer to `n` elements, like [`Vec::truncate`]. This has no effect if
+    /// `n` is greater than or equal to the current len. The contents and the capacity of
+    /// the buffer are left untouched.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// # use magic_buffer::MagicBuffer;
+    /// let mut buf = MagicBuffer::new(MagicBuffer::min_len()).unwrap();
+    /// buf.truncate(3);
+    /// assert_eq!(3, buf[..].len());
+    /// assert_eq!(2, buf[1..].len());
+    /// ```
+    pub fn truncate(&mut self, n: usize) {
+        self.len = self.len.min(n);
+    }
+
+    /// Hints the CPU to load the cache line holding the element at `offset` for reading,
+    /// e.g. a few iterations ahead of a consumer loop. The `offset` wraps around the
+    /// buffer like indices do. This uses `_mm_prefetch` on x86 and `prfm` on aarch64, and
+    /// does nothing on other architectures. Prefetching never faults and has no effect
+    /// on the contents of the buffer.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// # use magic_buffer::MagicBuffer;
+    /// let buf = MagicBuffer::new(MagicBuffer::min_len()).unwrap();
+    /// buf.prefetch(64);
+    /// ```
+    #[inline(always)]
+    pub fn prefetch(&self, offset: usize) {
+        prefetch(self.view_ptr(self.view_offset(offset)) as *const u8, false);
+    }
+
+    /// Hints the CPU to load the cache line holding the element at `offset` in
+    /// anticipation of a write. See [`MagicBuffer::prefetch`].
+    #[inline(always)]
+    pub fn prefetch_write(&self, offset: usize) {
+        prefetch(self.view_ptr(self.view_offset(offset)) as *const u8, true);
+    }
+
+    /// Returns an unsafe pointer to the [`MagicBuffer`]. The `offset` species the first
+    /// element the pointer points to. The pointer can be used to address up to `len` elements.
+    ///
+    /// The caller must ensure that the [`MagicBuffer`] outlives the pointer this function returns,
+    /// or else it will end up pointing to garbage.
+    ///
+    /// The caller must also ensure that the memory the pointer (non-transitively) points to is
+    /// never written to (except inside an UnsafeCell) using this pointer or any pointer derived
+    /// from it. If you need to mutate the contents of the slice, use [`as_mut_ptr`](MagicBuffer::as_mut_ptr).
+    ///
+    /// ## Examples
+    /// ```rust
+    /// # use magic_buffer::MagicBuffer;
+    /// let x = MagicBuffer::new(MagicBuffer::min_len()).unwrap();
+    /// let x_ptr = x.as_ptr(1);
+    ///
+    /// unsafe {
+    ///     for i in 0..x.len() {
+    ///         assert_eq!(*x_ptr.add(i), 0);
+    ///     }
+    /// }
+    /// ```
+    pub fn as_ptr(&self, offset: usize) -> *const T {
+        self.view_ptr(self.view_offset(offset)).cast_const()
+    }
+
+    /// Returns an unsafe mutable pointer to the [`MagicBuffer`]. The `offset` species the first
+    /// element the mutable pointer points to. The mutable pointer can be used to address up
+    /// to `len` elements.
+    ///
+    /// The caller must ensure that the [`MagicBuffer`] outlives the pointer this function returns,
+    /// or else it will end up pointing to garbage.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// # use magic_buffer::MagicBuffer;
+    /// let mut x = MagicBuffer::new(MagicBuffer::min_len()).unwrap();
+    /// let x_ptr = x.as_mut_ptr(1);
+    ///
+    /// unsafe {
+    ///     for i in 0..x.len() {
+    ///         *x_ptr.add(i) = (i % 256) as u8;
+    ///     }
+    /// }
+    /// ```
+    pub fn as_mut_ptr(&mut self, offset: usize) -> *mut T {
+        let offset = self.view_offset(offset);
+        let ptr = self.view_ptr(offset);
+        self.mark_written(offset, self.capacity);
+        ptr
+    }
+
+    /// Returns the two raw pointers spanning the `len` elements of the buffer, like
+    /// [`slice::as_ptr_range`]. This is handy for C APIs that take a `(begin, end)` pair.
+    ///
+    /// Elements up to `end` exclusive are valid. The mirrored mapping extends beyond
+    /// `end` for another `len` elements, which are the same memory as the range itself.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// # use magic_buffer::MagicBuffer;
+    /// let buf = MagicBuffer::new(MagicBuffer::min_len()).unwrap();
+    /// let range = buf.as_ptr_range();
+    /// assert_eq!(buf.len(), range.end as usize - range.start as usize);
+    /// ```
+    pub fn as_ptr_range(&self) -> Range<*const T> {
+        let base = self.view_offset(0);
+        self.view_ptr(base)..self.view_ptr(base + self.len)
+    }
+
+    /// Returns the two unsafe mutable pointers spanning the `len` elements of the buffer,
+    /// like [`slice::as_mut_ptr_range`]. See [`MagicBuffer::as_ptr_range`] for details.
+    pub fn as_mut_ptr_range(&mut self) -> Range<*mut T> {
+        let base = self.view_offset(0);
+        let range = self.view_ptr(base)..self.view_ptr(base + self.len);
+        self.mark_written(base, self.len);
+        range
+    }
+
+    /// Returns the maximum len of a contiguous slice, which is `len` unless the buffer
+    /// has been allocated with more than two mirrors.
+    #[inline(always)]
+    pub(crate) fn max_slice_len(&self) -> usize {
+        (self.mirror_count() - 1) * self.capacity
+    }
+
+    /// Returns whether the `len` elements starting at the logical `offset` are in bounds.
+    /// Offsets wrap around a full buffer, while the elements beyond the `len` of a
+    /// truncated buffer are out of bounds.
+    #[inline(always)]
+    pub(crate) fn in_bounds(&self, offset: usize, len: usize) -> bool {
+        if self.len == self.capacity {
+            len <= self.max_slice_len()
+        } else {
+            matches!(self.len.checked_sub(self.fast_mod(offset)), Some(max) if len <= max)
+        }
+    }
+
+    /// Returns the number of elements from the logical `offset` up to the end of the
+    /// buffer, which is `len` for a full buffer as offsets wrap around.
+    ///
+    /// ## Panics
+    /// Will panic if `offset` is beyond the `len` of a truncated buffer.
+    #[inline(always)]
+    fn len_from(&self, offset: usize) -> usize {
+        if self.len == self.capacity {
+            return self.len;
+        }
+
+        match self.len.checked_sub(self.fast_mod(offset)) {
+            Some(len) => len,
+            None => panic!("out of bounds"),
+        }
+    }
+
+    /// Converts the buffer into a [`MagicBuffer<MaybeUninit<T>>`] over the same mapping,
+    /// to fill it in place and track the initialized elements with
+    /// [`set_initialized_len`](MagicBuffer::set_initialized_len), like a [`Vec`] does
+    /// with its spare capacity. The initialized len starts at 0, and the positions and
+    /// rotation of the buffer are reset.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// # use magic_buffer::MagicBuffer;
+    /// let buf = MagicBuffer::<u32>::with_len(MagicBuffer::min_len() / 4).unwrap();
+    /// let mut buf = buf.into_uninit();
+    /// for (i, v) in buf.as_uninit_slice_mut()[..3].iter_mut().enumerate() {
+    ///     v.write(i as u32);
+    /// }
+    /// unsafe { buf.set_initialized_len(3) };
+    /// assert_eq!(&[0, 1, 2], buf.as_init_slice());
+    /// ```
+    pub fn into_uninit(self) -> MagicBuffer<MaybeUninit<T>> {
+        let mut buf = self.cast();
+        buf.len = 0;
+        buf
+    }
+
+    /// Converts the buffer into a [`MagicBuffer<U>`] over the same mapping, e.g. to serve
+    /// buffers of different element types from one pool without mapping new memory.
+    ///
+    /// The byte len of the mapping stays the same, so the capacity becomes
+    /// `capacity * size_of::<T>() / size_of::<U>()` elements. If `zero` is set, the
+    /// contents are zeroed, otherwise the previous bytes are kept and reinterpreted as
+    /// `U`, which [`Pod`] allows for any bit pattern. The len covers the whole capacity,
+    /// and the positions and rotation of the buffer are reset.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// # use magic_buffer::MagicBuffer;
+    /// let len = MagicBuffer::min_len();
+    /// let mut buf = MagicBuffer::new(len).unwrap();
+    /// buf.fill(1);
+    ///
+    /// let buf = buf.recycle_into::<u32>(false);
+    /// assert_eq!(len / 4, buf.capacity());
+    /// assert_eq!(0x0101_0101, buf[len / 4]);
+    ///
+    /// let buf = buf.recycle_into::<f32>(true);
+    /// assert_eq!(0.0f32, buf[0]);
+    /// ```
+    ///
+    /// ## Panics
+    /// Will panic if the byte len of the buffer is not a multiple of the size of `U`, or
+    /// if the alignment of `U` exceeds [`MagicBuffer::min_len`].
+    pub fn recycle_into<U: Pod>(self, zero: bool) -> MagicBuffer<U> {
+        let byte_len = self.capacity * size_of::<T>();
+        assert!(
+            byte_len.checked_rem(size_of::<U>()) == Some(0),
+            "len must be a multiple of the element size"
+        );
+        assert!(
+            std::mem::align_of::<U>() <= MagicBuffer::min_len(),
+            "element alignment must not exceed the page size"
+        );
+
+        if zero && byte_len != 0 {
+            unsafe {
+                ptr::write_bytes(self.view_ptr(0).cast::<u8>(), 0, byte_len);
+                magic_buf_sync_mirror(self.addr, byte_len, 0, byte_len);
+            }
+        }
+        self.cast()
+    }
+
+    /// Reinterprets the mapping as elements of type `U`, keeping its backing and its
+    /// options. The len covers the whole capacity, and the positions and rotation are
+    /// reset, since they can't be converted between element sizes in general. The
+    /// caller must check that the byte len is a multiple of the size of `U`.
+    fn cast<U>(self) -> MagicBuffer<U> {
+        let byte_len = self.capacity * size_of::<T>();
+        debug_assert_eq!(
+            Some(0),
+            byte_len.checked_rem(size_of::<U>()),
+            "len must be a multiple of the element size"
+        );
+        let capacity = byte_len / size_of::<U>();
+
+        // the fields are moved into the new buffer, which frees the mapping instead
+        let this = std::mem::ManuallyDrop::new(self);
+        unsafe {
+            MagicBuffer {
+                addr: this.addr,
+                capacity,
+                len: capacity,
+                // the empty buffer keeps its zero mask
+                mask: if capacity == 0 { 0 } else { len_mask(capacity) },
+                backing: ptr::read(&this.backing),
+                #[cfg(target_os = "linux")]
+                arena: ptr::read(&this.arena),
+                #[cfg(target_family = "windows")]
+                file: ptr::read(&this.file),
+                huge_page_len: this.huge_page_len,
+                options: ptr::read(&this.options),
+                write_pos: 0,
+                read_pos: 0,
+                base_offset: 0,
+                _marker: PhantomData,
+            }
+        }
+    }
+
+    /// Copies all `capacity` elements of `source` along with its len, positions and
+    /// rotation into `self`, which must have the same capacity.
+    fn copy_ring_from(&mut self, source: &Self) {
+        debug_assert_eq!(self.capacity, source.capacity, "capacities differ");
+        if self.capacity == 0 {
+            return;
+        }
+
+        unsafe {
+            ptr::copy_nonoverlapping(source.view_ptr(0), self.view_ptr(0), self.capacity);
+            let byte_len = self.capacity * size_of::<T>();
+            magic_buf_sync_mirror(self.addr, byte_len, 0, byte_len);
+        }
+        self.len = source.len;
+        self.write_pos = source.write_pos;
+        self.read_pos = source.read_pos;
+        self.base_offset = source.base_offset;
+    }
+}
+
+impl<T> MagicBuffer<T> {
+    /// Returns how many times the buffer is mapped back to back, see
+    /// [`MagicBufferOptions::mirror_count`]. This is 2 unless configured otherwise.
+    pub fn mirror_count(&self) -> usize {
+        self.options
+            .as_ref()
+            .map_or(2, |options| options.mirror_count)
+    }
+
+    /// Returns a pointer to the element at `offset` in either of the two views.
+    ///
+    /// The optimizer assumes that pointers derived from the same base pointer never
+    /// alias if their addresses differ, which does not hold for the two views of the
+    /// mapping. Every access to the buffer is therefore derived from the exposed address
+    /// of the mapping, and not from `addr` directly. The optimizer has to assume that
+    /// such pointers may alias any other access to the mapping, which keeps writes
+    /// through one view visible through the other.
+    ///
+    /// With the heap fallback, this first mirrors the region last handed out for writing.
+    #[inline(always)]
+    fn view_ptr(&self, offset: usize) -> *mut T {
+        unsafe { magic_buf_sync_written(self.addr, self.capacity * size_of::<T>()) };
+        (self.addr as usize + offset * size_of::<T>()) as *mut T
+    }
+
+    /// Records that the `len` elements at `offset` are handed out for writing, so that
+    /// the heap fallback can mirror them before the next access. Must be called after
+    /// the last [`view_ptr`](MagicBuffer::view_ptr) of the handout.
+    #[inline(always)]
+    fn mark_written(&mut self, offset: usize, len: usize) {
+        let size = size_of::<T>();
+        unsafe {
+            magic_buf_mark_written(self.addr, self.capacity * size, offset * size, len * size)
+        };
+    }
+
+    #[inline(always)]
+    unsafe fn view_slice(&self, offset: usize, len: usize) -> &[T] {
+        if self.addr.is_null() {
+            return &[];
+        }
+
+        debug_assert!(
+            offset + len <= self.mirror_count() * self.capacity,
+            "slice exceeds the mirrored mapping"
+        );
+        &*(slice_from_raw_parts(self.view_ptr(offset), len))
+    }
+
+    #[inline(always)]
+    unsafe fn view_slice_mut(&mut self, offset: usize, len: usize) -> &mut [T] {
+        if self.addr.is_null() {
+            return &mut [];
+        }
+
+        debug_assert!(
+            offset + len <= self.mirror_count() * self.capacity,
+            "slice exceeds the mirrored mapping"
+        );
+        let ptr = self.view_ptr(offset);
+        self.mark_written(offset, len);
+        &mut *(slice_from_raw_parts_mut(ptr, len))
+    }
+
+    #[inline(always)]
+    fn fast_mod(&self, v: usize) -> usize {
+        if self.mask != NO_MASK {
+            v & self.mask
+        } else {
+            v % self.capacity
+        }
+    }
+
+    /// Returns the offset in the first view that the logical `offset` maps to, taking
+    /// the rotation of the buffer into account.
+    #[inline(always)]
+    fn view_offset(&self, offset: usize) -> usize {
+        self.fast_mod(self.base_offset + self.fast_mod(offset))
+    }
+}
+
+impl<T: Pod> MagicBuffer<MaybeUninit<T>> {
+    /// Returns all `capacity` elements of the buffer, starting at the first one, to
+    /// initialize them in place. Elements written here become part of
+    /// [`as_init_slice`](MagicBuffer::as_init_slice) once they are covered by
+    /// [`set_initialized_len`](MagicBuffer::set_initialized_len).
+    pub fn as_uninit_slice_mut(&mut self) -> &mut [MaybeUninit<T>] {
+        unsafe { self.view_slice_mut(self.view_offset(0), self.capacity) }
+    }
+
+    /// Returns the number of elements at the front of the buffer that are initialized.
+    pub fn initialized_len(&self) -> usize {
+        self.len
+    }
+
+    /// Sets the number of elements at the front of the buffer that are initialized, like
+    /// [`Vec::set_len`]. The buffer is never reallocated, `n` is bounded by its capacity.
+    ///
+    /// ## Safety
+    /// The first `n` elements must be initialized, i.e. the caller must not have written
+    /// [`MaybeUninit::uninit`] into any of them through
+    /// [`as_uninit_slice_mut`](MagicBuffer::as_uninit_slice_mut). Elements that were
+    /// never written hold whatever the mapping held before, which is a valid `T` since
+    /// `T` is [`Pod`].
+    ///
+    /// ## Panics
+    /// Will panic if `n` is larger than the capacity.
+    pub unsafe fn set_initialized_len(&mut self, n: usize) {
+        if n > self.capacity {
+            panic!("out of bounds")
+        }
+
+        self.len = n;
+    }
+
+    /// Returns the first [`initialized_len`](MagicBuffer::initialized_len) elements of
+    /// the buffer.
+    pub fn as_init_slice(&self) -> &[T] {
+        // SAFETY: the caller of set_initialized_len guarantees these are initialized
+        unsafe {
+            let init = self.view_slice(self.view_offset(0), self.len);
+            &*(init as *const [MaybeUninit<T>] as *const [T])
+        }
+    }
+}
+
+/// Copies `len` bytes from `src` starting at `src_offset` into `dst` starting at
+/// `dst_offset`. Both ranges may wrap around the end of their buffers.
+///
+/// Unlike copying within one buffer, `src` and `dst` may have different lens, which
+/// allows to migrate data between a small and a large buffer, e.g. when resizing a ring.
+///
+/// ## Examples
+/// ```rust
+/// # use magic_buffer::{copy_ring_to_ring, MagicBuffer};
+/// let len = MagicBuffer::min_len();
+/// let mut src = MagicBuffer::new(len).unwrap();
+/// let mut dst = MagicBuffer::new(2 * len).unwrap();
+/// src.write_all_wrapping(len - 2, b"abcd");
+/// copy_ring_to_ring(&src, len - 2, &mut dst, 0, 4);
+/// assert_eq!(b"abcd", &dst[0..4]);
+/// ```
+///
+/// ## Panics
+/// Will panic if `len` is larger than either buffer.
+pub fn copy_ring_to_ring(
+    src: &MagicBuffer,
+    src_offset: usize,
+    dst: &mut MagicBuffer,
+    dst_offset: usize,
+    len: usize,
+) {
+    if len > src.capacity || len > dst.capacity {
+        panic!("out of bounds")
+    }
+
+    if len == 0 {
+        return;
+    }
+
+    let dst_offset = dst.view_offset(dst_offset);
+    unsafe {
+        ptr::copy_nonoverlapping(
+            src.view_ptr(src.view_offset(src_offset)),
+            dst.view_ptr(dst_offset),
+            len,
+        );
+        magic_buf_sync_mirror(dst.addr, dst.capacity, dst_offset, len);
+    }
+}
+
+/// Hashes a buffer address into an id that correlates allocation and free events,
+/// without leaking raw addresses into logs.
+#[cfg(feature = "tracing")]
+fn addr_id(addr: *const u8) -> u64 {
+    use std::{
+        collections::hash_map::RandomState,
+        hash::{BuildHasher, Hasher},
+        sync::OnceLock,
+    };
+
+    static STATE: OnceLock<RandomState> = OnceLock::new();
+    let mut hasher = STATE.get_or_init(RandomState::new).build_hasher();
+    hasher.write_usize(addr as usize);
+    hasher.finish()
+}
+
+impl<T> Drop for MagicBuffer<T> {
+    fn drop(&mut self) {
+        if self.addr.is_null() {
+            // the empty placeholder has no mapping to free
+            return;
+        }
+
+        let len = self.capacity * size_of::<T>();
+
+        #[cfg(feature = "tracing")]
+        tracing::trace!(len, id = addr_id(self.addr), "freeing magic buffer");
+
+        if matches!(&self.options, Some(options) if options.zero_on_drop) {
+            // both views share the same pages, so zeroing the first one is enough
+            for i in 0..len {
+                unsafe { ptr::write_volatile(self.addr.add(i), 0) };
+            }
+            atomic::compiler_fence(Ordering::SeqCst);
+        }
+
+        let mirrors = self
+            .options
+            .as_ref()
+            .map_or(2, |options| options.mirror_count);
+        let guard = matches!(&self.options, Some(options) if options.guard_page);
+        if matches!(&self.options, Some(options) if options.lock) {
+            // unmapping releases the lock as well, but be explicit about it
+            let _ = unsafe { magic_buf_unlock(self.addr, len * mirrors) };
+        }
+
+        #[cfg(target_os = "linux")]
+        if let Some(arena) = &self.arena {
+            unsafe { arena.free(self.addr, len) };
+            return;
+        }
+
+        unsafe { magic_buf_free(self.addr, len, mirrors, guard) }
+    }
+}
+
+/// The default buffer is [`MagicBuffer::empty`], which doesn't allocate.
+impl<T: Pod> Default for MagicBuffer<T> {
+    fn default() -> Self {
+        Self::empty()
+    }
+}
+
+/// Cloning allocates a new mapping of the same len and copies the contents over.
+///
+/// ## Panics
+/// Will panic if the allocation fails.
+impl<T: Pod> Clone for MagicBuffer<T> {
+    /// Allocates a new, independent mapping with the same options as `self`, e.g. the
+    /// same huge page size, and copies the contents over. The clone has the same len,
+    /// read and write positions and rotation as `self`.
+    ///
+    /// The clone always owns anonymous memory. Cloning a buffer that maps a file or a
+    /// shared object copies its contents into a private mapping, so writes to the clone
+    /// are not visible through the file or to other processes.
+    fn clone(&self) -> Self {
+        if self.addr.is_null() {
+            return Self::empty();
+        }
+
+        let buf = match &self.options {
+            Some(options) => {
+                // the clone can't be placed at the address of the original
+                let mut options = options.clone();
+                options.address_hint = None;
+                Self::from_options(&options)
+            }
+            None => Self::with_len(self.capacity),
+        };
+
+        let mut buf = buf.expect("failed to allocate magic buffer");
+        buf.copy_ring_from(self);
+        buf
+    }
+
+    /// Reuses the mapping of `self` if it has the same capacity and page size as
+    /// `source`, and only allocates a new one otherwise. A reused mapping keeps its
+    /// backing, e.g. the contents of `source` are written to the file `self` maps.
+    fn clone_from(&mut self, source: &Self) {
+        if self.capacity == source.capacity
+            && self.page_size() == source.page_size()
+            && self.mirror_count() == source.mirror_count()
+        {
+            self.copy_ring_from(source);
+        } else {
+            *self = source.clone();
+        }
+    }
+}
+
+/// Buffers are equal if their first `len` elements are equal. The mirrored region is
+/// the same memory, so it never affects the comparison.
+impl<T: Pod + PartialEq> PartialEq for MagicBuffer<T> {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+impl<T: Pod + Eq> Eq for MagicBuffer<T> {}
+
+/// Hashes the first `len` elements of the buffer, consistent with [`PartialEq`]. The
+/// mirrored region is the same memory and is not hashed again.
+///
+/// ## Examples
+/// ```rust
+/// # use magic_buffer::MagicBuffer;
+/// use std::collections::HashSet;
+///
+/// let a = MagicBuffer::new(MagicBuffer::min_len()).unwrap();
+/// let b = a.clone();
+/// let set: HashSet<_> = [a, b].into_iter().collect();
+/// assert_eq!(1, set.len());
+/// ```
+impl<T: Pod + Hash> Hash for MagicBuffer<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (**self).hash(state)
+    }
+}
+
+/// The number of bytes at the start and at the end of the buffer shown by `Debug`.
+const DEBUG_PREVIEW_LEN: usize = 8;
+
+/// Formats the buffer as its `len` and a hex preview of its first and last bytes. The
+/// address of the mapping is not included, so that it doesn't leak into logs.
+///
+/// ## Examples
+/// ```rust
+/// # use magic_buffer::MagicBuffer;
+/// let len = MagicBuffer::min_len();
+/// let mut buf = MagicBuffer::new(len).unwrap();
+/// buf[len - 1] = 0xfa;
+/// assert!(format!("{:?}", buf).ends_with(".., 00, 00, 00, 00, 00, 00, 00, fa] }"));
+/// ```
+impl<T> fmt::Debug for MagicBuffer<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        struct Preview<'a>(&'a [u8]);
+
+        impl fmt::Debug for Preview<'_> {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                let hex = |b: &u8| format!("{:02x}", b);
+                let mut list = f.debug_list();
+                if self.0.len() <= 2 * DEBUG_PREVIEW_LEN {
+                    list.entries(self.0.iter().map(hex).map(Raw));
+                } else {
+                    let (head, tail) = (
+                        &self.0[..DEBUG_PREVIEW_LEN],
+                        &self.0[self.0.len() - DEBUG_PREVIEW_LEN..],
+                    );
+                    list.entries(head.iter().map(hex).map(Raw))
+                        .entry(&Raw("..".to_string()))
+                        .entries(tail.iter().map(hex).map(Raw));
+                }
+                list.finish()
+            }
+        }
+
+        /// Prints a string without the quotes of its `Debug` representation.
+        struct Raw(String);
+
+        impl fmt::Debug for Raw {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str(&self.0)
+            }
+        }
+
+        let bytes = if self.addr.is_null() {
+            &[]
+        } else {
+            unsafe {
+                magic_buf_sync_written(self.addr, self.capacity * size_of::<T>());
+                let addr = self.addr.add(self.base_offset * size_of::<T>());
+                slice::from_raw_parts(addr, self.len * size_of::<T>())
+            }
+        };
+        f.debug_struct("MagicBuffer")
+            .field("len", &self.len)
+            .field("data", &Preview(bytes))
+            .finish()
+    }
+}
+
+/// Writes to the buffer start at [`MagicBuffer::write_pos`] and advance it.
+///
+/// Every write copies as much of the source as fits into the buffer in a single
+/// contiguous copy, wrapping around the end of the buffer through the mirrored mapping.
+/// Writes never block and may overwrite bytes that have not been read yet. Flushing is
+/// a no-op.
+///
+/// ## Examples
+/// ```rust
+/// # use magic_buffer::MagicBuffer;
+/// use std::io::Write;
+///
+/// let len = MagicBuffer::min_len();
+/// let mut buf = MagicBuffer::new(len).unwrap();
+/// buf.write_all(&vec![1u8; len - 2]).unwrap();
+/// buf.write_all(b"abcd").unwrap();
+/// assert_eq!(b"abcd", &buf[len - 2..len + 2]);
+/// assert_eq!(len + 2, buf.write_pos());
+/// ```
+impl io::Write for MagicBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = buf.len().min(self.capacity);
+        if len == 0 {
+            return Ok(0);
+        }
+
+        let start = self.view_offset(self.write_pos);
+        unsafe {
+            ptr::copy_nonoverlapping(buf.as_ptr(), self.view_ptr(start), len);
+            magic_buf_sync_mirror(self.addr, self.capacity, start, len);
+        }
+
+        self.write_pos = self.write_pos.wrapping_add(len);
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Reads from the buffer start at [`MagicBuffer::read_pos`] and advance it, up to
+/// [`MagicBuffer::write_pos`].
+///
+/// Every read copies as many of the available bytes as fit into the destination in a
+/// single contiguous copy. Reading returns `Ok(0)` once the read position caught up with
+/// the write position.
+///
+/// If more than `len` bytes were written since the last read, the writer has lapped the
+/// reader and the oldest bytes are lost. The read then fails with an
+/// [`io::ErrorKind::InvalidData`] error that wraps [`MagicBufferError::ReaderLapped`],
+/// and the read position skips ahead to the oldest byte still in the buffer, so that
+/// the next read continues from there.
+///
+/// ## Examples
+/// ```rust
+/// # use magic_buffer::MagicBuffer;
+/// use std::io::{Read, Write};
+///
+/// let mut buf = MagicBuffer::new(MagicBuffer::min_len()).unwrap();
+/// buf.write_all(b"hello").unwrap();
+///
+/// let mut out = String::new();
+/// buf.read_to_string(&mut out).unwrap();
+/// assert_eq!("hello", out);
+/// ```
+impl io::Read for MagicBuffer {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.write_pos.wrapping_sub(self.read_pos);
+        if available > self.capacity {
+            self.read_pos = self.write_pos.wrapping_sub(self.capacity);
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                MagicBufferError::ReaderLapped {
+                    lost: available - self.capacity,
+                },
+            ));
+        }
+
+        let len = buf.len().min(available);
+        if len == 0 {
+            return Ok(0);
+        }
+
+        unsafe {
+            ptr::copy_nonoverlapping(
+                self.view_ptr(self.view_offset(self.read_pos)),
+                buf.as_mut_ptr(),
+                len,
+            );
+        }
+
+        self.read_pos = self.read_pos.wrapping_add(len);
+        Ok(len)
+    }
+}
+
+/// Copies the contents of the buffer into a [`Vec`] and frees the mapping.
+impl<T: Pod> From<MagicBuffer<T>> for Vec<T> {
+    fn from(buf: MagicBuffer<T>) -> Self {
+        buf.to_vec()
+    }
+}
+
+/// Copies the contents of the buffer into a boxed slice and frees the mapping.
+impl<T: Pod> From<MagicBuffer<T>> for Box<[T]> {
+    fn from(buf: MagicBuffer<T>) -> Self {
+        Box::from(&*buf)
+    }
+}
+
+/// Allocates a buffer holding a copy of the bytes, see [`MagicBuffer::from_slice`]. The
+/// len is rounded up to the next valid len and the bytes after the input stay zeroed,
+/// so the input doesn't need to have a valid len itself.
+///
+/// ## Examples
+/// ```rust
+/// # use magic_buffer::{MagicBuffer, MagicBufferError};
+/// # fn main() -> Result<(), MagicBufferError> {
+/// let buf: MagicBuffer = b"abcd".as_slice().try_into()?;
+/// assert_eq!(MagicBuffer::min_len(), buf.len());
+/// # Ok(())
+/// # }
+/// ```
+impl TryFrom<&[u8]> for MagicBuffer {
+    type Error = MagicBufferError;
+
+    fn try_from(data: &[u8]) -> Result<Self, Self::Error> {
+        Self::from_slice(data)
+    }
+}
+
+/// Allocates a buffer holding a copy of the bytes, rounding the len up just like
+/// `TryFrom<&[u8]>`. The mapping can't take over the allocation of the [`Vec`], so the
+/// bytes are copied as well.
+impl TryFrom<Vec<u8>> for MagicBuffer {
+    type Error = MagicBufferError;
+
+    fn try_from(data: Vec<u8>) -> Result<Self, Self::Error> {
+        Self::from_slice(&data)
+    }
+}
+
+impl<T: Pod> Deref for MagicBuffer<T> {
+    type Target = [T];
+
+    fn deref(&self) -> &Self::Target {
+        unsafe { self.view_slice(self.view_offset(0), self.len) }
+    }
+}
+
+impl<T: Pod> DerefMut for MagicBuffer<T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        unsafe { self.view_slice_mut(self.view_offset(0), self.len) }
+    }
+}
+
+impl<T: Pod> AsRef<[T]> for MagicBuffer<T> {
+    fn as_ref(&self) -> &[T] {
+        self
+    }
+}
+
+impl<T: Pod> AsMut<[T]> for MagicBuffer<T> {
+    fn as_mut(&mut self) -> &mut [T] {
+        self
+    }
+}
+
+/// Iterates over the `len` elements of the buffer, not over the mirrored region.
+impl<'a, T: Pod> IntoIterator for &'a MagicBuffer<T> {
+    type Item = &'a T;
+    type IntoIter = slice::Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Iterates mutably over the `len` elements of the buffer, not over the mirrored region.
+impl<'a, T: Pod> IntoIterator for &'a mut MagicBuffer<T> {
+    type Item = &'a mut T;
+    type IntoIter = slice::IterMut<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
+
+impl<T: Pod> Index<usize> for MagicBuffer<T> {
+    type Output = T;
+
+    fn index(&self, index: usize) -> &Self::Output {
+        if self.fast_mod(index) >= self.len {
+            panic!("out of bounds")
+        }
+
+        unsafe { &*self.view_ptr(self.view_offset(index)) }
+    }
+}
+
+impl<T: Pod> IndexMut<usize> for MagicBuffer<T> {
+    fn index_mut(&mut self, index: usize) -> &mut Self::Output {
+        if self.fast_mod(index) >= self.len {
+            panic!("out of bounds")
+        }
+
+        let offset = self.view_offset(index);
+        let ptr = self.view_ptr(offset);
+        self.mark_written(offset, 1);
+        unsafe { &mut *ptr }
+    }
+}
+
+macro_rules! index_impl {
+    ($from:ty, $to:ty) => {
+        impl<T: Pod> Index<$from> for MagicBuffer<T> {
+            type Output = T;
+
+            fn index(&self, index: $from) -> &Self::Output {
+                &self[index as $to]
+            }
+        }
+
+        impl<T: Pod> IndexMut<$from> for MagicBuffer<T> {
+            fn index_mut(&mut self, index: $from) -> &mut Self::Output {
+                &mut self[index as $to]
+            }
+        }
+    };
+}
+
+index_impl!(i64, isize);
+index_impl!(i32, isize);
+index_impl!(i16, isize);
+index_impl!(i8, isize);
+
+index_impl!(u64, usize);
+index_impl!(u32, usize);
+index_impl!(u16, usize);
+index_impl!(u8, usize);
+
+impl<T: Pod> Index<isize> for MagicBuffer<T> {
+    type Output = T;
+
+    fn index(&self, index: isize) -> &Self::Output {
+        let index = if index < 0 {
+            self.capacity - self.fast_mod((-index) as usize)
+        } else {
+            self.fast_mod(index as usize)
+        };
+        &self[index]
+    }
+}
+
+impl<T: Pod> IndexMut<isize> for MagicBuffer<T> {
+    fn index_mut(&mut self, index: isize) -> &mut Self::Output {
+        let index = if index < 0 {
+            self.capacity - self.fast_mod((-index) as usize)
+        } else {
+            self.fast_mod(index as usize)
+        };
+        &mut self[index]
+    }
+}
+
+impl<T: Pod> Index<Range<usize>> for MagicBuffer<T> {
+    type Output = [T];
+
+    fn index(&self, index: Range<usize>) -> &Self::Output {
+        if index.start > index.end {
+            return &[];
+        }
+
+        let len = index.end - index.start;
+        if !self.in_bounds(index.start, len) {
+            panic!("out of bounds")
+        }
+
+        unsafe { self.view_slice(self.view_offset(index.start), len) }
+    }
+}
+
+impl<T: Pod> IndexMut<Range<usize>> for MagicBuffer<T> {
+    fn index_mut(&mut self, index: Range<usize>) -> &mut Self::Output {
+        if index.start > index.end {
+            return &mut [];
+        }
+
+        let len = index.end - index.start;
+        if !self.in_bounds(index.start, len) {
+            panic!("out of bounds")
+        }
+
+        unsafe { self.view_slice_mut(self.view_offset(index.start), len) }
+    }
+}
+
+impl<T: Pod> Index<RangeInclusive<usize>> for MagicBuffer<T> {
+    type Output = [T];
+
+    fn index(&self, index: RangeInclusive<usize>) -> &Self::Output {
+        let (start, end) = index.into_inner();
+        if start > end {
+            return &[];
+        }
+
+        let len = (end - start).saturating_add(1);
+        if !self.in_bounds(start, len) {
+            panic!("out of bounds")
+        }
+
+        unsafe { self.view_slice(self.view_offset(start), len) }
+    }
+}
+
+impl<T: Pod> IndexMut<RangeInclusive<usize>> for MagicBuffer<T> {
+    fn index_mut(&mut self, index: RangeInclusive<usize>) -> &mut Self::Output {
+        let (start, end) = index.into_inner();
+        if start > end {
+            return &mut [];
+        }
+
+        let len = (end - start).saturating_add(1);
+        if !self.in_bounds(start, len) {
+            panic!("out of bounds")
+        }
+
+        unsafe { self.view_slice_mut(self.view_offset(start), len) }
+    }
+}
+
+impl<T: Pod> Index<RangeTo<usize>> for MagicBuffer<T> {
+    type Output = [T];
+
+    fn index(&self, index: RangeTo<usize>) -> &Self::Output {
+        &self[0..index.end]
+    }
+}
+
+impl<T: Pod> IndexMut<RangeTo<usize>> for MagicBuffer<T> {
+    fn index_mut(&mut self, index: RangeTo<usize>) -> &mut Self::Output {
+        &mut self[0..index.end]
+    }
+}
+
+impl<T: Pod> Index<RangeFrom<usize>> for MagicBuffer<T> {
+    type Output = [T];
+
+    fn index(&self, index: RangeFrom<usize>) -> &Self::Output {
+        let len = self.len_from(index.start);
+        unsafe { self.view_slice(self.view_offset(index.start), len) }
+    }
+}
+
+impl<T: Pod> IndexMut<RangeFrom<usize>> for MagicBuffer<T> {
+    fn index_mut(&mut self, index: RangeFrom<usize>) -> &mut Self::Output {
+        let len = self.len_from(index.start);
+        unsafe { self.view_slice_mut(self.view_offset(index.start), len) }
+    }
+}
+
+impl<T: Pod> Index<RangeToInclusive<usize>> for MagicBuffer<T> {
+    type Output = [T];
+
+    fn index(&self, index: RangeToInclusive<usize>) -> &Self::Output {
+        &self[0..=index.end]
+    }
+}
+
+impl<T: Pod> IndexMut<RangeToInclusive<usize>> for MagicBuffer<T> {
+    fn index_mut(&mut self, index: RangeToInclusive<usize>) -> &mut Self::Output {
+        &mut self[0..=index.end]
+    }
+}
+
+impl<T: Pod> Index<RangeFull> for MagicBuffer<T> {
+    type Output = [T];
+
+    fn index(&self, _: RangeFull) -> &Self::Output {
+        unsafe { self.view_slice(self.view_offset(0), self.len) }
+    }
+}
+
+impl<T: Pod> IndexMut<RangeFull> for MagicBuffer<T> {
+    fn index_mut(&mut self, _: RangeFull) -> &mut Self::Output {
+        unsafe { self.view_slice_mut(self.view_offset(0), self.len) }
+    }
 }
 
-index_impl!(i64, isize);
-index_impl!(i32, isize);
-index_impl!(i16, isize);
-index_impl!(i8, isize);
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VALID_BUF_LEN: usize = 1 << 16;
+    const INVALID_BUF_LEN_ALIGN: usize = 1 << 8;
+    const INVALID_BUF_LEN_POW2: usize = (1 << 16) + 5;
+
+    #[test]
+    fn allocates_buffer() {
+        let buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        drop(buf);
+    }
+
+    #[test]
+    fn requires_power_of_two() {
+        MagicBuffer::new(INVALID_BUF_LEN_POW2)
+            .map_err(|e| {
+                println!("{}", e);
+                e
+            })
+            .expect_err("should not allocate buffer");
+    }
+
+    #[test]
+    fn requires_aligned_len() {
+        MagicBuffer::new(INVALID_BUF_LEN_ALIGN)
+            .map_err(|e| {
+                println!("{}", e);
+                e
+            })
+            .expect_err("should not allocate buffer");
+    }
+
+    #[test]
+    fn requires_len_below_max_len() {
+        let err =
+            MagicBuffer::new(MagicBuffer::max_len() << 1).expect_err("should not allocate buffer");
+        assert!(matches!(err, MagicBufferError::InvalidLen { .. }));
+    }
+
+    #[test]
+    fn typed_buffer_wrap_around() {
+        let len = VALID_BUF_LEN / 8;
+        let mut buf = MagicBuffer::<u64>::with_len(len).expect("should allocate buffer");
+        assert_eq!(len, buf.len());
+        assert_eq!(len, buf[..].len());
+
+        buf[len - 1] = u64::MAX;
+        buf[len] = 7;
+        assert_eq!(&[u64::MAX, 7], &buf[len - 1..len + 1]);
+        assert_eq!(7, buf[0usize]);
+        assert_eq!(u64::MAX, buf[-1isize]);
+
+        let v: Vec<u64> = buf.clone().into();
+        assert_eq!(len, v.len());
+        assert_eq!(7, v[0]);
+    }
+
+    #[test]
+    fn typed_buffer_requires_valid_len() {
+        let err = MagicBuffer::<[u8; 3]>::with_len(VALID_BUF_LEN)
+            .expect_err("should not allocate buffer");
+        assert!(matches!(err, MagicBufferError::InvalidLen { .. }));
+
+        MagicBuffer::<u32>::with_len(VALID_BUF_LEN / 4 + 1)
+            .expect_err("should not allocate buffer");
+        MagicBuffer::<u32>::with_len(usize::MAX).expect_err("should not allocate buffer");
+    }
+
+    #[test]
+    fn writes_are_visible_through_both_views() {
+        let mut buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        for i in 0..=255u8 {
+            let a = &mut buf[0..VALID_BUF_LEN];
+            a[0] = i;
+            let b = &buf[1..VALID_BUF_LEN + 1];
+            let c = &buf[VALID_BUF_LEN..2 * VALID_BUF_LEN];
+            assert_eq!(i, b[VALID_BUF_LEN - 1]);
+            assert_eq!(i, c[0]);
+        }
+    }
+
+    #[test]
+    fn typed_pointers_use_element_offsets() {
+        let mut buf =
+            MagicBuffer::<u32>::with_len(VALID_BUF_LEN / 4).expect("should allocate buffer");
+        buf[1usize] = 7;
+        assert_eq!(7, unsafe { *buf.as_ptr(VALID_BUF_LEN / 4 + 1) });
+        assert_eq!(buf.as_ptr(1), buf.as_mut_ptr(1).cast_const());
+    }
+
+    #[test]
+    fn writes_are_visible_wrap_around() {
+        let mut buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        buf[0] = b'a';
+        assert_eq!(buf[0], buf[VALID_BUF_LEN]);
+    }
+
+    #[test]
+    fn deref_as_slice() {
+        let buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        let slice: &[u8] = &buf;
+        assert_eq!(VALID_BUF_LEN, slice.len());
+    }
+
+    #[test]
+    fn deref_mut_as_slice() {
+        let mut buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        let slice: &mut [u8] = &mut buf;
+        assert_eq!(VALID_BUF_LEN, slice.len());
+    }
+
+    #[test]
+    fn closed_range() {
+        let buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        let slice = &buf[0..VALID_BUF_LEN];
+        assert_eq!(VALID_BUF_LEN, slice.len());
+    }
+
+    #[test]
+    fn closed_range_mut() {
+        let mut buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        let slice = &mut buf[0..VALID_BUF_LEN];
+        assert_eq!(VALID_BUF_LEN, slice.len());
+    }
+
+    #[test]
+    fn inclusive_range() {
+        let mut buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        buf[VALID_BUF_LEN - 4] = 1u8;
+        buf[4] = 2u8;
+
+        let slice = &buf[VALID_BUF_LEN - 4..=VALID_BUF_LEN + 4];
+        assert_eq!(9, slice.len());
+        assert_eq!(1u8, slice[0]);
+        assert_eq!(2u8, slice[8]);
+        assert_eq!(VALID_BUF_LEN, buf[0..=VALID_BUF_LEN - 1].len());
+
+        #[allow(clippy::reversed_empty_ranges)]
+        let slice = &buf[2..=1];
+        assert!(slice.is_empty());
+    }
+
+    #[test]
+    fn inclusive_range_mut() {
+        let mut buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        let slice = &mut buf[VALID_BUF_LEN - 4..=VALID_BUF_LEN + 4];
+        slice[8] = 1u8;
+        assert_eq!(1u8, buf[4]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn inclusive_range_out_of_bounds() {
+        let buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        let _ = &buf[0..=VALID_BUF_LEN];
+    }
+
+    #[test]
+    fn range_to() {
+        let buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        let slice = &buf[..VALID_BUF_LEN];
+        assert_eq!(VALID_BUF_LEN, slice.len());
+        assert_eq!(buf.as_ptr(0), slice.as_ptr());
+        assert_eq!(100, buf[..100].len());
+        assert!(buf[..0].is_empty());
+    }
+
+    #[test]
+    fn range_to_mut() {
+        let mut buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        let slice = &mut buf[..VALID_BUF_LEN];
+        assert_eq!(VALID_BUF_LEN, slice.len());
+        buf[..4].copy_from_slice(b"abcd");
+        assert_eq!(b'd', buf[3]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn range_to_out_of_bounds() {
+        let buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        let _ = &buf[..VALID_BUF_LEN + 1];
+    }
+
+    #[test]
+    fn range_from() {
+        let buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        let slice = &buf[1..];
+        assert_eq!(VALID_BUF_LEN, slice.len());
+    }
+
+    #[test]
+    fn range_from_mut() {
+        let mut buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        let slice = &mut buf[1..];
+        assert_eq!(VALID_BUF_LEN, slice.len());
+    }
+
+    #[test]
+    fn range_to_inclusive() {
+        let buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        let slice = &buf[..=VALID_BUF_LEN - 1];
+        assert_eq!(VALID_BUF_LEN, slice.len());
+        assert_eq!(1, buf[..=0].len());
+        assert_eq!(100, buf[..=99].len());
+    }
+
+    #[test]
+    fn range_to_inclusive_mut() {
+        let mut buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        let slice = &mut buf[..=VALID_BUF_LEN - 1];
+        assert_eq!(VALID_BUF_LEN, slice.len());
+        buf[..=1].copy_from_slice(b"ab");
+        assert_eq!(b'b', buf[1]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn range_to_inclusive_out_of_bounds() {
+        let buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        let _ = &buf[..=VALID_BUF_LEN];
+    }
+
+    #[test]
+    fn range_full() {
+        let buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        let slice = &buf[..];
+        assert_eq!(VALID_BUF_LEN, slice.len());
+    }
+
+    #[test]
+    fn range_full_mut() {
+        let mut buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        let slice = &mut buf[..];
+        assert_eq!(VALID_BUF_LEN, slice.len());
+    }
+
+    #[test]
+    fn index_wrap_around() {
+        let mut buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        buf[0] = b'1';
+        assert_eq!(b'1', buf[VALID_BUF_LEN]);
+    }
+
+    #[test]
+    fn split_around_gap() {
+        let mut buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        let (before, after) = buf.split_around(100..200);
+        assert_eq!(100, before.len());
+        assert_eq!(VALID_BUF_LEN - 200, after.len());
+        before.fill(b'a');
+        after.fill(b'b');
+        assert_eq!(b'a', buf[99]);
+        assert_eq!(0u8, buf[100]);
+        assert_eq!(0u8, buf[199]);
+        assert_eq!(b'b', buf[200]);
+    }
+
+    #[test]
+    fn split_around_wrapping_gap() {
+        let mut buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        let (before, after) = buf.split_around(VALID_BUF_LEN - 10..VALID_BUF_LEN + 10);
+        assert_eq!(VALID_BUF_LEN - 20, before.len());
+        assert!(after.is_empty());
+        before.fill(b'a');
+        assert_eq!(0u8, buf[9]);
+        assert_eq!(b'a', buf[10]);
+        assert_eq!(b'a', buf[VALID_BUF_LEN - 11]);
+        assert_eq!(0u8, buf[VALID_BUF_LEN - 10]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn split_around_oversized_gap() {
+        let mut buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        buf.split_around(0..VALID_BUF_LEN + 1);
+    }
+
+    #[cfg(feature = "rand")]
+    #[test]
+    fn fill_random() {
+        let mut buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        buf.fill_random(&mut rand::rngs::mock::StepRng::new(u64::MAX, 1));
+        assert!(buf.iter().any(|b| *b != 0));
+        assert_eq!(buf[0], buf[VALID_BUF_LEN]);
+    }
+
+    #[test]
+    fn move_keeps_mapping() {
+        let mut buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        buf[0] = b'a';
+        let ptr = buf.as_ptr(0);
+
+        let boxed = Box::new(buf);
+        assert_eq!(ptr, boxed.as_ptr(0));
+
+        let moved = std::thread::spawn(move || *boxed).join().unwrap();
+        assert_eq!(ptr, moved.as_ptr(0));
+        assert_eq!(b'a', unsafe { *ptr });
+        assert_eq!(b'a', unsafe { *ptr.add(VALID_BUF_LEN) });
+    }
+
+    #[test]
+    fn new_buffer_is_zeroed() {
+        let buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        assert!(buf.verify_zeroed());
+    }
+
+    #[test]
+    fn io_slice_wrap_around() {
+        use std::io::{Read, Write};
+
+        let mut buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        let data = (0..32u8).collect::<Vec<_>>();
+        let offset = VALID_BUF_LEN - 16;
+        let n = (&data[..])
+            .read_vectored(&mut [buf.as_io_slice_mut(offset, data.len())])
+            .unwrap();
+        assert_eq!(data.len(), n);
+
+        let mut single = Vec::new();
+        let n = single
+            .write_vectored(&[buf.as_io_slice(offset, data.len())])
+            .unwrap();
+        assert_eq!(data.len(), n);
+
+        let (head, tail) = (&buf[offset..VALID_BUF_LEN], &buf[0..16]);
+        let mut split = Vec::new();
+        let n = split
+            .write_vectored(&[IoSlice::new(head), IoSlice::new(tail)])
+            .unwrap();
+        assert_eq!(data.len(), n);
+        assert_eq!(split, single);
+        assert_eq!(data, single);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn dirty_pages() {
+        let mut buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        let page_len = MagicBuffer::min_len();
+        buf.reset_dirty_tracking()
+            .expect("should reset dirty tracking");
+        buf[VALID_BUF_LEN + page_len] = b'a';
+
+        let dirty = buf.dirty_pages().expect("should read dirty pages");
+        assert!(dirty
+            .iter()
+            .all(|o| *o < VALID_BUF_LEN && *o % page_len == 0));
+        // kernels without soft-dirty support never report any pages
+        if !dirty.is_empty() {
+            assert!(dirty.contains(&page_len));
+        }
+    }
+
+    #[test]
+    fn write_all_wrapping_keeps_tail() {
+        let mut buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        let src = (0..3 * VALID_BUF_LEN + 5)
+            .map(|i| (i % 251) as u8)
+            .collect::<Vec<_>>();
+
+        let end = buf.write_all_wrapping(7, &src);
+        assert_eq!((7 + src.len()) % VALID_BUF_LEN, end);
+
+        let tail = &src[src.len() - VALID_BUF_LEN..];
+        assert_eq!(tail, &buf[end..end + VALID_BUF_LEN]);
+    }
+
+    #[test]
+    fn write_all_wrapping_short_src() {
+        let mut buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        let end = buf.write_all_wrapping(VALID_BUF_LEN - 2, b"abcd");
+        assert_eq!(2, end);
+        assert_eq!(b"abcd", &buf[VALID_BUF_LEN - 2..VALID_BUF_LEN + 2]);
+        assert_eq!(b"cd", &buf[0..2]);
+    }
+
+    #[test]
+    fn copy_from_slice_at_wrap_around() {
+        let mut buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        buf.copy_from_slice_at(3 * VALID_BUF_LEN - 2, b"abcd");
+        assert_eq!(b"abcd", &buf[VALID_BUF_LEN - 2..VALID_BUF_LEN + 2]);
+        assert_eq!(b"cd", &buf[0..2]);
+    }
+
+    #[test]
+    fn try_copy_from_slice_at_out_of_bounds() {
+        let mut buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        let src = vec![1u8; VALID_BUF_LEN + 1];
+        let err = buf
+            .try_copy_from_slice_at(0, &src)
+            .expect_err("should not copy");
+        assert!(matches!(
+            err,
+            MagicBufferError::OutOfBounds {
+                len,
+                buf_len: VALID_BUF_LEN
+            } if len == VALID_BUF_LEN + 1
+        ));
+        assert!(buf.verify_zeroed());
+    }
+
+    #[test]
+    #[should_panic]
+    fn copy_from_slice_at_out_of_bounds() {
+        let mut buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        buf.copy_from_slice_at(0, &vec![1u8; VALID_BUF_LEN + 1]);
+    }
+
+    #[test]
+    fn reset_parallel_zeroes_buffer() {
+        let mut buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        for threads in [0, 1, 3, 64] {
+            buf.fill(1);
+            buf.reset_parallel(threads);
+            assert!(buf.verify_zeroed());
+        }
+    }
+
+    #[test]
+    fn reset_parallel_zeroes_rotated_buffer() {
+        let mut buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        buf.rotate_left(3);
+        buf.truncate(VALID_BUF_LEN / 2);
+        buf.fill(1);
+        buf.reset_parallel(3);
+        let mut out = vec![1u8; VALID_BUF_LEN];
+        buf.read_at(0, &mut out);
+        assert!(out.iter().all(|b| *b == 0));
+    }
+
+    #[test]
+    #[allow(clippy::reversed_empty_ranges)]
+    fn get_range_checks_bounds() {
+        let mut buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        buf.get_range_mut(VALID_BUF_LEN - 2..VALID_BUF_LEN + 2)
+            .expect("should be in bounds")
+            .copy_from_slice(b"abcd");
+
+        assert_eq!(Some(&b"cd"[..]), buf.get_range(0..2));
+        assert_eq!(Some(&b""[..]), buf.get_range(5..5));
+        assert_eq!(None, buf.get_range(5..4));
+        assert_eq!(None, buf.get_range(0..VALID_BUF_LEN + 1));
+        assert!(buf.get_range_mut(0..VALID_BUF_LEN + 1).is_none());
+    }
+
+    #[test]
+    fn copy_ring_to_ring_across_wrap_points() {
+        let mut small = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        let mut large = MagicBuffer::new(2 * VALID_BUF_LEN).expect("should allocate buffer");
+        small.write_all_wrapping(VALID_BUF_LEN - 2, b"abcd");
+
+        copy_ring_to_ring(
+            &small,
+            VALID_BUF_LEN - 2,
+            &mut large,
+            2 * VALID_BUF_LEN - 1,
+            4,
+        );
+        assert_eq!(b"a", &large[2 * VALID_BUF_LEN - 1..2 * VALID_BUF_LEN]);
+        assert_eq!(b"bcd", &large[0..3]);
+
+        small.fill(0);
+        copy_ring_to_ring(
+            &large,
+            2 * VALID_BUF_LEN - 1,
+            &mut small,
+            VALID_BUF_LEN - 3,
+            4,
+        );
+        assert_eq!(b"abc", &small[VALID_BUF_LEN - 3..VALID_BUF_LEN]);
+        assert_eq!(b"d", &small[0..1]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn copy_ring_to_ring_out_of_bounds() {
+        let small = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        let mut large = MagicBuffer::new(2 * VALID_BUF_LEN).expect("should allocate buffer");
+        copy_ring_to_ring(&small, 0, &mut large, 0, VALID_BUF_LEN + 1);
+    }
+
+    #[test]
+    fn fill_incrementing_pattern() {
+        let mut buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        buf.fill_incrementing(250);
+        for i in 0..2 * VALID_BUF_LEN {
+            assert_eq!(250u8.wrapping_add(i as u8), buf[i]);
+        }
+    }
+
+    #[test]
+    fn validate_after_operations() {
+        let mut buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        assert!(buf.validate());
+        buf.fill_incrementing(1);
+        assert!(buf.validate());
+        buf.shrink_to_fit_if_idle(0);
+        assert!(buf.validate());
+    }
+
+    #[test]
+    fn write_across_wrap_boundary() {
+        use std::io::Write;
+
+        let mut buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        assert_eq!(
+            VALID_BUF_LEN - 3,
+            buf.write(&vec![0u8; VALID_BUF_LEN - 3]).unwrap()
+        );
+        assert_eq!(6, buf.write(b"abcdef").unwrap());
+        assert_eq!(VALID_BUF_LEN + 3, buf.write_pos());
+
+        assert_eq!(b"abcdef", &buf[VALID_BUF_LEN - 3..VALID_BUF_LEN + 3]);
+        assert_eq!(
+            &buf[VALID_BUF_LEN..VALID_BUF_LEN + 3],
+            &buf[2 * VALID_BUF_LEN..2 * VALID_BUF_LEN + 3]
+        );
+        assert_eq!(b"def", &buf[0..3]);
+
+        let src = vec![1u8; VALID_BUF_LEN + 1];
+        assert_eq!(VALID_BUF_LEN, buf.write(&src).unwrap());
+        buf.flush().unwrap();
+    }
+
+    #[test]
+    fn read_across_wrap_boundary() {
+        use std::io::{Read, Write};
+
+        let mut buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        let mut out = vec![0u8; VALID_BUF_LEN];
+        buf.write_all(&vec![1u8; VALID_BUF_LEN - 3]).unwrap();
+        assert_eq!(VALID_BUF_LEN - 3, buf.read(&mut out).unwrap());
+
+        buf.write_all(b"abcdef").unwrap();
+        assert_eq!(4, buf.read(&mut out[..4]).unwrap());
+        assert_eq!(b"abcd", &out[..4]);
+        assert_eq!(2, buf.read(&mut out).unwrap());
+        assert_eq!(b"ef", &out[..2]);
+        assert_eq!(0, buf.read(&mut out).unwrap());
+        assert_eq!(buf.write_pos(), buf.read_pos());
+    }
+
+    #[test]
+    fn read_reports_lapped_reader() {
+        use std::io::{Read, Write};
+
+        let mut buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        buf.write_all(&vec![1u8; VALID_BUF_LEN]).unwrap();
+        buf.write_all(b"abc").unwrap();
+
+        let mut out = vec![0u8; VALID_BUF_LEN];
+        let err = buf.read(&mut out).expect_err("should detect lapped reader");
+        assert_eq!(std::io::ErrorKind::InvalidData, err.kind());
+        let err = err.into_inner().unwrap();
+        assert!(matches!(
+            err.downcast_ref::<MagicBufferError>(),
+            Some(MagicBufferError::ReaderLapped { lost: 3 })
+        ));
+
+        assert_eq!(VALID_BUF_LEN, buf.read(&mut out).unwrap());
+        assert_eq!(b"abc", &out[VALID_BUF_LEN - 3..]);
+    }
+
+    #[test]
+    fn read_array_wrap_around() {
+        let mut buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        buf.write_all_wrapping(VALID_BUF_LEN - 3, b"abcdef");
+        assert_eq!(*b"abcdef", buf.read_array::<6>(VALID_BUF_LEN - 3));
+        assert_eq!(*b"def", buf.read_array::<3>(2 * VALID_BUF_LEN));
+    }
+
+    #[test]
+    #[should_panic]
+    fn read_array_too_large() {
+        let buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        buf.read_array::<{ VALID_BUF_LEN + 1 }>(0);
+    }
+
+    #[test]
+    fn as_mut_bytes_generic() {
+        fn zero(buf: &mut impl AsMut<[u8]>) {
+            buf.as_mut().fill(0);
+        }
+
+        let mut buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        buf.as_mut_bytes().fill(1);
+        assert_eq!(VALID_BUF_LEN, buf.as_bytes().len());
+        assert!(!buf.verify_zeroed());
+        zero(&mut buf);
+        assert!(buf.verify_zeroed());
+    }
+
+    #[test]
+    fn next_valid_len_is_valid() {
+        let min_len = MagicBuffer::min_len();
+        assert_eq!(min_len, MagicBuffer::next_valid_len(0));
+        assert_eq!(min_len, MagicBuffer::next_valid_len(min_len));
+        assert_eq!(2 * min_len, MagicBuffer::next_valid_len(min_len + 1));
+        assert_eq!(
+            MagicBuffer::max_len(),
+            MagicBuffer::next_valid_len(MagicBuffer::max_len())
+        );
+        for requested in [1, 1000, 3 * min_len, VALID_BUF_LEN + 1] {
+            let len = MagicBuffer::next_valid_len(requested);
+            assert!(len >= requested);
+            assert!(MagicBuffer::validate_len(len).is_ok());
+        }
+    }
+
+    #[test]
+    fn from_slice_round_trip() {
+        let data: Vec<u8> = (0..MagicBuffer::min_len() + 3).map(|i| i as u8).collect();
+        let buf = MagicBuffer::from_slice(&data).expect("should allocate buffer");
+        assert_eq!(2 * MagicBuffer::min_len(), buf.len());
+        assert_eq!(&data[..], &buf[0..data.len()]);
+        assert!(buf[data.len()..buf.len()].iter().all(|b| *b == 0));
+    }
+
+    #[test]
+    #[should_panic]
+    fn copy_from_slice_too_large() {
+        let mut buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        buf.copy_from_slice(&vec![0u8; VALID_BUF_LEN + 1]);
+    }
+
+    #[test]
+    fn get_out_of_bounds() {
+        let mut buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        *buf.get_mut(VALID_BUF_LEN + 1).expect("should be in bounds") = 1u8;
+        assert_eq!(Some(&1u8), buf.get(1));
+
+        let range = buf.get_mut(VALID_BUF_LEN - 1..VALID_BUF_LEN + 1);
+        range.expect("should be in bounds")[0] = 2u8;
+        assert_eq!(
+            Some(&[2u8, 0u8][..]),
+            buf.get(VALID_BUF_LEN - 1..VALID_BUF_LEN + 1)
+        );
 
-index_impl!(u64, usize);
-index_impl!(u32, usize);
-index_impl!(u16, usize);
-index_impl!(u8, usize);
+        assert!(buf.get(0..VALID_BUF_LEN + 1).is_none());
+        assert!(buf.get_mut(0..VALID_BUF_LEN + 1).is_none());
+        #[allow(clippy::reversed_empty_ranges)]
+        let reversed = 2..1;
+        assert!(buf.get(reversed).is_none());
+    }
+
+    #[test]
+    fn write_at_wraps() {
+        let mut buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        buf.write_at(VALID_BUF_LEN - 3, b"0123456789");
+        assert_eq!(b"012", &buf[VALID_BUF_LEN - 3..VALID_BUF_LEN]);
+        assert_eq!(b"3456789", &buf[0..7]);
+
+        let mut dst = [0u8; 10];
+        buf.read_at(5 * VALID_BUF_LEN - 3, &mut dst);
+        assert_eq!(b"0123456789", &dst);
+    }
+
+    #[test]
+    #[should_panic]
+    fn read_at_too_large() {
+        let buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        buf.read_at(0, &mut vec![0u8; VALID_BUF_LEN + 1]);
+    }
+
+    #[test]
+    fn copy_within_overlapping_wrap() {
+        fn check(src: usize, dst: usize, len: usize) {
+            let mut buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+            buf.fill_incrementing(0);
+            let mut expected = buf.to_vec();
+            let tmp: Vec<u8> = (0..len)
+                .map(|i| expected[(src + i) % VALID_BUF_LEN])
+                .collect();
+            for (i, b) in tmp.into_iter().enumerate() {
+                expected[(dst + i) % VALID_BUF_LEN] = b;
+            }
+
+            buf.copy_within(src, dst, len);
+            assert_eq!(
+                &expected[..],
+                &buf[0..VALID_BUF_LEN],
+                "{src} -> {dst}, {len}"
+            );
+        }
+
+        let len = VALID_BUF_LEN;
+        // forward and backward overlap across the wrap point
+        check(len - 10, len - 5, 20);
+        check(len - 5, len - 10, 20);
+        // regions that look disjoint in the first view but overlap in the ring
+        check(len - 4, 2 * len - 2, 8);
+        check(2, len - 2, 8);
+        // the destination overlaps both ends of the source
+        check(0, len / 2, len - 2);
+        check(10, 0, len);
+        // disjoint and identical regions
+        check(len - 4, 100, 8);
+        check(7, 7 + len, 100);
+    }
+
+    #[test]
+    fn ioslice_across_wrap() {
+        let mut buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        let mut slice = buf.writable_ioslice_mut(3 * VALID_BUF_LEN - 3, 6);
+        slice.copy_from_slice(b"abcdef");
+
+        let slice = buf.readable_ioslice(VALID_BUF_LEN - 3, VALID_BUF_LEN + 3);
+        assert_eq!(6, slice.len());
+        assert_eq!(b'a', slice[0]);
+        assert_eq!(b'f', slice[5]);
+        assert_eq!(
+            VALID_BUF_LEN,
+            buf.readable_ioslice(5, VALID_BUF_LEN + 5).len()
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn readable_ioslice_lapped() {
+        let buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        buf.readable_ioslice(0, VALID_BUF_LEN + 1);
+    }
+
+    #[test]
+    fn iterates_len_elements() {
+        let mut buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        buf.fill(1);
+        for b in &mut buf {
+            *b += 1;
+        }
+
+        let sum: usize = (&buf).into_iter().map(|b| *b as usize).sum();
+        assert_eq!(2 * VALID_BUF_LEN, sum);
+        assert_eq!(VALID_BUF_LEN, buf.iter().count());
+        assert_eq!(VALID_BUF_LEN, buf.iter_mut().count());
+    }
+
+    #[test]
+    fn fill_and_clear() {
+        let mut buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        buf.fill(0xAB);
+        assert_eq!(0xABu8, buf[0]);
+        assert_eq!(0xABu8, buf[VALID_BUF_LEN]);
+        assert!(buf.iter().all(|b| *b == 0xAB));
+
+        buf.clear();
+        assert_eq!(0u8, buf[VALID_BUF_LEN - 1]);
+        assert!(buf.verify_zeroed());
+    }
+
+    #[test]
+    fn oom_displays_os_error() {
+        let err = MagicBufferError::OOM { code: Some(12) };
+        assert_eq!("out of memory, os error 12", err.to_string());
+        let err = MagicBufferError::OOM { code: None };
+        assert_eq!("out of memory", err.to_string());
+    }
+
+    #[test]
+    fn ptr_range_covers_len() {
+        let mut buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        let range = buf.as_mut_ptr_range();
+        unsafe { *range.end.sub(1) = 1u8 };
+        assert_eq!(1u8, buf[VALID_BUF_LEN - 1]);
+
+        let range = buf.as_ptr_range();
+        assert_eq!(buf.as_ptr(0), range.start);
+        assert_eq!(VALID_BUF_LEN, range.end as usize - range.start as usize);
+    }
+
+    #[test]
+    fn clone_copies_contents() {
+        let mut buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        buf.write_all_wrapping(VALID_BUF_LEN - 2, b"abcd");
+
+        let clone = buf.clone();
+        assert_ne!(buf.as_ptr(0), clone.as_ptr(0));
+        assert_eq!(b"abcd", &clone[VALID_BUF_LEN - 2..VALID_BUF_LEN + 2]);
+    }
+
+    #[test]
+    #[allow(clippy::mutable_key_type)] // the arena handle is not part of the hash
+    fn equal_buffers_hash_equally() {
+        let mut a = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        a.write_all_wrapping(VALID_BUF_LEN - 2, b"abcd");
+        let mut b = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        b.copy_from_slice(&a);
+        assert!(a == b);
+
+        let mut set = std::collections::HashSet::new();
+        set.insert(a);
+        set.insert(b);
+        assert_eq!(1, set.len());
+
+        let c = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        assert!(!set.contains(&c));
+        set.insert(c);
+        assert_eq!(2, set.len());
+    }
+
+    #[test]
+    fn clone_is_independent() {
+        let mut buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        buf[0] = 1u8;
+
+        let mut clone = buf.clone();
+        clone[0] = 2u8;
+        buf[1] = 3u8;
+        assert_eq!(1u8, buf[0]);
+        assert_eq!(2u8, clone[0]);
+        assert_eq!(0u8, clone[1]);
+    }
+
+    #[test]
+    fn clone_keeps_page_size() {
+        const LEN: usize = 2 << 20;
+        let buf = MagicBufferOptions::new(LEN)
+            .huge_pages(HugePageSize::Size2MiB)
+            .build()
+            .expect("should allocate buffer");
+
+        let clone = buf.clone();
+        assert_eq!(buf.page_size(), clone.page_size());
+        assert_eq!(0, clone.as_ptr(0) as usize & (clone.page_size() - 1));
+    }
+
+    #[test]
+    fn clone_from_reuses_mapping() {
+        let mut src = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        src[0] = 1u8;
+
+        let mut dst = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        let ptr = dst.as_ptr(0);
+        dst.clone_from(&src);
+        assert_eq!(ptr, dst.as_ptr(0));
+        assert_eq!(1u8, dst[0]);
+
+        let mut dst = MagicBuffer::new(2 * VALID_BUF_LEN).expect("should allocate buffer");
+        dst.clone_from(&src);
+        assert_eq!(VALID_BUF_LEN, dst.len());
+        assert_eq!(1u8, dst[0]);
+    }
+
+    #[test]
+    fn clone_keeps_positions() {
+        use std::io::{Read, Write};
+
+        let mut src = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        src.rotate_left(3);
+        src.write_all(b"abcd").expect("should write");
+        src.read_exact(&mut [0u8; 2]).expect("should read");
+        src.truncate(VALID_BUF_LEN - 1);
+
+        let assert_same = |dst: &MagicBuffer| {
+            assert_eq!(src.len(), dst.len());
+            assert_eq!(src.write_pos(), dst.write_pos());
+            assert_eq!(src.read_pos(), dst.read_pos());
+            assert_eq!(src[..], dst[..]);
+            assert_eq!(src[VALID_BUF_LEN - 3], dst[VALID_BUF_LEN - 3]);
+        };
+        assert_same(&src.clone());
+
+        let mut dst = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        dst.rotate_left(5);
+        dst.clone_from(&src);
+        assert_same(&dst);
+    }
+
+    #[test]
+    fn into_vec_and_boxed_slice() {
+        let mut buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        buf.write_all_wrapping(VALID_BUF_LEN - 2, b"abcd");
+
+        let v: Vec<u8> = buf.clone().into();
+        assert_eq!(VALID_BUF_LEN, v.len());
+        assert_eq!(b"cd", &v[0..2]);
+        assert_eq!(b"ab", &v[VALID_BUF_LEN - 2..]);
+
+        let b: Box<[u8]> = buf.into();
+        assert_eq!(v, b.into_vec());
+    }
+
+    #[test]
+    fn send_mut_ptr_to_thread() {
+        let mut buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        let ptr = buf.as_send_mut_ptr(VALID_BUF_LEN - 1);
+        std::thread::spawn(move || unsafe {
+            *ptr.get() = 1;
+            *ptr.get().add(1) = 2;
+        })
+        .join()
+        .unwrap();
+
+        assert_eq!(1u8, buf[VALID_BUF_LEN - 1]);
+        assert_eq!(2u8, buf[0]);
+        assert_eq!(buf.as_ptr(0), buf.as_send_ptr(VALID_BUF_LEN).get());
+    }
+
+    #[test]
+    fn leak_keeps_mapping() {
+        let mut buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        buf[VALID_BUF_LEN] = 1u8;
+
+        let bytes = buf.leak();
+        assert_eq!(VALID_BUF_LEN, bytes.len());
+        assert_eq!(1, bytes[0]);
+        bytes[1] = 2;
+        assert_eq!(2, bytes[1]);
+    }
+
+    #[test]
+    fn split_at_mut_wrapping_disjoint() {
+        let mut buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        buf.rotate_left(VALID_BUF_LEN - 50);
+        let (left, right) = buf.split_at_mut_wrapping(100);
+        assert_eq!(100, left.len());
+        assert_eq!(VALID_BUF_LEN - 100, right.len());
+
+        let left_range = left.as_ptr_range();
+        let right_range = right.as_ptr_range();
+        assert!(left_range.end <= right_range.start);
+
+        left.fill(1);
+        right.fill(2);
+        assert!(buf[0..100].iter().all(|b| *b == 1));
+        assert!(buf[100..VALID_BUF_LEN].iter().all(|b| *b == 2));
+    }
+
+    #[test]
+    fn chunks_mut_wrapping_disjoint() {
+        let mut buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        let chunks = buf.chunks_mut_wrapping(1024).collect::<Vec<_>>();
+        assert_eq!(VALID_BUF_LEN / 1024, chunks.len());
+        for pair in chunks.windows(2) {
+            assert!(pair[0].as_ptr_range().end <= pair[1].as_ptr_range().start);
+        }
+
+        for (i, chunk) in chunks.into_iter().enumerate() {
+            chunk.fill(i as u8);
+        }
+        assert_eq!(63u8, buf[VALID_BUF_LEN - 1]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn chunks_mut_wrapping_requires_divisor() {
+        let mut buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        let _ = buf.chunks_mut_wrapping(3);
+    }
+
+    #[test]
+    fn checksum_wrap_around() {
+        let mut buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        let offset = buf.write_all_wrapping(VALID_BUF_LEN - 4, b"12345678");
+        let sum = buf.checksum(VALID_BUF_LEN - 4, 8, 0, |acc, b| {
+            acc * 10 + (b - b'0') as u32
+        });
+        assert_eq!(4, offset);
+        assert_eq!(12345678, sum);
+    }
+
+    #[cfg(feature = "checksum")]
+    #[test]
+    fn crc32_wrap_around() {
+        let mut buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        buf.write_all_wrapping(VALID_BUF_LEN - 4, b"123456789");
+        assert_eq!(0xCBF4_3926, buf.crc32(VALID_BUF_LEN - 4, 9));
+        assert_eq!(0xCBF4_3926, buf.crc32(2 * VALID_BUF_LEN - 4, 9));
+    }
+
+    #[cfg(feature = "checksum")]
+    #[test]
+    fn adler32_wrap_around() {
+        let mut buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        buf.write_all_wrapping(VALID_BUF_LEN - 4, b"Wikipedia");
+        assert_eq!(0x11E6_0398, buf.adler32(VALID_BUF_LEN - 4, 9));
+    }
+
+    #[test]
+    fn shrink_keeps_live_data() {
+        let mut buf = MagicBuffer::new(4 * VALID_BUF_LEN).expect("should allocate buffer");
+        buf.write_all_wrapping(0, b"live");
+        assert!(buf.shrink_to_fit_if_idle(VALID_BUF_LEN));
+        assert_eq!(VALID_BUF_LEN, buf.len());
+        assert_eq!(b"live", &buf[0..4]);
+        assert_eq!(b"live", &buf[VALID_BUF_LEN..VALID_BUF_LEN + 4]);
+    }
+
+    #[test]
+    fn shrink_is_noop_when_full() {
+        let mut buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        let ptr = buf.as_ptr(0);
+        assert!(!buf.shrink_to_fit_if_idle(VALID_BUF_LEN));
+        assert_eq!(VALID_BUF_LEN, buf.len());
+        assert_eq!(ptr, buf.as_ptr(0));
+        assert!(!buf.shrink_to_fit_if_idle(usize::MAX));
+        assert_eq!(ptr, buf.as_ptr(0));
+    }
+
+    #[test]
+    fn shrink_keeps_exact_capacity() {
+        let min_len = MagicBuffer::min_len();
+        let mut buf =
+            MagicBuffer::with_exact_capacity(5 * min_len).expect("should allocate buffer");
+        buf.write_all_wrapping(0, b"live");
+        assert!(buf.shrink_to_fit_if_idle(2 * min_len + 1));
+        assert_eq!(3 * min_len, buf.capacity());
+        assert_eq!(b"live", &buf[3 * min_len..3 * min_len + 4]);
+    }
+
+    #[test]
+    fn shrink_keeps_file_backing() {
+        let path = std::env::temp_dir().join(format!("magic_buffer_shrink_{}", std::process::id()));
+        let mut buf = MagicBuffer::from_file(&path, 4 * VALID_BUF_LEN).expect("should map file");
+        let ptr = buf.as_ptr(0);
+        assert!(!buf.shrink_to_fit_if_idle(VALID_BUF_LEN));
+        assert_eq!(ptr, buf.as_ptr(0));
+        assert_eq!(4 * VALID_BUF_LEN, buf.capacity());
+        drop(buf);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn from_fd_with_offset() {
+        use std::{fs::OpenOptions, os::unix::fs::FileExt};
+
+        let offset = MagicBuffer::min_len() as u64;
+        let path =
+            std::env::temp_dir().join(format!("magic_buffer_from_fd_{}", std::process::id()));
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)
+            .expect("should create file");
+        std::fs::remove_file(&path).expect("should remove file");
+        file.set_len(offset + VALID_BUF_LEN as u64).unwrap();
+        file.write_all_at(b"head", offset).unwrap();
+
+        let mut buf = MagicBuffer::from_fd(&file, offset, VALID_BUF_LEN).expect("should map fd");
+        drop(file);
+        assert_eq!(b"head", &buf[0..4]);
+        assert_eq!(b"head", &buf[VALID_BUF_LEN..VALID_BUF_LEN + 4]);
+        buf[VALID_BUF_LEN - 1] = b'!';
+        assert_eq!(b'!', buf[2 * VALID_BUF_LEN - 1]);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn from_fd_requires_aligned_offset() {
+        let file = std::fs::File::open("/dev/zero").expect("should open /dev/zero");
+        let err = MagicBuffer::from_fd(&file, 1, VALID_BUF_LEN).expect_err("should not map fd");
+        assert!(matches!(err, MagicBufferError::InvalidOffset { .. }));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn from_fd_sync_rejects_non_dax_backing() {
+        let fd = MagicBuffer::new(VALID_BUF_LEN)
+            .expect("should allocate buffer")
+            .into_backing()
+            .expect("should have backing");
+        let err = MagicBuffer::from_fd_sync(fd, 0, VALID_BUF_LEN).expect_err("should not map fd");
+        assert!(matches!(err, MagicBufferError::Unsupported { .. }));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn into_backing_keeps_contents() {
+        let mut buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        buf.write_all_wrapping(VALID_BUF_LEN - 2, b"abcd");
+
+        let fd = buf.into_backing().expect("should have backing");
+        let buf = MagicBuffer::from_fd(fd, 0, VALID_BUF_LEN).expect("should map fd");
+        assert_eq!(b"abcd", &buf[VALID_BUF_LEN - 2..VALID_BUF_LEN + 2]);
+    }
+
+    #[test]
+    fn closed_range_stays_within_mirror() {
+        let mut buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        for i in 0..VALID_BUF_LEN {
+            buf[i] = (i % 251) as u8;
+        }
+
+        let slice = &buf[VALID_BUF_LEN - 1..2 * VALID_BUF_LEN - 1];
+        assert_eq!(VALID_BUF_LEN, slice.len());
+        assert_eq!(((VALID_BUF_LEN - 1) % 251) as u8, slice[0]);
+        assert_eq!(0, slice[1]);
+        assert_eq!(((VALID_BUF_LEN - 2) % 251) as u8, slice[VALID_BUF_LEN - 1]);
+
+        let slice = &buf[3 * VALID_BUF_LEN - 1..4 * VALID_BUF_LEN - 1];
+        assert_eq!(((VALID_BUF_LEN - 1) % 251) as u8, slice[0]);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn io_uring_buffer_is_aligned() {
+        const LEN: usize = 2 << 20;
+        let mut buf = MagicBuffer::new_for_io_uring(LEN).expect("should allocate buffer");
+        assert_eq!(0, buf.as_ptr(0) as usize % LEN);
+        buf[LEN - 1] = b'a';
+        assert_eq!(b'a', buf[2 * LEN - 1]);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn io_uring_buffer_requires_huge_page_len() {
+        let err = MagicBuffer::new_for_io_uring(VALID_BUF_LEN).expect_err("should not allocate");
+        assert!(matches!(err, MagicBufferError::InvalidLen { .. }));
+    }
+
+    #[test]
+    fn build_from_options() {
+        let buf = MagicBufferOptions::new(VALID_BUF_LEN)
+            .build()
+            .expect("should allocate buffer");
+        assert_eq!(VALID_BUF_LEN, buf.len());
+
+        let err = MagicBufferOptions::new(VALID_BUF_LEN - 1)
+            .build()
+            .expect_err("should not allocate");
+        assert!(matches!(err, MagicBufferError::InvalidLen { .. }));
+    }
+
+    #[test]
+    fn build_named() {
+        let a = MagicBufferOptions::new(VALID_BUF_LEN)
+            .name("ring-a")
+            .build()
+            .expect("should allocate buffer");
+        let b = MagicBufferOptions::new(VALID_BUF_LEN)
+            .name("x".repeat(300))
+            .build()
+            .expect("should allocate buffer");
+        let c = MagicBufferOptions::new(VALID_BUF_LEN)
+            .name("nul\0name")
+            .build()
+            .expect("should allocate buffer");
+        assert_ne!(a.as_ptr(0), b.as_ptr(0));
+        assert_ne!(b.as_ptr(0), c.as_ptr(0));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn build_dont_inherit_on_fork() {
+        let mut buf = MagicBufferOptions::new(VALID_BUF_LEN)
+            .fork_behavior(ForkBehavior::DontInherit)
+            .build()
+            .expect("should allocate buffer");
+        buf[0] = 1u8;
+
+        let pid = unsafe { libc::fork() };
+        assert_ne!(-1, pid);
+        if pid == 0 {
+            // the mapping is gone in the child, touching it faults
+            let status = if unsafe { libc::mincore(buf.as_mut_ptr(0) as _, 1, &mut 0) } == -1 {
+                0
+            } else {
+                1
+            };
+            unsafe { libc::_exit(status) };
+        }
+
+        let mut status = 0;
+        assert_eq!(pid, unsafe { libc::waitpid(pid, &mut status, 0) });
+        assert!(libc::WIFEXITED(status));
+        assert_eq!(0, libc::WEXITSTATUS(status));
+        assert_eq!(1u8, buf[0]);
+    }
+
+    #[test]
+    fn open_shared_attaches() {
+        let name = format!("/magic_buffer_test_{}", std::process::id());
+        let mut a = MagicBuffer::open_shared(&name, VALID_BUF_LEN).expect("should open buffer");
+        let b = MagicBuffer::open_shared(&name, VALID_BUF_LEN).expect("should open buffer");
+        assert_ne!(a.as_ptr(0), b.as_ptr(0));
+
+        a.write_at(VALID_BUF_LEN - 2, b"abcd");
+        assert_eq!(b"abcd", &b[VALID_BUF_LEN - 2..VALID_BUF_LEN + 2]);
+
+        let err = MagicBuffer::open_shared(&name, 2 * VALID_BUF_LEN).expect_err("should not open");
+        assert!(matches!(err, MagicBufferError::InvalidLen { .. }));
+
+        MagicBuffer::unlink_shared(&name).expect("should unlink buffer");
+        drop(a);
+        assert_eq!(b"abcd", &b[VALID_BUF_LEN - 2..VALID_BUF_LEN + 2]);
+
+        let err = MagicBuffer::open_shared("/nul\0", VALID_BUF_LEN).expect_err("should not open");
+        assert!(matches!(err, MagicBufferError::InvalidName { .. }));
+    }
+
+    #[test]
+    fn from_file_persists() {
+        let path = std::env::temp_dir().join(format!("magic_buffer_test_{}", std::process::id()));
+        let mut buf = MagicBuffer::from_file(&path, VALID_BUF_LEN).expect("should map file");
+        buf.write_at(VALID_BUF_LEN - 2, b"abcd");
+        drop(buf);
+
+        assert_eq!(
+            VALID_BUF_LEN as u64,
+            std::fs::metadata(&path).unwrap().len()
+        );
+        let buf = MagicBuffer::from_file(&path, VALID_BUF_LEN).expect("should map file");
+        assert_eq!(b"abcd", &buf[VALID_BUF_LEN - 2..VALID_BUF_LEN + 2]);
+        drop(buf);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn resize_keeps_file_backing() {
+        let path = std::env::temp_dir().join(format!("magic_buffer_resize_{}", std::process::id()));
+        let mut buf = MagicBuffer::from_file(&path, VALID_BUF_LEN).expect("should map file");
+        buf.write_at(0, b"abcd");
+
+        let err = buf
+            .resize(2 * VALID_BUF_LEN)
+            .expect_err("should keep file backing");
+        assert!(matches!(err, MagicBufferError::Unsupported { .. }));
+        assert_eq!(VALID_BUF_LEN, buf.capacity());
+        buf.write_at(4, b"efgh");
+        buf.flush().expect("should flush");
+        assert_eq!(b"abcdefgh", &std::fs::read(&path).unwrap()[..8]);
+        drop(buf);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn flush_anonymous_buffer() {
+        let buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        buf.flush().expect("should flush");
+        buf.flush_async().expect("should flush");
+    }
+
+    #[test]
+    fn flush_file_backed_buffer() {
+        let path = std::env::temp_dir().join(format!("magic_buffer_flush_{}", std::process::id()));
+        let mut buf = MagicBuffer::from_file(&path, VALID_BUF_LEN).expect("should map file");
+        buf.write_at(0, b"abcd");
+        buf.flush().expect("should flush");
+
+        let contents = std::fs::read(&path).unwrap();
+        assert_eq!(b"abcd", &contents[..4]);
+        drop(buf);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn triple_mirror_slices() {
+        let mut buf = MagicBufferOptions::new(VALID_BUF_LEN)
+            .mirror_count(3)
+            .build()
+            .expect("should allocate buffer");
+        assert_eq!(3, buf.mirror_count());
+
+        // the slice reaches halfway into the third mapping
+        let start = VALID_BUF_LEN - 1;
+        let end = start + VALID_BUF_LEN + VALID_BUF_LEN / 2;
+        buf[VALID_BUF_LEN - 1] = 1u8;
+        buf[VALID_BUF_LEN / 2 - 2] = 2u8;
+
+        let slice = &buf[start..end];
+        assert_eq!(VALID_BUF_LEN + VALID_BUF_LEN / 2, slice.len());
+        assert_eq!(1u8, slice[0]);
+        assert_eq!(1u8, slice[VALID_BUF_LEN]);
+        assert_eq!(2u8, slice[VALID_BUF_LEN + VALID_BUF_LEN / 2 - 1]);
+        assert!(buf.get(start..end + VALID_BUF_LEN).is_none());
+
+        let clone = buf.clone();
+        assert_eq!(3, clone.mirror_count());
+    }
+
+    #[test]
+    fn mirror_count_limits_slices() {
+        let buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        assert_eq!(2, buf.mirror_count());
+        assert!(buf.get(0..VALID_BUF_LEN + 1).is_none());
+
+        MagicBufferOptions::new(VALID_BUF_LEN)
+            .mirror_count(1)
+            .build()
+            .expect_err("should reject a single mirror");
+    }
+
+    #[test]
+    fn guard_page_option() {
+        let mut buf = MagicBufferOptions::new(VALID_BUF_LEN)
+            .guard_page(true)
+            .build()
+            .expect("should allocate buffer");
+        buf[0] = 1u8;
+        assert_eq!(1u8, buf[VALID_BUF_LEN]);
+
+        let clone = buf.clone();
+        assert_eq!(1u8, clone[0]);
+    }
+
+    #[cfg(any(target_os = "linux", target_family = "windows"))]
+    #[test]
+    fn guard_page_is_reserved() {
+        let page = MagicBuffer::min_len();
+        let at = |addr| MagicBufferOptions::new(page).base_hint(addr).build();
+        retry_released(|| {
+            let buf = MagicBufferOptions::new(VALID_BUF_LEN)
+                .guard_page(true)
+                .build()
+                .expect("should allocate buffer");
+
+            // the guard directly follows both views, so nothing else can be mapped there
+            let end = buf.as_ptr(0) as usize + 2 * VALID_BUF_LEN;
+            let err = at(end).expect_err("guard page should be reserved");
+            assert!(matches!(err, MagicBufferError::AddressUnavailable));
+
+            // and it is released along with the views
+            drop(buf);
+            at(end - page)
+        });
+    }
+
+    /// Retries `place`, which maps a buffer at an address that it has just released.
+    /// Tests run in parallel and may map the range in between, which `place` reports as
+    /// [`MagicBufferError::AddressUnavailable`].
+    #[cfg(any(target_os = "linux", target_family = "windows"))]
+    fn retry_released<T: fmt::Debug>(mut place: impl FnMut() -> Result<T, MagicBufferError>) -> T {
+        for _ in 0..10 {
+            match place() {
+                Err(MagicBufferError::AddressUnavailable) => continue,
+                result => return result.expect("should allocate buffer"),
+            }
+        }
+        panic!("released address should be available")
+    }
+
+    #[cfg(any(target_os = "linux", target_family = "windows"))]
+    #[test]
+    fn base_hint_does_not_replace_mapping() {
+        let mut buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        buf[0] = 1u8;
+        let base = buf.as_ptr(0) as usize;
+
+        for addr in [base, base + VALID_BUF_LEN] {
+            let err = MagicBufferOptions::new(VALID_BUF_LEN)
+                .base_hint(addr)
+                .build()
+                .expect_err("should not replace existing mapping");
+            assert!(matches!(err, MagicBufferError::AddressUnavailable));
+        }
+        assert_eq!(1u8, buf[0]);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn address_hint_places_mapping() {
+        let buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        let addr = buf.as_ptr(0) as usize;
+
+        let err = MagicBufferOptions::new(VALID_BUF_LEN)
+            .address_hint(addr)
+            .fixed_address(true)
+            .build()
+            .expect_err("address should be in use");
+        assert!(matches!(err, MagicBufferError::AddressUnavailable));
+
+        // without fixed placement the mapping lands elsewhere
+        let other = MagicBufferOptions::new(VALID_BUF_LEN)
+            .address_hint(addr)
+            .build()
+            .expect("should allocate buffer");
+        assert_ne!(addr, other.as_ptr(0) as usize);
+
+        drop(buf);
+        let (addr, mut buf) = retry_released(|| {
+            let addr = MagicBuffer::new(VALID_BUF_LEN)?.as_ptr(0) as usize;
+            MagicBufferOptions::new(VALID_BUF_LEN)
+                .address_hint(addr)
+                .fixed_address(true)
+                .build()
+                .map(|buf| (addr, buf))
+        });
+        assert_eq!(addr, buf.as_ptr(0) as usize);
+        buf[0] = 1u8;
+        assert_eq!(1u8, buf[VALID_BUF_LEN]);
 
-impl Index<isize> for MagicBuffer {
-    type Output = u8;
+        let clone = buf.clone();
+        assert_ne!(addr, clone.as_ptr(0) as usize);
+    }
 
-    fn index(&self, index: isize) -> &Self::Output {
-        let index = if index < 0 {
-            self.len - self.fast_mod((-index) as usize)
-        } else {
-            self.fast_mod(index as usize)
-        };
-        unsafe { &*self.addr.add(index) }
+    #[test]
+    fn address_hint_must_be_aligned() {
+        let err = MagicBufferOptions::new(VALID_BUF_LEN)
+            .address_hint(MagicBuffer::min_len() + 1)
+            .build()
+            .expect_err("should reject unaligned hint");
+        assert!(matches!(err, MagicBufferError::InvalidOffset { .. }));
     }
-}
 
-impl IndexMut<isize> for MagicBuffer {
-    fn index_mut(&mut self, index: isize) -> &mut Self::Output {
-        let index = if index < 0 {
-            self.len - self.fast_mod((-index) as usize)
-        } else {
-            self.fast_mod(index as usize)
-        };
-        unsafe { &mut *self.addr.add(index) }
+    #[test]
+    fn numa_node_zero() {
+        // every system has node 0, even without NUMA support
+        let mut buf = MagicBufferOptions::new(VALID_BUF_LEN)
+            .numa_node(0)
+            .build()
+            .expect("should allocate buffer");
+        buf[0] = 1u8;
+        assert_eq!(1u8, buf[VALID_BUF_LEN]);
     }
-}
 
-impl Index<Range<usize>> for MagicBuffer {
-    type Output = [u8];
+    #[test]
+    fn transparent_huge_pages_are_advisory() {
+        let mut buf = MagicBufferOptions::new(VALID_BUF_LEN)
+            .transparent_huge_pages(true)
+            .build()
+            .expect("should allocate buffer");
+        assert_eq!(MagicBuffer::min_len(), buf.page_size());
+        buf[0] = 1u8;
+        assert_eq!(1u8, buf[VALID_BUF_LEN]);
+    }
 
-    fn index(&self, index: Range<usize>) -> &Self::Output {
-        if index.start > index.end {
-            return &[];
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn transparent_huge_pages_advise_all_views() {
+        let buf = MagicBufferOptions::new(VALID_BUF_LEN)
+            .mirror_count(3)
+            .transparent_huge_pages(true)
+            .build()
+            .expect("should allocate buffer");
+
+        // smaps lists the flags of every mapping, "hg" marks MADV_HUGEPAGE
+        let smaps = std::fs::read_to_string("/proc/self/smaps").expect("should read smaps");
+        let advised = |addr: usize| {
+            let start = smaps
+                .find(&format!("{:x}-", addr))
+                .expect("view should be mapped");
+            let flags = smaps[start..]
+                .lines()
+                .find(|line| line.starts_with("VmFlags:"))
+                .expect("view should have flags");
+            flags.split_whitespace().any(|flag| flag == "hg")
+        };
+
+        // kernels without THP support reject the advice altogether
+        let base = buf.as_ptr(0) as usize;
+        if advised(base) {
+            assert!(advised(base + VALID_BUF_LEN));
+            assert!(advised(base + 2 * VALID_BUF_LEN));
         }
+    }
 
-        let len = index.end - index.start;
-        if len > self.len {
-            panic!("out of bounds")
+    #[test]
+    fn prefetch_wraps_around() {
+        let buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        for offset in (0..3 * VALID_BUF_LEN).step_by(64) {
+            buf.prefetch(offset);
+            buf.prefetch_write(offset);
         }
+        buf.prefetch(usize::MAX);
+    }
+
+    #[test]
+    fn as_slice_and_to_vec() {
+        let mut buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        buf.as_mut_slice()[VALID_BUF_LEN - 1] = 1u8;
+        assert_eq!(VALID_BUF_LEN, buf.as_slice().len());
+        assert_eq!(1u8, buf.as_slice()[VALID_BUF_LEN - 1]);
 
-        unsafe { self.as_slice(self.fast_mod(index.start), len) }
+        let v = buf.to_vec();
+        assert_eq!(VALID_BUF_LEN, v.len());
+        assert_eq!(buf.as_slice(), &v[..]);
     }
-}
 
-impl IndexMut<Range<usize>> for MagicBuffer {
-    fn index_mut(&mut self, index: Range<usize>) -> &mut Self::Output {
-        if index.start > index.end {
-            return &mut [];
-        }
+    #[test]
+    fn try_from_exact_len() {
+        let data = vec![1u8; VALID_BUF_LEN];
+        let buf = MagicBuffer::try_from(data.as_slice()).expect("should allocate buffer");
+        assert_eq!(VALID_BUF_LEN, buf.len());
+        assert_eq!(&data[..], &buf[..]);
 
-        let len = index.end - index.start;
-        if len > self.len {
-            panic!("out of bounds")
+        let buf = MagicBuffer::try_from(data).expect("should allocate buffer");
+        assert_eq!(VALID_BUF_LEN, buf.len());
+        assert_eq!(1u8, buf[VALID_BUF_LEN - 1]);
+    }
+
+    #[test]
+    fn try_from_rounds_up() {
+        let data = vec![1u8; VALID_BUF_LEN + 1];
+        let buf: MagicBuffer = data.try_into().expect("should allocate buffer");
+        assert_eq!(2 * VALID_BUF_LEN, buf.len());
+        assert_eq!(1u8, buf[VALID_BUF_LEN]);
+        assert_eq!(0u8, buf[VALID_BUF_LEN + 1]);
+    }
+
+    #[test]
+    fn base_pointer_is_aligned() {
+        let buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        assert!(buf.alignment() >= MagicBuffer::min_len());
+        assert_eq!(0, buf.as_ptr(0) as usize % buf.alignment());
+
+        let buf = MagicBuffer::<u64>::with_len(VALID_BUF_LEN).expect("should allocate buffer");
+        assert_eq!(0, buf.as_ptr(0) as usize % buf.alignment());
+    }
+
+    #[test]
+    fn alignment_after_rotation() {
+        let mut buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        buf.rotate_left(1);
+        assert_eq!(1, buf.alignment());
+        assert_eq!(0, buf.as_ptr(0) as usize % buf.alignment());
+
+        buf.rotate_left(23);
+        assert_eq!(8, buf.alignment());
+        assert_eq!(0, buf.as_ptr(0) as usize % buf.alignment());
+
+        buf.rotate_right(24);
+        assert_eq!(buf.page_size(), buf.alignment());
+
+        let mut buf = MagicBuffer::<u64>::with_len(VALID_BUF_LEN).expect("should allocate buffer");
+        buf.rotate_left(3);
+        assert_eq!(8, buf.alignment());
+        assert_eq!(0, buf.as_ptr(0) as usize % buf.alignment());
+    }
+
+    #[test]
+    fn split_at_mut_halves_are_independent() {
+        let mut buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        let (front, back) = buf.split_at_mut(VALID_BUF_LEN / 2);
+        assert_eq!(VALID_BUF_LEN / 2, front.len());
+        assert_eq!(VALID_BUF_LEN / 2, back.len());
+        front.fill(1);
+        back.fill(2);
+
+        assert!(buf[..VALID_BUF_LEN / 2].iter().all(|v| *v == 1));
+        assert!(buf[VALID_BUF_LEN / 2..VALID_BUF_LEN]
+            .iter()
+            .all(|v| *v == 2));
+
+        let (front, back) = buf.split_at_mut(VALID_BUF_LEN);
+        assert_eq!(VALID_BUF_LEN, front.len());
+        assert!(back.is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "out of bounds")]
+    fn split_at_mut_out_of_bounds() {
+        let mut buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        buf.split_at_mut(VALID_BUF_LEN + 1);
+    }
+
+    #[test]
+    fn chunks_wrapping_rotates_buffer() {
+        let mut buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        for (i, v) in buf.iter_mut().enumerate() {
+            *v = i as u8;
         }
 
-        unsafe { self.as_slice_mut(self.fast_mod(index.start), len) }
+        let start = 3 * VALID_BUF_LEN - 100;
+        let chunks = buf.chunks_wrapping(start, 1000).collect::<Vec<_>>();
+        assert_eq!(VALID_BUF_LEN / 1000 + 1, chunks.len());
+        assert!(chunks[..chunks.len() - 1].iter().all(|c| c.len() == 1000));
+        assert_eq!(VALID_BUF_LEN % 1000, chunks[chunks.len() - 1].len());
+
+        let mut rotated = buf.to_vec();
+        rotated.rotate_left(VALID_BUF_LEN - 100);
+        assert_eq!(rotated, chunks.concat());
     }
-}
 
-impl Index<RangeTo<usize>> for MagicBuffer {
-    type Output = [u8];
+    #[test]
+    fn atomics_round_trip() {
+        let len = VALID_BUF_LEN;
+        let buf = MagicBuffer::new(len).expect("should allocate buffer");
+        unsafe {
+            buf.store_u32(4, 0xdead_beef, Ordering::Release);
+            buf.store_u64(len + 8, u64::MAX - 1, Ordering::SeqCst);
+        }
 
-    fn index(&self, index: RangeTo<usize>) -> &Self::Output {
-        let start = index.end - self.len;
-        unsafe { self.as_slice(self.fast_mod(start), self.len) }
+        assert_eq!(0xdead_beef, buf.load_u32(len + 4, Ordering::Acquire));
+        assert_eq!(u64::MAX - 1, buf.load_u64(8, Ordering::Relaxed));
+        assert_eq!(0xdead_beef_u32.to_ne_bytes(), buf[4..8]);
     }
-}
 
-impl IndexMut<RangeTo<usize>> for MagicBuffer {
-    fn index_mut(&mut self, index: RangeTo<usize>) -> &mut Self::Output {
-        let start = index.end - self.len;
-        unsafe { self.as_slice_mut(self.fast_mod(start), self.len) }
+    #[test]
+    #[should_panic(expected = "offset must be aligned to 8 bytes")]
+    fn atomics_panic_if_misaligned() {
+        let buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        buf.load_u64(4, Ordering::Relaxed);
     }
-}
 
-impl Index<RangeFrom<usize>> for MagicBuffer {
-    type Output = [u8];
+    #[test]
+    #[should_panic(expected = "out of bounds")]
+    fn atomics_panic_if_empty() {
+        MagicBuffer::default().load_u32(0, Ordering::Relaxed);
+    }
 
-    fn index(&self, index: RangeFrom<usize>) -> &Self::Output {
-        unsafe { self.as_slice(self.fast_mod(index.start), self.len) }
+    #[test]
+    fn writes_through_uninit_slice() {
+        let len = VALID_BUF_LEN;
+        let mut buf = MagicBuffer::new(len).expect("should allocate buffer");
+        let uninit = unsafe { buf.as_uninit_mut(len - 2, 4) };
+        for (i, v) in uninit.iter_mut().enumerate() {
+            v.write(i as u8 + 1);
+        }
+
+        assert_eq!([1u8, 2, 3, 4], buf[len - 2..len + 2]);
+        assert_eq!([3u8, 4], buf[..2]);
     }
-}
 
-impl IndexMut<RangeFrom<usize>> for MagicBuffer {
-    fn index_mut(&mut self, index: RangeFrom<usize>) -> &mut Self::Output {
-        unsafe { self.as_slice_mut(self.fast_mod(index.start), self.len) }
+    #[test]
+    #[should_panic(expected = "out of bounds")]
+    fn uninit_slice_panics_if_too_long() {
+        let mut buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        unsafe { buf.as_uninit_mut(0, VALID_BUF_LEN + 1) };
     }
-}
 
-impl Index<RangeToInclusive<usize>> for MagicBuffer {
-    type Output = [u8];
+    #[test]
+    fn debug_previews_contents() {
+        let len = VALID_BUF_LEN;
+        let mut buf = MagicBuffer::new(len).expect("should allocate buffer");
+        buf[0] = 0xabu8;
+        buf[len - 1] = 0xfa;
 
-    fn index(&self, index: RangeToInclusive<usize>) -> &Self::Output {
-        let start = index.end - self.len + 1;
-        unsafe { self.as_slice(self.fast_mod(start), self.len) }
+        let debug = format!("{:?}", buf);
+        assert_eq!(
+            "MagicBuffer { len: 65536, data: [ab, 00, 00, 00, 00, 00, 00, 00, \
+             .., 00, 00, 00, 00, 00, 00, 00, fa] }",
+            debug
+        );
+        assert!(!debug.contains("addr"));
     }
-}
 
-impl IndexMut<RangeToInclusive<usize>> for MagicBuffer {
-    fn index_mut(&mut self, index: RangeToInclusive<usize>) -> &mut Self::Output {
-        let start = index.end - self.len + 1;
-        unsafe { self.as_slice_mut(self.fast_mod(start), self.len) }
+    #[test]
+    fn resize_keeps_contents() {
+        let len = VALID_BUF_LEN;
+        let mut buf = MagicBuffer::new(len).expect("should allocate buffer");
+        for (i, v) in buf.iter_mut().enumerate() {
+            *v = i as u8;
+        }
+
+        buf.resize(4 * len).expect("should grow buffer");
+        assert_eq!(4 * len, buf.len());
+        assert!(buf[..len].iter().enumerate().all(|(i, v)| *v == i as u8));
+        assert!(buf[len..4 * len].iter().all(|v| *v == 0));
+        buf[0] = b'a';
+        assert_eq!(b'a', buf[4 * len]);
+
+        buf.resize(len + 1).expect_err("should reject invalid len");
+        assert_eq!(4 * len, buf.len());
+    }
+
+    #[test]
+    fn resize_keeps_options() {
+        let len = VALID_BUF_LEN;
+        let mut buf = MagicBufferOptions::new(len)
+            .mirror_count(3)
+            .build()
+            .expect("should allocate buffer");
+        buf.resize(2 * len).expect("should grow buffer");
+        assert_eq!(3, buf.mirror_count());
+        assert_eq!(4 * len, buf[1..4 * len + 1].len());
     }
-}
 
-impl Index<RangeFull> for MagicBuffer {
-    type Output = [u8];
+    #[test]
+    fn resize_exact_capacity() {
+        let page = MagicBuffer::min_len();
+        let mut buf = MagicBuffer::with_exact_capacity(3 * page).expect("should allocate buffer");
+        buf[3 * page - 1] = 1u8;
+        buf.resize(5 * page).expect("should grow buffer");
+        assert_eq!(5 * page, buf.capacity());
+        assert_eq!(&[1u8, 0u8], &buf[3 * page - 1..3 * page + 1]);
+        assert_eq!(1u8, buf[8 * page - 1]);
 
-    fn index(&self, _: RangeFull) -> &Self::Output {
-        unsafe { self.as_slice(0, self.len) }
+        let err = buf
+            .resize(5 * page + 1)
+            .expect_err("should reject unaligned len");
+        assert!(matches!(err, MagicBufferError::InvalidLen { .. }));
     }
-}
 
-impl IndexMut<RangeFull> for MagicBuffer {
-    fn index_mut(&mut self, _: RangeFull) -> &mut Self::Output {
-        unsafe { self.as_slice_mut(0, self.len) }
+    #[test]
+    fn rotate_shifts_logical_start() {
+        let len = VALID_BUF_LEN;
+        let mut buf = MagicBuffer::new(len).expect("should allocate buffer");
+        for (i, v) in buf.iter_mut().enumerate() {
+            *v = i as u8;
+        }
+
+        buf.rotate_left(3);
+        assert_eq!(3u8, buf[0]);
+        assert_eq!(2u8, buf[len - 1]);
+        assert_eq!([254u8, 255, 0, 1], buf[len - 5..len - 1]);
+        assert_eq!(len, buf.len());
+        assert_eq!(2u8, *buf.last().expect("should not be empty"));
+        assert_eq!(2u8, buf[-1isize]);
+
+        buf[0] = b'a';
+        buf.rotate_right(3);
+        assert_eq!(0u8, buf[0]);
+        assert_eq!(b'a', buf[3]);
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[test]
+    fn rotate_wraps_around() {
+        let len = VALID_BUF_LEN;
+        let mut buf = MagicBuffer::new(len).expect("should allocate buffer");
+        buf[1] = 1u8;
+        buf.rotate_right(len - 1);
+        assert_eq!(1u8, buf[0]);
+        buf.rotate_left(2 * len);
+        assert_eq!(1u8, buf[0]);
 
-    const VALID_BUF_LEN: usize = 1 << 16;
-    const INVALID_BUF_LEN_ALIGN: usize = 1 << 8;
-    const INVALID_BUF_LEN_POW2: usize = (1 << 16) + 5;
+        let (front, back) = buf.split_at_mut(len / 2);
+        assert_eq!(1u8, front[0]);
+        back[back.len() - 1] = 2u8;
+        assert_eq!(2u8, buf[len - 1]);
+        assert_eq!(vec![1u8, 0], buf.to_vec()[..2]);
+    }
 
     #[test]
-    fn allocates_buffer() {
-        let buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
-        drop(buf);
+    fn checked_new_separates_errors() {
+        let err = MagicBuffer::checked_new(VALID_BUF_LEN + 1).expect_err("should reject len");
+        assert!(matches!(err, MagicBufferError::InvalidLen { .. }));
+
+        let buf = MagicBuffer::checked_new(VALID_BUF_LEN)
+            .expect("should accept len")
+            .expect("should allocate buffer");
+        assert_eq!(VALID_BUF_LEN, buf.len());
     }
 
     #[test]
-    fn requires_power_of_two() {
-        MagicBuffer::new(INVALID_BUF_LEN_POW2)
-            .map_err(|e| {
-                println!("{}", e);
-                e
-            })
-            .expect_err("should not allocate buffer");
+    fn new_unchecked_allocates() {
+        MagicBuffer::validate_len(VALID_BUF_LEN).expect("len should be valid");
+        let mut buf =
+            unsafe { MagicBuffer::new_unchecked(VALID_BUF_LEN) }.expect("should allocate buffer");
+        buf[0] = b'a';
+        assert_eq!(b'a', buf[VALID_BUF_LEN]);
     }
 
     #[test]
-    fn requires_aligned_len() {
-        MagicBuffer::new(INVALID_BUF_LEN_ALIGN)
-            .map_err(|e| {
-                println!("{}", e);
-                e
-            })
-            .expect_err("should not allocate buffer");
+    fn try_slice_reports_invalid_ranges() {
+        let len = VALID_BUF_LEN;
+        let mut buf = MagicBuffer::new(len).expect("should allocate buffer");
+        buf.try_slice_mut(len - 1..len + 1)
+            .expect("should slice across the wrap")
+            .copy_from_slice(b"ab");
+        assert_eq!(
+            b"ab",
+            buf.try_slice(2 * len - 1..2 * len + 1)
+                .expect("should slice")
+        );
+
+        let err = buf
+            .try_slice(1..len + 2)
+            .expect_err("should reject long range");
+        assert!(matches!(
+            err,
+            MagicBufferError::OutOfBounds { len: l, buf_len } if l == len + 1 && buf_len == len
+        ));
+        assert_eq!(
+            format!("out of bounds, {} exceeds the buffer len {}", len + 1, len),
+            err.to_string()
+        );
+
+        #[allow(clippy::reversed_empty_ranges)]
+        let err = buf
+            .try_slice_mut(2..1)
+            .expect_err("should reject reversed range");
+        assert!(matches!(err, MagicBufferError::InvalidOffset { .. }));
     }
 
     #[test]
-    fn writes_are_visible_wrap_around() {
+    fn exact_capacity_wraps_with_modulo() {
+        let len = 3 * MagicBuffer::min_len();
+        let mut buf = MagicBuffer::with_exact_capacity(len).expect("should allocate buffer");
+        assert_eq!(len, buf.len());
+        assert!(buf.validate());
+
+        buf[len - 2..len + 2].copy_from_slice(b"abcd");
+        assert_eq!(b"cd", &buf[..2]);
+        assert_eq!(b'a', buf[3 * len - 2]);
+        assert_eq!(b'd', buf[-(len as isize) - 1 + 2]);
+
+        buf.copy_within(len - 2, 1, 4);
+        assert_eq!(b"cabcd", &buf[0..5]);
+
+        buf.rotate_right(2);
+        assert_eq!(b"abcabcd", &buf[..7]);
+        assert_eq!(b"abcabcd", &buf[len..len + 7]);
+    }
+
+    #[test]
+    fn exact_capacity_requires_page_aligned_len() {
+        MagicBuffer::with_exact_capacity(3 * MagicBuffer::min_len() + 1)
+            .expect_err("should reject len");
+        MagicBuffer::validate_len(3 * MagicBuffer::min_len()).expect_err("should reject len");
+    }
+
+    #[test]
+    fn truncate_limits_deref() {
         let mut buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
-        buf[0] = b'a';
-        assert_eq!(buf[0], buf[VALID_BUF_LEN]);
+        buf.write_at(0, b"abcdef");
+
+        buf.truncate(4);
+        assert_eq!(4, buf.len());
+        assert_eq!(VALID_BUF_LEN, buf.capacity());
+        assert_eq!(b"abcd", &*buf);
+        assert_eq!(b"abcd", &buf[..]);
+        assert_eq!(b"cd", &buf[2..]);
+        assert_eq!(b"cd", &buf[2..4]);
+        assert_eq!(b"d", &buf[3..=3]);
+        assert!(buf[4..].is_empty());
+        assert!(buf.get(0..5).is_none());
+        assert!(buf.get(4..5).is_none());
+        assert!(buf.get(4).is_none());
+        assert_eq!(Some(&b'd'), buf.get(3));
+        assert!(buf.try_slice(3..5).is_err());
+
+        buf.truncate(8);
+        assert_eq!(4, buf.len());
+
+        unsafe { buf.set_len(6) };
+        assert_eq!(b"abcdef", &*buf);
     }
 
     #[test]
-    fn deref_as_slice() {
-        let buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
-        let slice: &[u8] = &buf;
-        assert_eq!(VALID_BUF_LEN, slice.len());
+    #[should_panic(expected = "out of bounds")]
+    fn truncate_limits_ranges() {
+        let mut buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        buf.truncate(4);
+        let _ = &buf[VALID_BUF_LEN - 2..VALID_BUF_LEN + 3];
     }
 
     #[test]
-    fn deref_mut_as_slice() {
+    #[should_panic(expected = "out of bounds")]
+    fn truncate_limits_range_start() {
         let mut buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
-        let slice: &mut [u8] = &mut buf;
-        assert_eq!(VALID_BUF_LEN, slice.len());
+        buf.truncate(3);
+        let _ = &buf[5..];
     }
 
     #[test]
-    fn closed_range() {
-        let buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
-        let slice = &buf[0..VALID_BUF_LEN];
-        assert_eq!(VALID_BUF_LEN, slice.len());
+    #[should_panic(expected = "out of bounds")]
+    fn truncate_limits_index() {
+        let mut buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        buf.truncate(3);
+        buf[3] = 1u8;
     }
 
     #[test]
-    fn closed_range_mut() {
+    fn truncated_clone_keeps_len() {
         let mut buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
-        let slice = &mut buf[0..VALID_BUF_LEN];
-        assert_eq!(VALID_BUF_LEN, slice.len());
+        buf.write_at(0, b"abcdef");
+        buf.truncate(3);
+
+        let clone = buf.clone();
+        assert_eq!(b"abc", &*clone);
+        assert_eq!(VALID_BUF_LEN, clone.capacity());
     }
 
     #[test]
-    fn range_to() {
-        let buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
-        let slice = &buf[..VALID_BUF_LEN + 1];
-        assert_eq!(VALID_BUF_LEN, slice.len());
+    fn extend_from_slice_laps_oldest_bytes() {
+        let mut buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        buf.truncate(0);
+
+        buf.extend_from_slice(&vec![1u8; VALID_BUF_LEN - 2]);
+        assert_eq!(VALID_BUF_LEN - 2, buf.len());
+
+        buf.extend_from_slice(b"abcd");
+        assert_eq!(VALID_BUF_LEN, buf.len());
+        assert_eq!(b"abcd", &buf[VALID_BUF_LEN - 4..VALID_BUF_LEN]);
+        assert!(buf[..VALID_BUF_LEN - 4].iter().all(|b| *b == 1));
     }
 
     #[test]
-    fn range_to_mut() {
+    fn extend_from_slice_keeps_tail_of_large_data() {
         let mut buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
-        let slice = &mut buf[..VALID_BUF_LEN + 1];
-        assert_eq!(VALID_BUF_LEN, slice.len());
+        buf.truncate(1);
+
+        let data = (0..2 * VALID_BUF_LEN + 3)
+            .map(|i| i as u8)
+            .collect::<Vec<_>>();
+        buf.extend_from_slice(&data);
+        assert_eq!(VALID_BUF_LEN, buf.len());
+        assert_eq!(&data[data.len() - VALID_BUF_LEN..], &*buf);
     }
 
     #[test]
-    fn range_from() {
-        let buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
-        let slice = &buf[1..];
-        assert_eq!(VALID_BUF_LEN, slice.len());
+    fn empty_has_no_elements() {
+        let buf: MagicBuffer = MagicBuffer::empty();
+        assert_eq!(0, buf.len());
+        assert_eq!(0, buf.capacity());
+        assert!(buf.is_empty());
+        assert!(buf[..].is_empty());
+        assert!(buf[5..].is_empty());
+        assert!(buf[3..3].is_empty());
+        assert!(buf.get(0).is_none());
+        assert!(buf.get(0..1).is_none());
+        assert_eq!("MagicBuffer { len: 0, data: [] }", format!("{:?}", buf));
+        assert_eq!(buf, MagicBuffer::default());
     }
 
     #[test]
-    fn range_from_mut() {
+    #[should_panic(expected = "out of bounds")]
+    fn empty_index_out_of_bounds() {
+        let buf = MagicBuffer::<u32>::default();
+        let _ = buf[0];
+    }
+
+    #[test]
+    fn empty_is_valid() {
+        let mut buf = MagicBuffer::empty();
+        assert!(buf.validate());
+        buf.truncate(0);
+        assert!(buf.validate());
+    }
+
+    #[cfg(any(target_os = "linux", target_family = "windows"))]
+    #[test]
+    fn empty_has_no_backing() {
+        assert!(MagicBuffer::empty().into_backing().is_none());
+    }
+
+    #[test]
+    fn empty_drop_and_clone() {
+        let mut buf = MagicBuffer::empty();
+        buf.write_at(0, &[]);
+        buf.fill(1);
+        assert_eq!(0, buf.write_all_wrapping(0, b"abcd"));
+        drop(buf.clone());
+        drop(buf);
+    }
+
+    #[test]
+    fn uninit_tracks_initialized_len() {
+        let len = MagicBuffer::min_len() / 8;
+        let mut buf = MagicBuffer::<u64>::with_len(len)
+            .expect("should allocate buffer")
+            .into_uninit();
+        assert_eq!(0, buf.initialized_len());
+        assert!(buf.as_init_slice().is_empty());
+        assert_eq!(len, buf.as_uninit_slice_mut().len());
+
+        buf.as_uninit_slice_mut()[len - 1].write(7);
+        buf.as_uninit_slice_mut()[0].write(8);
+        unsafe { buf.set_initialized_len(len) };
+        assert_eq!(len, buf.as_init_slice().len());
+        assert_eq!(7u64, buf.as_init_slice()[len - 1]);
+
+        // the writes are visible through the mirror
+        let mirror = unsafe { buf.view_slice(len - 1, 2) };
+        assert_eq!(8u64, unsafe { mirror[1].assume_init() });
+
+        unsafe { buf.set_initialized_len(1) };
+        assert_eq!(&[8u64], buf.as_init_slice());
+    }
+
+    #[test]
+    #[should_panic(expected = "out of bounds")]
+    fn uninit_initialized_len_out_of_bounds() {
+        let mut buf = MagicBuffer::new(VALID_BUF_LEN)
+            .expect("should allocate buffer")
+            .into_uninit();
+        unsafe { buf.set_initialized_len(VALID_BUF_LEN + 1) };
+    }
+
+    #[test]
+    fn recycle_keeps_mapping() {
         let mut buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
-        let slice = &mut buf[1..];
-        assert_eq!(VALID_BUF_LEN, slice.len());
+        buf.rotate_left(1);
+        buf.write_all_wrapping(0, &[1, 2, 3, 4]);
+        let ptr = buf.as_ptr(0) as usize - 1;
+
+        let mut buf = buf.recycle_into::<u16>(false);
+        assert_eq!(ptr, buf.as_ptr(0) as usize);
+        assert_eq!(VALID_BUF_LEN / 2, buf.capacity());
+        assert_eq!(VALID_BUF_LEN / 2, buf.len());
+        assert_eq!(u16::from_ne_bytes([0, 1]), buf[0]);
+        buf[VALID_BUF_LEN / 2 - 1] = 5;
+        assert_eq!(5u16, buf[VALID_BUF_LEN - 1]);
+
+        let buf = buf.recycle_into::<[u8; 4]>(true);
+        assert_eq!(VALID_BUF_LEN / 4, buf.capacity());
+        assert!(buf.iter().all(|v| *v == [0; 4]));
     }
 
     #[test]
-    fn range_to_inclusive() {
+    #[should_panic(expected = "multiple of the element size")]
+    fn recycle_requires_multiple_of_element_size() {
+        let buf = MagicBuffer::with_exact_capacity(3 * MagicBuffer::min_len())
+            .expect("should allocate buffer");
+        let _ = buf.recycle_into::<[u8; 8192]>(false);
+    }
+
+    #[test]
+    #[cfg(all(
+        feature = "heap-fallback",
+        not(any(
+            target_family = "windows",
+            target_os = "linux",
+            target_os = "android",
+            target_os = "macos",
+            target_os = "ios",
+            target_os = "freebsd",
+            target_os = "netbsd",
+            target_os = "dragonfly"
+        ))
+    ))]
+    fn heap_fallback_mirrors_index_mut() {
+        let len = MagicBuffer::min_len();
+        let mut buf = MagicBuffer::new(len).expect("should allocate buffer");
+        buf[len - 1] = 1u8;
+        buf[0] = 2u8;
+        assert_eq!(&[1u8, 2u8], &buf[len - 1..len + 1]);
+
+        buf[..].fill(3u8);
+        let (front, _) = buf.split_at_mut(1);
+        front[0] = 4u8;
+        assert_eq!(&[3u8, 4u8], &buf[len - 1..len + 1]);
+    }
+
+    #[test]
+    fn default_page_size() {
         let buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
-        let slice = &buf[..=VALID_BUF_LEN];
-        assert_eq!(VALID_BUF_LEN, slice.len());
+        assert_eq!(MagicBuffer::min_len(), buf.page_size());
     }
 
     #[test]
-    fn range_to_inclusive_mut() {
-        let mut buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
-        let slice = &mut buf[..=VALID_BUF_LEN];
-        assert_eq!(VALID_BUF_LEN, slice.len());
+    fn huge_pages_fall_back() {
+        const LEN: usize = 2 << 20;
+        let mut buf = MagicBufferOptions::new(LEN)
+            .huge_pages(HugePageSize::Size2MiB)
+            .build()
+            .expect("should allocate buffer");
+        assert!(buf.page_size() == LEN || buf.page_size() == MagicBuffer::min_len());
+        buf[LEN - 1] = b'a';
+        assert_eq!(b'a', buf[2 * LEN - 1]);
     }
 
     #[test]
-    fn range_full() {
+    fn huge_pages_require_huge_page_len() {
+        let err = MagicBufferOptions::new(VALID_BUF_LEN)
+            .huge_pages(HugePageSize::Size2MiB)
+            .build()
+            .expect_err("should not allocate");
+        assert!(matches!(err, MagicBufferError::InvalidLen { .. }));
+    }
+
+    #[test]
+    fn lock_wrapping_region() {
         let buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
-        let slice = &buf[..];
-        assert_eq!(VALID_BUF_LEN, slice.len());
+        buf.lock_region(VALID_BUF_LEN - 100, 200)
+            .expect("should lock region");
+        buf.unlock_region(VALID_BUF_LEN - 100, 200)
+            .expect("should unlock region");
+        buf.lock_region(0, 0).expect("should lock empty region");
     }
 
     #[test]
-    fn range_full_mut() {
-        let mut buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
-        let slice = &mut buf[..];
-        assert_eq!(VALID_BUF_LEN, slice.len());
+    fn build_locked() {
+        let len = MagicBuffer::min_len();
+        let result = MagicBufferOptions::new(len).lock(true).build();
+        // the memlock limit may be too tight for the mapping in a sandbox
+        match result {
+            Ok(mut buf) => {
+                buf[len] = 1u8;
+                assert_eq!(1u8, buf[0]);
+            }
+            Err(err) => assert!(matches!(
+                err,
+                MagicBufferError::LockLimitExceeded { .. } | MagicBufferError::LockFailed { .. }
+            )),
+        }
     }
 
     #[test]
-    fn index_wrap_around() {
-        let mut buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
-        buf[0] = b'1';
-        assert_eq!(b'1', buf[VALID_BUF_LEN]);
+    fn build_zero_on_drop() {
+        let mut buf = MagicBufferOptions::new(VALID_BUF_LEN)
+            .zero_on_drop(true)
+            .build()
+            .expect("should allocate buffer");
+        buf.write_at(VALID_BUF_LEN - 2, b"abcd");
+        assert_eq!(b"abcd", &buf[VALID_BUF_LEN - 2..VALID_BUF_LEN + 2]);
+
+        // a second mapping of the memfd outlives the buffer and observes the zeroing
+        #[cfg(target_os = "linux")]
+        let other = {
+            let fd = buf.backing.as_ref().expect("should have a backing");
+            MagicBuffer::from_fd(fd, 0, VALID_BUF_LEN).expect("should map backing")
+        };
+        #[cfg(target_os = "linux")]
+        assert_eq!(b"abcd", &other[VALID_BUF_LEN - 2..VALID_BUF_LEN + 2]);
+
+        let clone = buf.clone();
+        drop(buf);
+        assert_eq!(b"abcd", &clone[VALID_BUF_LEN - 2..VALID_BUF_LEN + 2]);
+
+        #[cfg(target_os = "linux")]
+        assert!(other.verify_zeroed());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn lock_reports_memlock_limit() {
+        let mut rlim = libc::rlimit {
+            rlim_cur: 0,
+            rlim_max: 0,
+        };
+        assert_eq!(0, unsafe {
+            libc::getrlimit(libc::RLIMIT_MEMLOCK, &mut rlim)
+        });
+        if rlim.rlim_cur == libc::RLIM_INFINITY {
+            return;
+        }
+
+        let len = (rlim.rlim_cur as usize * 2)
+            .next_power_of_two()
+            .max(VALID_BUF_LEN);
+        let buf = MagicBuffer::new(len).expect("should allocate buffer");
+        // privileged processes are not subject to the limit
+        if let Err(err) = buf.lock_region(0, len) {
+            assert!(matches!(
+                err,
+                MagicBufferError::LockLimitExceeded { requested, .. } if requested == len
+            ));
+        }
     }
 
     #[test]
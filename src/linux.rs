@@ -1,14 +1,56 @@
 // This implementation is based on
 // https://github.com/gnzlbg/slice_deque/blob/master/src/mirrored/linux.rs
+//
+// It is shared with Android, where most of the Linux specific features are not exposed.
 
-use crate::MagicBufferError;
+use crate::{ForkBehavior, HugePageSize, MagicBufferError, MagicBufferOptions};
 
 use libc::{
-    c_char, c_int, c_long, c_uint, close, ftruncate, mkstemp, mmap, munmap, off_t, size_t, syscall,
-    sysconf, unlink, SYS_memfd_create, ENOSYS, MAP_FAILED, MAP_FIXED, MAP_SHARED, PROT_READ,
-    PROT_WRITE, _SC_PAGESIZE,
+    c_char, c_int, c_long, c_uint, c_ulong, close, ftruncate, getrlimit, madvise, mlock, mmap,
+    mprotect, msync, munlock, munmap, off_t, rlimit, size_t, syscall, sysconf, SYS_mbind,
+    SYS_memfd_create, _SC_PAGESIZE, EAGAIN, ENOMEM, ENOSYS, EOPNOTSUPP, MADV_DONTFORK,
+    MADV_HUGEPAGE, MAP_ANONYMOUS, MAP_FAILED, MAP_FIXED, MAP_NORESERVE, MAP_PRIVATE, MAP_SHARED,
+    MFD_CLOEXEC, MFD_HUGETLB, MFD_HUGE_1GB, MFD_HUGE_2MB, MPOL_BIND, MS_ASYNC, MS_SYNC, PROT_NONE,
+    PROT_READ, PROT_WRITE, RLIMIT_MEMLOCK, RLIM_INFINITY,
 };
-use std::ptr;
+
+#[cfg(target_os = "linux")]
+use libc::{
+    fstat, mkostemp, shm_open, shm_unlink, stat, unlink, MAP_FIXED_NOREPLACE, MAP_SHARED_VALIDATE,
+    MAP_SYNC, O_CLOEXEC, O_CREAT, O_RDWR,
+};
+
+#[cfg(target_os = "android")]
+use libc::EPERM;
+
+// libc doesn't export these for Android, the values are part of the kernel ABI
+#[cfg(target_os = "android")]
+const MAP_FIXED_NOREPLACE: c_int = 0x100000;
+#[cfg(target_os = "android")]
+const MAP_SHARED_VALIDATE: c_int = 0x03;
+#[cfg(target_os = "android")]
+const MAP_SYNC: c_int = 0x80000;
+
+#[cfg(target_os = "android")]
+#[link(name = "android")]
+extern "C" {
+    /// Creates an ashmem region of `size` bytes, available since API level 26.
+    fn ASharedMemory_create(name: *const c_char, size: size_t) -> c_int;
+}
+use std::{
+    ffi::CStr,
+    fs::File,
+    io,
+    mem::MaybeUninit,
+    os::fd::{AsRawFd, FromRawFd, OwnedFd},
+    ptr,
+};
+
+#[cfg(target_os = "linux")]
+use std::{fs, os::unix::fs::FileExt};
+
+/// The buffer keeps its memfd open, so that it can be handed off to another process.
+pub(super) type MagicBufBacking = OwnedFd;
 
 #[cfg(any(target_os = "android", target_os = "openbsd"))]
 use libc::__errno;
@@ -42,63 +84,611 @@ pub(super) unsafe fn magic_buf_min_len() -> usize {
     sysconf(_SC_PAGESIZE) as _
 }
 
-pub(super) unsafe fn magic_buf_alloc(len: usize) -> Result<*mut u8, MagicBufferError> {
-    let file_name = *b"magic_buffer\0";
-    let mut fd = memfd_create(file_name.as_ptr() as _, 0);
+/// Returns the len of the inaccessible guard region after the views, one page if enabled.
+unsafe fn magic_buf_guard_len(guard: bool) -> usize {
+    if guard {
+        magic_buf_min_len()
+    } else {
+        0
+    }
+}
+
+/// The kernel limits memfd names to 249 bytes, excluding the terminating null byte.
+const MEMFD_NAME_MAX: usize = 249;
 
-    if fd == -1 && errno() == ENOSYS {
-        // memfd_create is not implemented, use mkstemp instead:
-        fd = c_long::from(mkstemp(file_name.as_ptr() as _));
-        // and unlink the file
-        if fd != -1 {
-            assert_eq!(0, unlink(file_name.as_ptr() as _));
-        }
+#[cfg(target_os = "linux")]
+unsafe fn magic_buf_create_backing(len: usize) -> Result<OwnedFd, MagicBufferError> {
+    magic_buf_create_memfd(len, 0, None)
+}
+
+fn magic_buf_memfd_name(name: Option<&str>) -> Vec<u8> {
+    let name = name.unwrap_or("magic_buffer").as_bytes();
+    let len = name
+        .iter()
+        .position(|b| *b == 0)
+        .unwrap_or(name.len())
+        .min(MEMFD_NAME_MAX);
+
+    let mut c_name = name[..len].to_vec();
+    c_name.push(0);
+    c_name
+}
+
+unsafe fn magic_buf_create_memfd(
+    len: usize,
+    flags: c_uint,
+    name: Option<&str>,
+) -> Result<OwnedFd, MagicBufferError> {
+    let memfd_name = magic_buf_memfd_name(name);
+    let fd = memfd_create(memfd_name.as_ptr() as _, MFD_CLOEXEC | flags);
+
+    #[cfg(target_os = "android")]
+    if fd == -1 && matches!(errno(), ENOSYS | EPERM) && flags == 0 {
+        // memfd_create may be blocked by seccomp for apps, ashmem is always available
+        return magic_buf_create_ashmem(&memfd_name, len);
     }
 
+    #[cfg(target_os = "linux")]
+    let fd = if fd == -1 && errno() == ENOSYS && flags == 0 {
+        // memfd_create is not implemented, use a temporary file instead
+        magic_buf_create_tmpfile()
+    } else {
+        fd
+    };
+
     if fd == -1 {
-        return Err(MagicBufferError::OOM);
+        return Err(MagicBufferError::last_os_oom());
     }
 
     let fd = fd as c_int;
     if ftruncate(fd, len as off_t) == -1 {
+        let err = MagicBufferError::last_os_oom();
         assert_eq!(0, close(fd));
-        return Err(MagicBufferError::OOM);
+        return Err(err);
+    };
+
+    Ok(OwnedFd::from_raw_fd(fd))
+}
+
+/// Creates an unlinked temporary file with mkstemp, or returns -1 if that fails.
+#[cfg(target_os = "linux")]
+unsafe fn magic_buf_create_tmpfile() -> c_long {
+    // mkstemp replaces the trailing Xs of the template with the actual file name
+    let mut file_name = magic_buf_tmpfile_template();
+    let fd = c_long::from(mkostemp(file_name.as_mut_ptr() as _, O_CLOEXEC));
+    // unlink the file right away, it lives on until the fd is closed
+    if fd != -1 {
+        assert_eq!(0, unlink(file_name.as_ptr() as _));
+    }
+    fd
+}
+
+/// Returns the nul terminated mkstemp template for a file in the temp directory, as
+/// the current directory is not necessarily writable.
+#[cfg(target_os = "linux")]
+fn magic_buf_tmpfile_template() -> Vec<u8> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = std::env::temp_dir().join("magic_buffer-XXXXXX");
+    let mut template = path.as_os_str().as_bytes().to_vec();
+    template.push(0);
+    template
+}
+
+/// Creates an ashmem region of `len` bytes. Unlike a memfd, ashmem regions can't be
+/// resized after creation, so they are created with their final len right away.
+#[cfg(target_os = "android")]
+unsafe fn magic_buf_create_ashmem(name: &[u8], len: usize) -> Result<OwnedFd, MagicBufferError> {
+    let fd = ASharedMemory_create(name.as_ptr() as _, len);
+    if fd < 0 {
+        return Err(MagicBufferError::last_os_oom());
+    }
+
+    Ok(OwnedFd::from_raw_fd(fd))
+}
+
+pub(super) unsafe fn magic_buf_alloc(
+    options: &MagicBufferOptions,
+) -> Result<(*mut u8, MagicBufBacking, Option<usize>), MagicBufferError> {
+    let (addr, fd, huge_page_len) = match options.huge_pages {
+        Some(huge_pages) => magic_buf_alloc_huge(options, huge_pages)?,
+        None => {
+            let fd = magic_buf_create_memfd(options.len, 0, options.name.as_deref())?;
+
+            // the reservation beyond the views stays inaccessible and serves as guard page
+            let reserved_len =
+                options.len * options.mirror_count + magic_buf_guard_len(options.guard_page);
+            let hint = options.address_hint.unwrap_or(0) as *mut u8;
+            let addr = magic_buf_reserve_hinted(reserved_len, hint, options.fixed_address)?;
+            let views = magic_buf_map_views(
+                addr,
+                fd.as_raw_fd(),
+                0,
+                options.len,
+                options.mirror_count,
+                MAP_SHARED,
+            );
+            if let Err(err) = views {
+                magic_buf_unreserve(addr, reserved_len);
+                return Err(err);
+            }
+            (addr, fd, None)
+        }
+    };
+
+    if options.transparent_huge_pages {
+        // purely advisory, the buffer works just as well with regular pages. The advice
+        // is a property of the mapping, so every view needs it, not just the first one
+        madvise(addr as _, options.len * options.mirror_count, MADV_HUGEPAGE);
+    }
+
+    if let Some(node) = options.numa_node {
+        if let Err(err) = magic_buf_bind(addr, options.len, node) {
+            magic_buf_free(addr, options.len, options.mirror_count, options.guard_page);
+            return Err(err);
+        }
+    }
+
+    if options.fork_behavior == ForkBehavior::DontInherit {
+        // the views are MAP_SHARED, so a child would otherwise write to the same pages
+        if madvise(addr as _, options.len * options.mirror_count, MADV_DONTFORK) == -1 {
+            let err = MagicBufferError::last_os_oom();
+            magic_buf_free(addr, options.len, options.mirror_count, options.guard_page);
+            return Err(err);
+        }
+    }
+
+    Ok((addr, fd, huge_page_len))
+}
+
+/// Binds the pages of the first view to `node`. The policy is stored with the memfd, so
+/// it applies to the mirrors as well.
+unsafe fn magic_buf_bind(addr: *mut u8, len: usize, node: usize) -> Result<(), MagicBufferError> {
+    const WORD_BITS: usize = c_ulong::BITS as usize;
+
+    let mut nodemask = vec![0 as c_ulong; node / WORD_BITS + 1];
+    nodemask[node / WORD_BITS] |= 1 << (node % WORD_BITS);
+
+    // the kernel ignores the last bit of maxnode, so pass one more than the mask holds
+    let maxnode = nodemask.len() * WORD_BITS + 1;
+    let result = syscall(
+        SYS_mbind,
+        addr,
+        len,
+        MPOL_BIND,
+        nodemask.as_ptr(),
+        maxnode,
+        0,
+    );
+
+    // kernels without NUMA support don't implement mbind, there is nothing to bind to
+    if result == -1 && errno() != ENOSYS {
+        return Err(MagicBufferError::BindFailed {
+            source: io::Error::last_os_error(),
+        });
+    }
+
+    Ok(())
+}
+
+unsafe fn magic_buf_alloc_huge(
+    options: &MagicBufferOptions,
+    huge_pages: HugePageSize,
+) -> Result<(*mut u8, MagicBufBacking, Option<usize>), MagicBufferError> {
+    let page_len = huge_pages.bytes();
+    let flags = match huge_pages {
+        HugePageSize::Size2MiB => MFD_HUGETLB | MFD_HUGE_2MB,
+        HugePageSize::Size1GiB => MFD_HUGETLB | MFD_HUGE_1GB,
+    };
+
+    match magic_buf_alloc_aligned(options, page_len, flags) {
+        Ok((addr, fd)) => Ok((addr, fd, Some(page_len))),
+        Err(err) if options.strict => Err(err),
+        // huge pages may not be available (or exhausted), fall back to regular pages
+        Err(_) => magic_buf_alloc_aligned(options, page_len, 0).map(|(addr, fd)| (addr, fd, None)),
+    }
+}
+
+unsafe fn magic_buf_alloc_aligned(
+    options: &MagicBufferOptions,
+    align: usize,
+    flags: c_uint,
+) -> Result<(*mut u8, MagicBufBacking), MagicBufferError> {
+    let (len, mirrors) = (options.len, options.mirror_count);
+    let fd = magic_buf_create_memfd(len, flags, options.name.as_deref())?;
+
+    let reserved_len = len * mirrors + magic_buf_guard_len(options.guard_page);
+    let hint = options.address_hint.unwrap_or(0) as *mut u8;
+    let addr = magic_buf_reserve_aligned(reserved_len, align, hint, options.fixed_address)?;
+
+    if let Err(err) = magic_buf_map_views(addr, fd.as_raw_fd(), 0, len, mirrors, MAP_SHARED) {
+        assert_eq!(0, munmap(addr as _, reserved_len as size_t));
+        return Err(err);
+    }
+
+    Ok((addr, fd))
+}
+
+/// Reserves `len` bytes of address space at an `align` aligned address, preferably at
+/// `hint` if it is not null.
+unsafe fn magic_buf_reserve_aligned(
+    len: usize,
+    align: usize,
+    hint: *mut u8,
+    fixed: bool,
+) -> Result<*mut u8, MagicBufferError> {
+    if !hint.is_null() {
+        let addr = magic_buf_reserve_hinted(len, hint, fixed)?;
+        if addr as usize & (align - 1) == 0 {
+            return Ok(addr);
+        }
+        // the kernel ignored the hint, which only happens if the placement is not fixed
+        magic_buf_unreserve(addr, len);
+    }
+
+    // over-reserve and trim the reservation down to an aligned range
+    let reserved = magic_buf_reserve(len + align)?;
+    let offset = reserved.align_offset(align);
+    let addr = reserved.add(offset);
+    if offset > 0 {
+        magic_buf_unreserve(reserved, offset);
+    }
+    magic_buf_unreserve(addr.add(len), align - offset);
+
+    Ok(addr)
+}
+
+#[cfg(target_os = "android")]
+pub(super) unsafe fn magic_buf_open_shared(
+    _name: &CStr,
+    _len: usize,
+) -> Result<(*mut u8, MagicBufBacking), MagicBufferError> {
+    Err(MagicBufferError::Unsupported {
+        msg: "shared buffers are not supported on android".to_string(),
+    })
+}
+
+#[cfg(target_os = "linux")]
+pub(super) unsafe fn magic_buf_open_shared(
+    name: &CStr,
+    len: usize,
+) -> Result<(*mut u8, MagicBufBacking), MagicBufferError> {
+    let fd = shm_open(name.as_ptr(), O_CREAT | O_RDWR | O_CLOEXEC, 0o600);
+    if fd == -1 {
+        return Err(MagicBufferError::last_os_oom());
+    }
+
+    let fd = OwnedFd::from_raw_fd(fd);
+    magic_buf_size_shared(fd.as_raw_fd(), len)?;
+    let addr = magic_buf_map_fd(fd.as_raw_fd(), 0, len, 2, false)?;
+    Ok((addr, fd))
+}
+
+/// Sizes a newly created shared memory object to `len`, or checks that an existing
+/// one has been created with the same `len`.
+#[cfg(target_os = "linux")]
+unsafe fn magic_buf_size_shared(fd: c_int, len: usize) -> Result<(), MagicBufferError> {
+    let mut st = MaybeUninit::<stat>::uninit();
+    if fstat(fd, st.as_mut_ptr()) == -1 {
+        return Err(MagicBufferError::last_os_oom());
+    }
+
+    let size = st.assume_init().st_size as usize;
+    if size == 0 {
+        if ftruncate(fd, len as off_t) == -1 {
+            return Err(MagicBufferError::last_os_oom());
+        }
+    } else if size != len {
+        return Err(MagicBufferError::InvalidLen {
+            msg: format!("len must match the len of the shared buffer, {}", size),
+        });
+    }
+
+    Ok(())
+}
+
+#[cfg(target_os = "android")]
+pub(super) fn magic_buf_unlink_shared(_name: &CStr) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "shared buffers are not supported on android",
+    ))
+}
+
+#[cfg(target_os = "linux")]
+pub(super) fn magic_buf_unlink_shared(name: &CStr) -> io::Result<()> {
+    if unsafe { shm_unlink(name.as_ptr()) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+pub(super) unsafe fn magic_buf_map_file(
+    file: File,
+    len: usize,
+) -> Result<(*mut u8, MagicBufBacking), MagicBufferError> {
+    let fd = OwnedFd::from(file);
+    let addr = magic_buf_map_fd(fd.as_raw_fd(), 0, len, 2, false)?;
+    Ok((addr, fd))
+}
+
+pub(super) unsafe fn magic_buf_map_fd(
+    fd: c_int,
+    offset: off_t,
+    len: usize,
+    mirrors: usize,
+    sync: bool,
+) -> Result<*mut u8, MagicBufferError> {
+    // MAP_SYNC is only honored with MAP_SHARED_VALIDATE, which makes the kernel
+    // reject the mapping instead of silently ignoring the flag for non-DAX files
+    let flags = if sync {
+        MAP_SHARED_VALIDATE | MAP_SYNC
+    } else {
+        MAP_SHARED
+    };
+
+    // reserve the address space for all views first, so that the mirrors
+    // can't race with other mappings and the fd is never mapped beyond
+    // the requested range (which device fds reject)
+    let addr = magic_buf_reserve(len * mirrors)?;
+
+    if let Err(err) = magic_buf_map_views(addr, fd, offset, len, mirrors, flags) {
+        assert_eq!(0, munmap(addr as _, (len * mirrors) as size_t));
+        return Err(err);
+    }
+
+    Ok(addr)
+}
+
+unsafe fn magic_buf_map_views(
+    addr: *mut u8,
+    fd: c_int,
+    offset: off_t,
+    len: usize,
+    mirrors: usize,
+    flags: c_int,
+) -> Result<(), MagicBufferError> {
+    for view in (0..mirrors).map(|i| addr.add(i * len)) {
+        let ptr = mmap(
+            view as _,
+            len,
+            PROT_READ | PROT_WRITE,
+            flags | MAP_FIXED,
+            fd,
+            offset,
+        );
+
+        if ptr == MAP_FAILED {
+            if errno() == EOPNOTSUPP && flags & MAP_SYNC != 0 {
+                return Err(MagicBufferError::Unsupported {
+                    msg: "the backing does not support MAP_SYNC".to_string(),
+                });
+            }
+            return Err(MagicBufferError::last_os_oom());
+        }
+    }
+
+    Ok(())
+}
+
+pub(super) unsafe fn magic_buf_free(addr: *mut u8, len: usize, mirrors: usize, guard: bool) {
+    let mapping_len = len * mirrors + magic_buf_guard_len(guard);
+    assert_eq!(0, munmap(addr as _, mapping_len as size_t));
+}
+
+pub(super) unsafe fn magic_buf_flush(addr: *mut u8, len: usize, sync: bool) -> io::Result<()> {
+    let flags = if sync { MS_SYNC } else { MS_ASYNC };
+    if msync(addr as _, len, flags) == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+/// Makes all views of the buffer read-only, or readable and writable again.
+pub(super) unsafe fn magic_buf_protect(
+    addr: *mut u8,
+    len: usize,
+    mirrors: usize,
+    writable: bool,
+) -> Result<(), MagicBufferError> {
+    let prot = if writable {
+        PROT_READ | PROT_WRITE
+    } else {
+        PROT_READ
     };
+    if mprotect(addr as _, len * mirrors, prot) == -1 {
+        return Err(MagicBufferError::last_os_oom());
+    }
+
+    Ok(())
+}
+
+pub(super) unsafe fn magic_buf_lock(addr: *const u8, len: usize) -> Result<(), MagicBufferError> {
+    if mlock(addr as _, len) == -1 {
+        let source = io::Error::last_os_error();
+        if matches!(source.raw_os_error(), Some(EAGAIN | ENOMEM)) {
+            if let Some(limit) = magic_buf_lock_limit() {
+                return Err(MagicBufferError::LockLimitExceeded {
+                    requested: len,
+                    limit,
+                });
+            }
+        }
+
+        return Err(MagicBufferError::LockFailed { source });
+    }
+
+    Ok(())
+}
+
+unsafe fn magic_buf_lock_limit() -> Option<usize> {
+    let mut rlim = MaybeUninit::<rlimit>::uninit();
+    if getrlimit(RLIMIT_MEMLOCK, rlim.as_mut_ptr()) == -1 {
+        return None;
+    }
+
+    let rlim = rlim.assume_init();
+    if rlim.rlim_cur == RLIM_INFINITY {
+        return None;
+    }
 
-    // mmap memory
+    Some(rlim.rlim_cur as usize)
+}
+
+pub(super) unsafe fn magic_buf_unlock(addr: *const u8, len: usize) -> Result<(), MagicBufferError> {
+    if munlock(addr as _, len) == -1 {
+        return Err(MagicBufferError::LockFailed {
+            source: io::Error::last_os_error(),
+        });
+    }
+
+    Ok(())
+}
+
+pub(super) unsafe fn magic_buf_reserve(len: usize) -> Result<*mut u8, MagicBufferError> {
     let ptr = mmap(
         ptr::null_mut(),
-        len * 2,
-        PROT_READ | PROT_WRITE,
-        MAP_SHARED,
-        fd,
+        len,
+        PROT_NONE,
+        MAP_PRIVATE | MAP_ANONYMOUS | MAP_NORESERVE,
+        -1,
         0,
     );
 
     if ptr == MAP_FAILED {
-        assert_eq!(0, close(fd));
-        return Err(MagicBufferError::OOM);
+        return Err(MagicBufferError::last_os_oom());
+    }
+
+    Ok(ptr as *mut u8)
+}
+
+/// Reserves `len` bytes of address space at `hint`, or anywhere if `hint` is null. The
+/// kernel may place the reservation elsewhere unless it is `fixed`, which fails instead
+/// of replacing existing mappings.
+unsafe fn magic_buf_reserve_hinted(
+    len: usize,
+    hint: *mut u8,
+    fixed: bool,
+) -> Result<*mut u8, MagicBufferError> {
+    if hint.is_null() {
+        return magic_buf_reserve(len);
     }
 
-    let ptr2 = mmap(
-        (ptr as *mut u8).add(len) as _,
+    let flags = if fixed { MAP_FIXED_NOREPLACE } else { 0 };
+    let addr = mmap(
+        hint as _,
         len,
-        PROT_READ | PROT_WRITE,
-        MAP_SHARED | MAP_FIXED,
-        fd,
+        PROT_NONE,
+        MAP_PRIVATE | MAP_ANONYMOUS | MAP_NORESERVE | flags,
+        -1,
         0,
     );
 
-    if ptr2 == MAP_FAILED {
-        assert_eq!(0, munmap(ptr, (len * 2) as size_t));
-        assert_eq!(0, close(fd));
-        return Err(MagicBufferError::OOM);
+    if addr == MAP_FAILED {
+        if fixed {
+            return Err(MagicBufferError::AddressUnavailable);
+        }
+        return Err(MagicBufferError::last_os_oom());
     }
 
-    assert_eq!(0, close(fd));
-    Ok(ptr as *mut u8)
+    // kernels before 4.17 don't know MAP_FIXED_NOREPLACE and treat the address as a
+    // hint, so they may have placed the mapping somewhere else
+    let addr = addr as *mut u8;
+    if fixed && addr != hint {
+        magic_buf_unreserve(addr, len);
+        return Err(MagicBufferError::AddressUnavailable);
+    }
+
+    Ok(addr)
+}
+
+pub(super) unsafe fn magic_buf_unreserve(addr: *mut u8, len: usize) {
+    assert_eq!(0, munmap(addr as _, len as size_t));
+}
+
+#[cfg(target_os = "linux")]
+pub(super) unsafe fn magic_buf_alloc_at(
+    addr: *mut u8,
+    len: usize,
+) -> Result<MagicBufBacking, MagicBufferError> {
+    let fd = magic_buf_create_backing(len)?;
+    if let Err(err) = magic_buf_map_views(addr, fd.as_raw_fd(), 0, len, 2, MAP_SHARED) {
+        magic_buf_free_at(addr, len);
+        return Err(err);
+    }
+
+    Ok(fd)
+}
+
+#[cfg(target_os = "linux")]
+pub(super) unsafe fn magic_buf_free_at(addr: *mut u8, len: usize) {
+    // replace both views with an inaccessible mapping, which drops the
+    // backing memory but keeps the address range reserved
+    let ptr = mmap(
+        addr as _,
+        len * 2,
+        PROT_NONE,
+        MAP_PRIVATE | MAP_ANONYMOUS | MAP_NORESERVE | MAP_FIXED,
+        -1,
+        0,
+    );
+    assert_ne!(MAP_FAILED, ptr, "re-reserving the mapping failed");
+}
+
+#[cfg(target_os = "linux")]
+pub(super) fn magic_buf_clear_soft_dirty() -> io::Result<()> {
+    // see https://www.kernel.org/doc/Documentation/vm/soft-dirty.txt
+    fs::write("/proc/self/clear_refs", b"4")
 }
 
-pub(super) unsafe fn magic_buf_free(addr: *mut u8, len: usize) {
-    assert_eq!(0, munmap(addr as _, (len * 2) as size_t));
+#[cfg(target_os = "linux")]
+pub(super) unsafe fn magic_buf_soft_dirty_pages(
+    addr: *const u8,
+    len: usize,
+) -> io::Result<Vec<usize>> {
+    const ENTRY_LEN: usize = 8;
+    const SOFT_DIRTY: u64 = 1 << 55;
+
+    let page_len = magic_buf_min_len();
+    let pages = len / page_len;
+
+    // each view has its own page table entries, so a page written through the
+    // mirror is only marked as soft-dirty in the second half of the mapping
+    let mut entries = vec![0u8; 2 * pages * ENTRY_LEN];
+    let offset = (addr as usize / page_len * ENTRY_LEN) as u64;
+    File::open("/proc/self/pagemap")?.read_exact_at(&mut entries, offset)?;
+
+    let entry = |page: usize| {
+        let bytes = &entries[page * ENTRY_LEN..(page + 1) * ENTRY_LEN];
+        u64::from_ne_bytes(bytes.try_into().unwrap())
+    };
+
+    Ok((0..pages)
+        .filter(|page| (entry(*page) | entry(*page + pages)) & SOFT_DIRTY != 0)
+        .map(|page| page * page_len)
+        .collect())
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use std::os::unix::ffi::OsStrExt;
+
+    #[test]
+    fn tmpfile_template_is_in_temp_dir() {
+        let template = magic_buf_tmpfile_template();
+        let (path, nul) = template.split_at(template.len() - 1);
+        assert_eq!([0], nul);
+        assert!(path.ends_with(b"/magic_buffer-XXXXXX"));
+        assert!(path.starts_with(std::env::temp_dir().as_os_str().as_bytes()));
+    }
+
+    #[test]
+    fn tmpfile_fallback_creates_fd() {
+        let fd = unsafe { magic_buf_create_tmpfile() };
+        assert_ne!(-1, fd);
+        assert_eq!(0, unsafe { close(fd as c_int) });
+    }
 }
@@ -1,7 +1,7 @@
 // This implementation is based on
 // https://github.com/gnzlbg/slice_deque/blob/master/src/mirrored/macos.rs
 
-use crate::MagicBufferError;
+use crate::{MagicBufferError, MagicBufferOptions};
 
 use mach2::{
     boolean::boolean_t,
@@ -9,28 +9,93 @@ use mach2::{
     mach_types::mem_entry_name_port_t,
     memory_object_types::memory_object_size_t,
     traps::mach_task_self,
-    vm::{mach_make_memory_entry_64, mach_vm_allocate, mach_vm_deallocate, mach_vm_remap},
+    vm::{
+        mach_make_memory_entry_64, mach_vm_allocate, mach_vm_deallocate, mach_vm_protect,
+        mach_vm_remap,
+    },
     vm_inherit::VM_INHERIT_NONE,
     vm_page_size::vm_page_size,
-    vm_prot::{vm_prot_t, VM_PROT_READ, VM_PROT_WRITE},
+    vm_prot::{vm_prot_t, VM_PROT_NONE, VM_PROT_READ, VM_PROT_WRITE},
     vm_statistics::{VM_FLAGS_ANYWHERE, VM_FLAGS_FIXED, VM_FLAGS_OVERWRITE},
     vm_types::mach_vm_address_t,
 };
 
-use std::mem::MaybeUninit;
+use libc::{
+    c_int, c_uint, fstat, ftruncate, getrlimit, mlock, mmap, mprotect, msync, munlock, munmap,
+    off_t, rlimit, shm_open, shm_unlink, size_t, stat, EAGAIN, ENOMEM, MAP_ANON, MAP_FAILED,
+    MAP_FIXED, MAP_PRIVATE, MAP_SHARED, MS_ASYNC, MS_SYNC, O_CREAT, O_RDWR, PROT_NONE, PROT_READ,
+    PROT_WRITE, RLIMIT_MEMLOCK, RLIM_INFINITY,
+};
+use std::{
+    ffi::CStr,
+    fs::File,
+    io,
+    mem::MaybeUninit,
+    os::fd::{AsRawFd, FromRawFd, OwnedFd},
+    ptr,
+};
+
+/// The mirrored views are created from a memory entry, there is no backing object to keep.
+pub(super) type MagicBufBacking = ();
 
 pub(super) unsafe fn magic_buf_min_len() -> usize {
     vm_page_size
 }
 
-pub(super) unsafe fn magic_buf_alloc(len: usize) -> Result<*mut u8, MagicBufferError> {
+/// Returns the len of the inaccessible guard region after the views, one page if enabled.
+unsafe fn magic_buf_guard_len(guard: bool) -> usize {
+    if guard {
+        magic_buf_min_len()
+    } else {
+        0
+    }
+}
+
+pub(super) unsafe fn magic_buf_alloc(
+    options: &MagicBufferOptions,
+) -> Result<(*mut u8, MagicBufBacking, Option<usize>), MagicBufferError> {
+    if options.huge_pages.is_some() && options.strict {
+        return Err(MagicBufferError::Unsupported {
+            msg: "huge pages are only supported on linux".to_string(),
+        });
+    }
+
+    let len = options.len;
+    let mirrors = options.mirror_count;
+    let mapping_len = len * mirrors + magic_buf_guard_len(options.guard_page);
     let task = mach_task_self();
 
-    let mut addr: mach_vm_address_t = 0;
-    let result = mach_vm_allocate(task, &mut addr as _, (len * 2) as u64, VM_FLAGS_ANYWHERE);
+    // VM_FLAGS_FIXED without VM_FLAGS_OVERWRITE fails instead of replacing existing mappings
+    let hint = options.address_hint.unwrap_or(0) as mach_vm_address_t;
+    let mut addr = hint;
+    let placed = hint != 0
+        && mach_vm_allocate(task, &mut addr as _, mapping_len as u64, VM_FLAGS_FIXED)
+            == KERN_SUCCESS;
 
-    if result != KERN_SUCCESS {
-        return Err(MagicBufferError::OOM);
+    if !placed {
+        if hint != 0 && options.fixed_address {
+            return Err(MagicBufferError::AddressUnavailable);
+        }
+
+        addr = 0;
+        let result = mach_vm_allocate(task, &mut addr as _, mapping_len as u64, VM_FLAGS_ANYWHERE);
+        if result != KERN_SUCCESS {
+            return Err(MagicBufferError::OOM { code: Some(result) });
+        }
+    }
+
+    if options.guard_page {
+        // make the region after the views inaccessible, so that overruns fault
+        let guard = addr + (len * mirrors) as mach_vm_address_t;
+        let guard_len = (mapping_len - len * mirrors) as u64;
+        let result = mach_vm_protect(task, guard, guard_len, 0, VM_PROT_NONE);
+
+        if result != KERN_SUCCESS {
+            let err = MagicBufferError::OOM { code: Some(result) };
+            let result = mach_vm_deallocate(task, addr, mapping_len as u64);
+            assert_eq!(result, KERN_SUCCESS);
+            return Err(err);
+        }
     }
 
     let result = mach_vm_allocate(
@@ -41,7 +106,7 @@ pub(super) unsafe fn magic_buf_alloc(len: usize) -> Result<*mut u8, MagicBufferE
     );
 
     if result != KERN_SUCCESS {
-        return Err(MagicBufferError::OOM);
+        return Err(MagicBufferError::OOM { code: Some(result) });
     }
 
     let mut memory_object_size = len as memory_object_size_t;
@@ -56,38 +121,204 @@ pub(super) unsafe fn magic_buf_alloc(len: usize) -> Result<*mut u8, MagicBufferE
     );
 
     if result != KERN_SUCCESS {
-        let result = mach_vm_deallocate(task, addr, (len * 2) as u64);
+        let err = MagicBufferError::OOM { code: Some(result) };
+        let result = mach_vm_deallocate(task, addr, mapping_len as u64);
         assert_eq!(result, KERN_SUCCESS);
-        return Err(MagicBufferError::OOM);
+        return Err(err);
     }
 
-    let mut to = (addr as *mut u8).add(len) as mach_vm_address_t;
-    let mut current_prot = MaybeUninit::<vm_prot_t>::uninit();
-    let mut out_prot = MaybeUninit::<vm_prot_t>::uninit();
-    let result = mach_vm_remap(
-        task,
-        &mut to as _,
-        len as u64,
+    for i in 1..mirrors {
+        let mut to = (addr as *mut u8).add(i * len) as mach_vm_address_t;
+        let mut current_prot = MaybeUninit::<vm_prot_t>::uninit();
+        let mut out_prot = MaybeUninit::<vm_prot_t>::uninit();
+        let result = mach_vm_remap(
+            task,
+            &mut to as _,
+            len as u64,
+            0,
+            VM_FLAGS_FIXED | VM_FLAGS_OVERWRITE,
+            task,
+            addr,
+            0 as boolean_t,
+            current_prot.as_mut_ptr(),
+            out_prot.as_mut_ptr(),
+            VM_INHERIT_NONE,
+        );
+
+        if result != KERN_SUCCESS {
+            let err = MagicBufferError::OOM { code: Some(result) };
+            let result = mach_vm_deallocate(task, addr, mapping_len as u64);
+            assert_eq!(result, KERN_SUCCESS);
+            return Err(err);
+        }
+    }
+
+    Ok((addr as _, (), None))
+}
+
+pub(super) unsafe fn magic_buf_open_shared(
+    name: &CStr,
+    len: usize,
+) -> Result<(*mut u8, MagicBufBacking), MagicBufferError> {
+    // shm_open is variadic on macOS, so the mode has to be promoted explicitly
+    let fd = shm_open(name.as_ptr(), O_CREAT | O_RDWR, 0o600 as c_uint);
+    if fd == -1 {
+        return Err(MagicBufferError::last_os_oom());
+    }
+
+    // the mappings keep the shared memory object alive, the fd can be closed afterwards
+    let fd = OwnedFd::from_raw_fd(fd);
+    magic_buf_size_shared(fd.as_raw_fd(), len)?;
+
+    magic_buf_map_fd(fd.as_raw_fd(), len).map(|addr| (addr, ()))
+}
+
+pub(super) unsafe fn magic_buf_map_file(
+    file: File,
+    len: usize,
+) -> Result<(*mut u8, MagicBufBacking), MagicBufferError> {
+    // the mappings keep the file alive, it is closed when `file` is dropped
+    magic_buf_map_fd(file.as_raw_fd(), len).map(|addr| (addr, ()))
+}
+
+unsafe fn magic_buf_map_fd(fd: c_int, len: usize) -> Result<*mut u8, MagicBufferError> {
+    let addr = mmap(
+        ptr::null_mut(),
+        len * 2,
+        PROT_NONE,
+        MAP_PRIVATE | MAP_ANON,
+        -1,
         0,
-        VM_FLAGS_FIXED | VM_FLAGS_OVERWRITE,
-        task,
-        addr,
-        0 as boolean_t,
-        current_prot.as_mut_ptr(),
-        out_prot.as_mut_ptr(),
-        VM_INHERIT_NONE,
     );
 
-    if result != KERN_SUCCESS {
-        let result = mach_vm_deallocate(task, addr, (len * 2) as u64);
-        assert_eq!(result, KERN_SUCCESS);
-        return Err(MagicBufferError::OOM);
+    if addr == MAP_FAILED {
+        return Err(MagicBufferError::last_os_oom());
+    }
+
+    let addr = addr as *mut u8;
+    for view in [addr, addr.add(len)] {
+        let ptr = mmap(
+            view as _,
+            len,
+            PROT_READ | PROT_WRITE,
+            MAP_SHARED | MAP_FIXED,
+            fd,
+            0,
+        );
+
+        if ptr == MAP_FAILED {
+            let err = MagicBufferError::last_os_oom();
+            assert_eq!(0, munmap(addr as _, (len * 2) as size_t));
+            return Err(err);
+        }
     }
 
-    Ok(addr as _)
+    Ok(addr)
 }
 
-pub(super) unsafe fn magic_buf_free(addr: *mut u8, len: usize) {
-    let result = mach_vm_deallocate(mach_task_self(), addr as _, (len * 2) as u64);
+/// Sizes a newly created shared memory object to `len`, or checks that an existing
+/// one has been created with the same `len`.
+unsafe fn magic_buf_size_shared(fd: c_int, len: usize) -> Result<(), MagicBufferError> {
+    let mut st = MaybeUninit::<stat>::uninit();
+    if fstat(fd, st.as_mut_ptr()) == -1 {
+        return Err(MagicBufferError::last_os_oom());
+    }
+
+    let size = st.assume_init().st_size as usize;
+    if size == 0 {
+        if ftruncate(fd, len as off_t) == -1 {
+            return Err(MagicBufferError::last_os_oom());
+        }
+    } else if size < len {
+        // only reject smaller objects, macOS rounds their size up to whole pages
+        return Err(MagicBufferError::InvalidLen {
+            msg: format!("len must match the len of the shared buffer, {}", size),
+        });
+    }
+
+    Ok(())
+}
+
+pub(super) fn magic_buf_unlink_shared(name: &CStr) -> io::Result<()> {
+    if unsafe { shm_unlink(name.as_ptr()) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+pub(super) unsafe fn magic_buf_free(addr: *mut u8, len: usize, mirrors: usize, guard: bool) {
+    let mapping_len = len * mirrors + magic_buf_guard_len(guard);
+    let result = mach_vm_deallocate(mach_task_self(), addr as _, mapping_len as u64);
     assert_eq!(result, KERN_SUCCESS, "de-allocation failed");
 }
+
+pub(super) unsafe fn magic_buf_flush(addr: *mut u8, len: usize, sync: bool) -> io::Result<()> {
+    let flags = if sync { MS_SYNC } else { MS_ASYNC };
+    if msync(addr as _, len, flags) == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+/// Makes all views of the buffer read-only, or readable and writable again.
+pub(super) unsafe fn magic_buf_protect(
+    addr: *mut u8,
+    len: usize,
+    mirrors: usize,
+    writable: bool,
+) -> Result<(), MagicBufferError> {
+    let prot = if writable {
+        PROT_READ | PROT_WRITE
+    } else {
+        PROT_READ
+    };
+    if mprotect(addr as _, len * mirrors, prot) == -1 {
+        return Err(MagicBufferError::last_os_oom());
+    }
+
+    Ok(())
+}
+
+pub(super) unsafe fn magic_buf_lock(addr: *const u8, len: usize) -> Result<(), MagicBufferError> {
+    if mlock(addr as _, len) == -1 {
+        let source = io::Error::last_os_error();
+        if matches!(source.raw_os_error(), Some(EAGAIN | ENOMEM)) {
+            if let Some(limit) = magic_buf_lock_limit() {
+                return Err(MagicBufferError::LockLimitExceeded {
+                    requested: len,
+                    limit,
+                });
+            }
+        }
+
+        return Err(MagicBufferError::LockFailed { source });
+    }
+
+    Ok(())
+}
+
+unsafe fn magic_buf_lock_limit() -> Option<usize> {
+    let mut rlim = MaybeUninit::<rlimit>::uninit();
+    if getrlimit(RLIMIT_MEMLOCK, rlim.as_mut_ptr()) == -1 {
+        return None;
+    }
+
+    let rlim = rlim.assume_init();
+    if rlim.rlim_cur == RLIM_INFINITY {
+        return None;
+    }
+
+    Some(rlim.rlim_cur as usize)
+}
+
+pub(super) unsafe fn magic_buf_unlock(addr: *const u8, len: usize) -> Result<(), MagicBufferError> {
+    if munlock(addr as _, len) == -1 {
+        return Err(MagicBufferError::LockFailed {
+            source: io::Error::last_os_error(),
+        });
+    }
+
+    Ok(())
+}
@@ -0,0 +1,280 @@
+use crate::{MagicBuffer, MagicBufferError};
+
+/// The size of the huge pages to back a [`MagicBuffer`] with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HugePageSize {
+    /// 2MiB huge pages.
+    Size2MiB,
+    /// 1GiB huge pages.
+    Size1GiB,
+}
+
+impl HugePageSize {
+    /// Returns the size of a huge page in bytes.
+    pub fn bytes(self) -> usize {
+        match self {
+            HugePageSize::Size2MiB => 2 << 20,
+            HugePageSize::Size1GiB => 1 << 30,
+        }
+    }
+}
+
+/// Whether a child process created with `fork` inherits the mapping of a
+/// [`MagicBuffer`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum ForkBehavior {
+    /// The child inherits the mapping. As the mapping is shared, the parent and the
+    /// child keep reading and writing the same memory after the fork.
+    #[default]
+    Inherit,
+    /// The mapping is not available in the child, via `MADV_DONTFORK` on Linux. This is
+    /// ignored on other platforms.
+    DontInherit,
+}
+
+/// A builder to configure and allocate a [`MagicBuffer`].
+///
+/// ## Examples
+/// ```rust
+/// # use magic_buffer::*;
+/// let buf = MagicBufferOptions::new(MagicBuffer::min_len())
+///     .huge_pages(HugePageSize::Size2MiB)
+///     .len(2 << 20)
+///     .build()
+///     .unwrap();
+/// assert_eq!(2 << 20, buf.len());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MagicBufferOptions {
+    pub(crate) len: usize,
+    pub(crate) huge_pages: Option<HugePageSize>,
+    pub(crate) strict: bool,
+    pub(crate) lock: bool,
+    pub(crate) zero_on_drop: bool,
+    pub(crate) name: Option<String>,
+    pub(crate) fork_behavior: ForkBehavior,
+    pub(crate) mirror_count: usize,
+    pub(crate) guard_page: bool,
+    pub(crate) address_hint: Option<usize>,
+    pub(crate) fixed_address: bool,
+    pub(crate) numa_node: Option<usize>,
+    pub(crate) transparent_huge_pages: bool,
+    /// Whether `len` may be any multiple of the page size, see
+    /// [`MagicBuffer::with_exact_capacity`].
+    pub(crate) exact_len: bool,
+}
+
+impl MagicBufferOptions {
+    /// Creates a new set of options for a [`MagicBuffer`] of `len` bytes, see
+    /// [`MagicBuffer::new`] for the requirements on `len`.
+    pub fn new(len: usize) -> Self {
+        Self {
+            len,
+            huge_pages: None,
+            strict: false,
+            lock: false,
+            zero_on_drop: false,
+            name: None,
+            fork_behavior: ForkBehavior::Inherit,
+            mirror_count: 2,
+            guard_page: false,
+            address_hint: None,
+            fixed_address: false,
+            numa_node: None,
+            transparent_huge_pages: false,
+            exact_len: false,
+        }
+    }
+
+    /// Sets the length of the buffer in bytes.
+    pub fn len(&mut self, len: usize) -> &mut Self {
+        self.len = len;
+        self
+    }
+
+    /// Backs the buffer with huge pages of the given size. `len` must then be a multiple
+    /// of the huge page size, and the base address of the buffer is aligned to it.
+    ///
+    /// Huge pages reduce the TLB pressure of large buffers, but need to be reserved by
+    /// the system up front. This is supported on Linux, where huge pages are reserved
+    /// e.g. via `/proc/sys/vm/nr_hugepages`, and on Windows, where it uses large pages and
+    /// requires the `SeLockMemoryPrivilege` to be enabled for the process. The size must
+    /// be a multiple of the large page size on Windows. If huge pages are not available,
+    /// the buffer falls back to regular pages, unless [`MagicBufferOptions::strict`] is
+    /// set. Use [`MagicBuffer::page_size`] to find out which page size is actually used.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// # use magic_buffer::*;
+    /// let buf = MagicBufferOptions::new(2 << 20)
+    ///     .huge_pages(HugePageSize::Size2MiB)
+    ///     .build()
+    ///     .unwrap();
+    /// assert!(buf.page_size() == 2 << 20 || buf.page_size() == MagicBuffer::min_len());
+    /// ```
+    pub fn huge_pages(&mut self, size: HugePageSize) -> &mut Self {
+        self.huge_pages = Some(size);
+        self
+    }
+
+    /// Fails instead of falling back to regular pages if the buffer can't be backed by
+    /// huge pages, see [`MagicBufferOptions::huge_pages`].
+    pub fn strict(&mut self, strict: bool) -> &mut Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Locks the whole mapping into physical memory after allocating it, so that its
+    /// contents are never swapped to disk, e.g. for key material. The pages are
+    /// unlocked again when the buffer is dropped.
+    ///
+    /// This uses `mlock` on Linux and macOS, and `VirtualLock` on Windows, and counts
+    /// against `RLIMIT_MEMLOCK` on Linux and macOS.
+    pub fn lock(&mut self, lock: bool) -> &mut Self {
+        self.lock = lock;
+        self
+    }
+
+    /// Overwrites the contents of the buffer with zeros when it is dropped, before the
+    /// mapping is freed, so that sensitive data doesn't linger in the released pages.
+    /// The zeros are written with volatile writes that the compiler can't optimize away.
+    pub fn zero_on_drop(&mut self, zero_on_drop: bool) -> &mut Self {
+        self.zero_on_drop = zero_on_drop;
+        self
+    }
+
+    /// Names the memfd backing the buffer on Linux, which shows up in `/proc/<pid>/maps`
+    /// and `lsof` and makes it easier to tell buffers apart. The name defaults to
+    /// `magic_buffer`. It is truncated to the 249 bytes the kernel allows and at the
+    /// first null byte. Other platforms ignore the name.
+    pub fn name(&mut self, name: impl Into<String>) -> &mut Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Sets whether child processes created with `fork` inherit the mapping, see
+    /// [`ForkBehavior`]. By default they do, and share the memory with the parent
+    /// because the mapping is backed by shared memory, not copy-on-write pages.
+    pub fn fork_behavior(&mut self, fork_behavior: ForkBehavior) -> &mut Self {
+        self.fork_behavior = fork_behavior;
+        self
+    }
+
+    /// Sets how many times the `len` bytes of the buffer are mapped back to back. The
+    /// default of 2 allows contiguous slices of up to `len` bytes from any offset, a
+    /// `mirror_count` of `n` allows contiguous slices of up to `(n - 1) * len` bytes.
+    ///
+    /// Each mirror only costs address space, not physical memory, but the buffer reserves
+    /// `mirror_count * len` bytes of address space. Only Linux, macOS and the BSDs support
+    /// more than two mirrors, building the buffer fails with
+    /// [`MagicBufferError::Unsupported`] on other platforms.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// # use magic_buffer::*;
+    /// let len = MagicBuffer::min_len();
+    /// let mut buf = MagicBufferOptions::new(len).mirror_count(3).build().unwrap();
+    /// buf[0] = 1u8;
+    /// assert_eq!(2 * len, buf[1..2 * len + 1].len());
+    /// assert_eq!(1u8, buf[2 * len]);
+    /// ```
+    pub fn mirror_count(&mut self, mirror_count: usize) -> &mut Self {
+        self.mirror_count = mirror_count;
+        self
+    }
+
+    /// Reserves an inaccessible guard page after the `mirror_count * len` mirrored
+    /// region, so that accesses beyond the end of the mapping fault deterministically
+    /// instead of touching unrelated memory. This is meant for catching overruns during
+    /// development and costs an extra page of address space, on Windows one allocation
+    /// granularity. Accesses within the mirrored region still alias the buffer.
+    ///
+    /// The heap fallback doesn't support guard pages.
+    pub fn guard_page(&mut self, guard_page: bool) -> &mut Self {
+        self.guard_page = guard_page;
+        self
+    }
+
+    /// Requests the mapping at the address `addr`, e.g. to keep pointers into the buffer
+    /// within a known range. `addr` must be a multiple of the page size of the buffer.
+    ///
+    /// The address is only a hint, the operating system places the mapping elsewhere
+    /// if the range is in use, unless [`MagicBufferOptions::fixed_address`] is set. It is
+    /// passed to `mmap` on Linux and the BSDs, `mach_vm_allocate` on macOS and
+    /// `VirtualAlloc2` on Windows, and ignored by the heap fallback. A hint of 0 keeps the
+    /// default placement. Clones of the buffer are placed anywhere.
+    pub fn address_hint(&mut self, addr: usize) -> &mut Self {
+        self.address_hint = (addr != 0).then_some(addr);
+        self
+    }
+
+    /// Fails with [`MagicBufferError::AddressUnavailable`] instead of placing the mapping
+    /// elsewhere if the range at [`MagicBufferOptions::address_hint`] is in use. Existing
+    /// mappings are never replaced, on Linux the range is requested with
+    /// `MAP_FIXED_NOREPLACE`. This has no effect without an address hint.
+    pub fn fixed_address(&mut self, fixed: bool) -> &mut Self {
+        self.fixed_address = fixed;
+        self
+    }
+
+    /// Places the mapping at the base address `base` or fails, i.e. sets both
+    /// [`MagicBufferOptions::address_hint`] and [`MagicBufferOptions::fixed_address`].
+    ///
+    /// This is useful for layouts that require the buffer at a predictable address, e.g.
+    /// to embed absolute pointers into it. Existing mappings are never replaced, building
+    /// the buffer fails with [`MagicBufferError::AddressUnavailable`] if the range is in
+    /// use.
+    ///
+    /// ## Examples
+    /// ```rust
+    /// # use magic_buffer::*;
+    /// let len = MagicBuffer::min_len();
+    /// let buf = MagicBuffer::new(len).unwrap();
+    /// let err = MagicBufferOptions::new(len)
+    ///     .base_hint(buf.as_ptr(0) as usize)
+    ///     .build()
+    ///     .unwrap_err();
+    /// assert!(matches!(err, MagicBufferError::AddressUnavailable));
+    /// ```
+    pub fn base_hint(&mut self, base: usize) -> &mut Self {
+        self.address_hint(base).fixed_address(true)
+    }
+
+    /// Binds the physical pages of the buffer to the NUMA node `node` on Linux, so that
+    /// they are allocated close to the CPUs working on the buffer. The buffer is backed
+    /// by a memfd, i.e. shmem, which stores the policy with the shared object rather than
+    /// with the mapping, so binding the `len` bytes of one view covers the mirrors as well.
+    ///
+    /// This uses `mbind` with `MPOL_BIND`. Building the buffer fails with
+    /// [`MagicBufferError::BindFailed`] if the node doesn't exist, and the option is
+    /// ignored if the kernel doesn't support NUMA. Other platforms ignore the node.
+    pub fn numa_node(&mut self, node: usize) -> &mut Self {
+        self.numa_node = Some(node);
+        self
+    }
+
+    /// Advises the kernel to back the buffer with transparent huge pages on Linux, via
+    /// `madvise(MADV_HUGEPAGE)`. Unlike [`MagicBufferOptions::huge_pages`] this doesn't
+    /// require reserved huge pages or a huge page aligned `len`, and never fails the
+    /// allocation. The advice is stored with each mapping, so all views are advised.
+    ///
+    /// The buffer is backed by a memfd, i.e. shmem, so THP for it is not controlled by
+    /// `/sys/kernel/mm/transparent_hugepage/enabled` but by
+    /// `/sys/kernel/mm/transparent_hugepage/shmem_enabled`. This only has an effect if
+    /// the latter is set to `advise`, `always` or `within_size`. Other platforms ignore
+    /// the option.
+    pub fn transparent_huge_pages(&mut self, transparent_huge_pages: bool) -> &mut Self {
+        self.transparent_huge_pages = transparent_huge_pages;
+        self
+    }
+
+    /// Allocates a new [`MagicBuffer`] with these options.
+    ///
+    /// ## Errors
+    /// Will return a [`MagicBufferError`] if the options are invalid or the allocation fails.
+    /// If locking the mapping fails, it will return [`MagicBufferError::LockLimitExceeded`]
+    /// or [`MagicBufferError::LockFailed`].
+    pub fn build(&self) -> Result<MagicBuffer, MagicBufferError> {
+        MagicBuffer::from_options(self)
+    }
+}
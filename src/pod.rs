@@ -0,0 +1,20 @@
+/// Marker trait for plain old data types that can be safely reinterpreted from
+/// the raw bytes of a [`MagicBuffer`](crate::MagicBuffer).
+///
+/// # Safety
+/// Implementors must guarantee that every bit pattern, including all zeros, is a
+/// valid value of the type, that the type has no padding bytes, and that it has
+/// no interior mutability.
+pub unsafe trait Pod: Copy + 'static {}
+
+macro_rules! pod_impl {
+    ($($t:ty),*) => {
+        $(unsafe impl Pod for $t {})*
+    };
+}
+
+pod_impl!(u8, u16, u32, u64, u128, usize);
+pod_impl!(i8, i16, i32, i64, i128, isize);
+pod_impl!(f32, f64);
+
+unsafe impl<T: Pod, const N: usize> Pod for [T; N] {}
@@ -0,0 +1,338 @@
+use crate::{magic_buf_sync_mirror, MagicBuffer, Pod};
+use std::mem::size_of;
+
+/// A [`MagicRingQueue`] turns a [`MagicBuffer`] into a byte FIFO, similar to a
+/// `VecDeque<u8>`.
+///
+/// Bytes are pushed to the back and popped from the front of the queue. Thanks to the
+/// mirrored mapping, the queued bytes are always readable as a single contiguous
+/// slice, and every push and pop is a single copy, no matter where the queue wraps
+/// around. Unlike [`SpscBuffer`](crate::SpscBuffer), the queue is not split into
+/// halves and is used from one thread at a time.
+///
+/// A queue over a typed [`MagicBuffer<T>`] holds elements instead of bytes, and
+/// supports pushing and popping them one at a time, e.g. as a queue of audio samples.
+///
+/// # Examples
+/// ```
+/// # use magic_buffer::*;
+/// # fn main() -> Result<(), MagicBufferError> {
+/// let mut queue = MagicRingQueue::new(MagicBuffer::new(MagicBuffer::min_len())?);
+/// queue.push_back_slice(b"hello");
+/// queue.push_back_slice(b" world");
+/// assert_eq!(b"hello world", queue.as_slices());
+///
+/// let mut out = [0u8; 6];
+/// assert_eq!(6, queue.pop_front_slice(&mut out));
+/// assert_eq!(b"hello ", &out);
+/// assert_eq!(b"world", queue.as_slices());
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct MagicRingQueue<T = u8> {
+    buf: MagicBuffer<T>,
+    head: usize,
+    tail: usize,
+}
+
+impl<T: Pod> MagicRingQueue<T> {
+    /// Creates an empty queue on top of `buf`, which can hold up to `buf.capacity()`
+    /// elements. The contents of `buf` are ignored.
+    pub fn new(mut buf: MagicBuffer<T>) -> Self {
+        buf.len = buf.capacity;
+        Self {
+            buf,
+            head: 0,
+            tail: 0,
+        }
+    }
+
+    /// Appends `val` to the back of the queue, and returns `false` if the queue is full.
+    ///
+    /// ## Examples
+    /// ```
+    /// # use magic_buffer::*;
+    /// # fn main() -> Result<(), MagicBufferError> {
+    /// let len = MagicBuffer::min_len() / 4;
+    /// let mut queue = MagicRingQueue::new(MagicBuffer::<f32>::with_len(len)?);
+    /// assert!(queue.push(0.5));
+    /// assert!(queue.push(1.5));
+    /// assert_eq!(Some(0.5), queue.pop());
+    /// assert_eq!(&[1.5], queue.as_slices());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn push(&mut self, val: T) -> bool {
+        if self.free_space() == 0 {
+            return false;
+        }
+
+        let offset = self.buf.view_offset(self.tail);
+        unsafe {
+            self.buf.view_ptr(offset).write(val);
+            magic_buf_sync_mirror(
+                self.buf.addr,
+                self.buf.capacity * size_of::<T>(),
+                offset * size_of::<T>(),
+                size_of::<T>(),
+            );
+        }
+        self.tail = self.tail.wrapping_add(1);
+        true
+    }
+
+    /// Removes the element at the front of the queue and returns it, or `None` if the
+    /// queue is empty.
+    pub fn pop(&mut self) -> Option<T> {
+        if self.is_empty() {
+            return None;
+        }
+
+        let val = unsafe { self.buf.view_ptr(self.buf.view_offset(self.head)).read() };
+        self.head = self.head.wrapping_add(1);
+        Some(val)
+    }
+
+    /// Returns the queued elements as one contiguous slice, from the front to the back
+    /// of the queue. Unlike `VecDeque::as_slices`, the mirrored mapping never splits
+    /// them into two slices.
+    pub fn as_slices(&self) -> &[T] {
+        // the positions wrap around usize, so only their offset into the buffer is used
+        let start = self.buf.fast_mod(self.head);
+        &self.buf[start..start + self.len()]
+    }
+
+    /// Returns the number of queued elements.
+    pub fn len(&self) -> usize {
+        self.tail.wrapping_sub(self.head)
+    }
+
+    /// Returns `true` if no elements are queued.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the maximum number of elements the queue can hold.
+    pub fn capacity(&self) -> usize {
+        self.buf.capacity()
+    }
+
+    /// Returns the number of elements that can be pushed before the queue is full.
+    pub fn free_space(&self) -> usize {
+        self.capacity() - self.len()
+    }
+
+    /// Removes all elements from the queue.
+    pub fn clear(&mut self) {
+        self.head = self.tail;
+    }
+
+    /// Consumes the queue and returns the underlying [`MagicBuffer`].
+    pub fn into_inner(self) -> MagicBuffer<T> {
+        self.buf
+    }
+}
+
+impl MagicRingQueue {
+    /// Appends as much of `src` to the back of the queue as there is free space, and
+    /// returns the number of bytes pushed.
+    pub fn push_back_slice(&mut self, src: &[u8]) -> usize {
+        let len = src.len().min(self.free_space());
+        self.buf.write_at(self.tail, &src[..len]);
+        self.tail = self.tail.wrapping_add(len);
+        len
+    }
+
+    /// Removes as many bytes from the front of the queue as are queued and fit into
+    /// `dst`, and returns the number of bytes popped.
+    pub fn pop_front_slice(&mut self, dst: &mut [u8]) -> usize {
+        let len = dst.len().min(self.len());
+        self.buf.read_at(self.head, &mut dst[..len]);
+        self.head = self.head.wrapping_add(len);
+        len
+    }
+
+    /// Returns `n` bytes of free space at the back of the queue as one contiguous slice,
+    /// or `None` if fewer than `n` bytes are free.
+    ///
+    /// This allows to encode data directly into the queue without an intermediate
+    /// buffer. The bytes are only appended to the queue once they are committed with
+    /// [`MagicRingQueue::commit`]. The slice has stale contents, it is not zeroed.
+    ///
+    /// ## Examples
+    /// ```
+    /// # use magic_buffer::*;
+    /// # fn main() -> Result<(), MagicBufferError> {
+    /// let mut queue = MagicRingQueue::new(MagicBuffer::new(MagicBuffer::min_len())?);
+    /// let dst = queue.reserve(4).unwrap();
+    /// dst.copy_from_slice(&42u32.to_be_bytes());
+    /// queue.commit(4);
+    /// assert_eq!(&[0, 0, 0, 42], queue.as_slices());
+    /// assert!(queue.reserve(MagicBuffer::min_len()).is_none());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn reserve(&mut self, n: usize) -> Option<&mut [u8]> {
+        if n > self.free_space() {
+            return None;
+        }
+
+        let start = self.buf.fast_mod(self.tail);
+        Some(&mut self.buf[start..start + n])
+    }
+
+    /// Appends the first `n` bytes of the space returned by [`MagicRingQueue::reserve`]
+    /// to the back of the queue.
+    ///
+    /// ## Panics
+    /// Will panic if `n` exceeds the free space of the queue.
+    pub fn commit(&mut self, n: usize) {
+        if n > self.free_space() {
+            panic!("out of bounds")
+        }
+
+        // the reserved slice is written like any other slice, so the mirror has to be
+        // brought up to date on targets without mirrored mappings
+        let start = self.buf.fast_mod(self.tail);
+        unsafe { magic_buf_sync_mirror(self.buf.addr, self.buf.capacity, start, n) };
+        self.tail = self.tail.wrapping_add(n);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+
+    const VALID_BUF_LEN: usize = 1 << 16;
+
+    #[test]
+    fn push_until_full() {
+        let buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        let mut queue = MagicRingQueue::new(buf);
+
+        let src = vec![1u8; VALID_BUF_LEN + 1];
+        assert_eq!(VALID_BUF_LEN, queue.push_back_slice(&src));
+        assert_eq!(0, queue.free_space());
+        assert_eq!(0, queue.push_back_slice(&src));
+
+        let mut dst = vec![0u8; 10];
+        assert_eq!(10, queue.pop_front_slice(&mut dst));
+        assert_eq!(10, queue.push_back_slice(b"abcdefghijk"));
+        assert_eq!(b"abcdefghij", &queue.as_slices()[VALID_BUF_LEN - 10..]);
+    }
+
+    #[test]
+    fn positions_wrap_around_usize() {
+        let buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        let mut queue = MagicRingQueue::new(buf);
+        queue.head = usize::MAX - 2;
+        queue.tail = usize::MAX - 2;
+
+        assert_eq!(6, queue.push_back_slice(b"abcdef"));
+        assert_eq!(b"abcdef", queue.as_slices());
+
+        let mut dst = [0u8; 4];
+        assert_eq!(4, queue.pop_front_slice(&mut dst));
+        assert_eq!(b"abcd", &dst);
+        assert_eq!(b"ef", queue.as_slices());
+    }
+
+    #[test]
+    fn reserve_across_wrap_boundary() {
+        let buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        let mut queue = MagicRingQueue::new(buf);
+        queue.head = VALID_BUF_LEN - 2;
+        queue.tail = VALID_BUF_LEN - 2;
+
+        let dst = queue.reserve(4).expect("should reserve space");
+        assert_eq!(4, dst.len());
+        dst.copy_from_slice(b"abcd");
+        assert!(queue.is_empty());
+
+        queue.commit(3);
+        assert_eq!(b"abc", queue.as_slices());
+        assert_eq!(VALID_BUF_LEN - 3, queue.free_space());
+    }
+
+    #[test]
+    fn reserve_checks_free_space() {
+        let buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        let mut queue = MagicRingQueue::new(buf);
+        assert_eq!(
+            Some(VALID_BUF_LEN),
+            queue.reserve(VALID_BUF_LEN).map(|dst| dst.len())
+        );
+        assert!(queue.reserve(VALID_BUF_LEN + 1).is_none());
+
+        queue.commit(VALID_BUF_LEN);
+        assert_eq!(Some(0), queue.reserve(0).map(|dst| dst.len()));
+        assert!(queue.reserve(1).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "out of bounds")]
+    fn commit_beyond_free_space() {
+        let buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        let mut queue = MagicRingQueue::new(buf);
+        queue.commit(VALID_BUF_LEN + 1);
+    }
+
+    #[test]
+    fn push_and_pop_elements() {
+        let len = MagicBuffer::min_len() / 4;
+        let buf = MagicBuffer::<f32>::with_len(len).expect("should allocate buffer");
+        let mut queue = MagicRingQueue::new(buf);
+        queue.head = len - 1;
+        queue.tail = len - 1;
+
+        for i in 0..len {
+            assert!(queue.push(i as f32));
+        }
+        assert!(!queue.push(-1.0));
+        assert_eq!(len, queue.len());
+        assert_eq!(1.0, queue.as_slices()[1]);
+
+        for i in 0..len {
+            assert_eq!(Some(i as f32), queue.pop());
+        }
+        assert_eq!(None, queue.pop());
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn matches_vec_deque() {
+        let buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        let mut queue = MagicRingQueue::new(buf);
+        let mut oracle = VecDeque::<u8>::new();
+
+        // xorshift, to get reproducible pseudo random sizes without a dependency
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        let mut next = move |max: usize| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state % max as u64) as usize
+        };
+
+        let mut dst = vec![0u8; VALID_BUF_LEN];
+        for round in 0..2000 {
+            let src = (0..next(VALID_BUF_LEN / 2))
+                .map(|i| (round + i) as u8)
+                .collect::<Vec<_>>();
+            let pushed = queue.push_back_slice(&src);
+            assert_eq!(src.len().min(VALID_BUF_LEN - oracle.len()), pushed);
+            oracle.extend(&src[..pushed]);
+
+            let len = next(VALID_BUF_LEN / 2);
+            let popped = queue.pop_front_slice(&mut dst[..len]);
+            assert_eq!(len.min(oracle.len()), popped);
+            assert!(oracle.drain(..popped).eq(dst[..popped].iter().copied()));
+
+            assert_eq!(oracle.len(), queue.len());
+            assert_eq!(VALID_BUF_LEN - oracle.len(), queue.free_space());
+            assert!(oracle.iter().eq(queue.as_slices().iter()));
+        }
+    }
+}
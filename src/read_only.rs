@@ -0,0 +1,116 @@
+use crate::{magic_buf_protect, MagicBuffer, MagicBufferError};
+use std::ops::{Deref, Index};
+
+/// A [`ReadOnlyMagicBuffer`] is a [`MagicBuffer`] whose mapping has been made read-only
+/// with [`MagicBuffer::freeze`].
+///
+/// The operating system enforces the immutability, so a stray write through a pointer
+/// into the buffer faults deterministically instead of silently corrupting the data.
+/// The buffer derefs into a slice and supports the same wrap-around indexing and
+/// slicing as a [`MagicBuffer`], but doesn't expose any mutable accessors.
+///
+/// # Examples
+/// ```
+/// # use magic_buffer::*;
+/// # fn main() -> Result<(), MagicBufferError> {
+/// let len = MagicBuffer::min_len();
+/// let mut buf = MagicBuffer::new(len)?;
+/// buf[len - 1] = b'a';
+///
+/// let buf = buf.freeze()?;
+/// assert_eq!(b"a\0", &buf[len - 1..len + 1]);
+/// # Ok(())
+/// # }
+/// ```
+///
+/// Mutable accessors are not available:
+/// ```compile_fail
+/// # use magic_buffer::*;
+/// let mut buf = MagicBuffer::new(MagicBuffer::min_len()).unwrap().freeze().unwrap();
+/// buf[0] = b'a';
+/// ```
+#[derive(Debug)]
+pub struct ReadOnlyMagicBuffer {
+    buf: MagicBuffer,
+}
+
+#[allow(clippy::len_without_is_empty)]
+impl ReadOnlyMagicBuffer {
+    pub(crate) fn new(buf: MagicBuffer) -> Result<Self, MagicBufferError> {
+        unsafe { magic_buf_protect(buf.addr, buf.capacity, buf.mirror_count(), false) }?;
+        Ok(Self { buf })
+    }
+
+    /// Returns the length of this [`ReadOnlyMagicBuffer`].
+    pub fn len(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// Returns an unsafe pointer to the buffer. See [`MagicBuffer::as_ptr`], the memory
+    /// must not be written to through this pointer.
+    pub fn as_ptr(&self, offset: usize) -> *const u8 {
+        self.buf.as_ptr(offset)
+    }
+}
+
+impl Drop for ReadOnlyMagicBuffer {
+    fn drop(&mut self) {
+        // dropping the buffer may zero it or write to it otherwise, which would fault
+        let buf = &self.buf;
+        let _ = unsafe { magic_buf_protect(buf.addr, buf.capacity, buf.mirror_count(), true) };
+    }
+}
+
+impl Deref for ReadOnlyMagicBuffer {
+    type Target = [u8];
+
+    fn deref(&self) -> &Self::Target {
+        &self.buf
+    }
+}
+
+impl AsRef<[u8]> for ReadOnlyMagicBuffer {
+    fn as_ref(&self) -> &[u8] {
+        &self.buf
+    }
+}
+
+impl<I> Index<I> for ReadOnlyMagicBuffer
+where
+    MagicBuffer: Index<I>,
+{
+    type Output = <MagicBuffer as Index<I>>::Output;
+
+    fn index(&self, index: I) -> &Self::Output {
+        &self.buf[index]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MagicBufferOptions;
+
+    const VALID_BUF_LEN: usize = 1 << 16;
+
+    #[test]
+    fn keeps_contents() {
+        let mut buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        buf.write_all_wrapping(VALID_BUF_LEN - 2, b"abcd");
+
+        let buf = buf.freeze().expect("should freeze buffer");
+        assert_eq!(VALID_BUF_LEN, buf.len());
+        assert_eq!(b"abcd", &buf[VALID_BUF_LEN - 2..VALID_BUF_LEN + 2]);
+        assert_eq!(b'c', buf[0]);
+        assert_eq!(b"cd", &buf[..2]);
+    }
+
+    #[test]
+    fn drops_with_zero_on_drop() {
+        let buf = MagicBufferOptions::new(VALID_BUF_LEN)
+            .zero_on_drop(true)
+            .build()
+            .expect("should allocate buffer");
+        drop(buf.freeze().expect("should freeze buffer"));
+    }
+}
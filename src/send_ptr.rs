@@ -0,0 +1,32 @@
+/// A raw pointer into a [`MagicBuffer`](crate::MagicBuffer) that can be sent to and
+/// shared with other threads.
+///
+/// Raw pointers are neither `Send` nor `Sync`, even when they point into memory that
+/// may be accessed from any thread, like a [`MagicBuffer`](crate::MagicBuffer). This
+/// wrapper allows to move such a pointer into a spawned thread. It is returned by
+/// [`MagicBuffer::as_send_ptr`](crate::MagicBuffer::as_send_ptr) and
+/// [`MagicBuffer::as_send_mut_ptr`](crate::MagicBuffer::as_send_mut_ptr).
+///
+/// Dereferencing the pointer carries the same obligations as for the raw pointer: the
+/// buffer must outlive every use of it, and accesses from different threads must not
+/// race with each other.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SendPtr<P> {
+    ptr: P,
+}
+
+unsafe impl Send for SendPtr<*const u8> {}
+unsafe impl Sync for SendPtr<*const u8> {}
+unsafe impl Send for SendPtr<*mut u8> {}
+unsafe impl Sync for SendPtr<*mut u8> {}
+
+impl<P: Copy> SendPtr<P> {
+    pub(crate) fn new(ptr: P) -> Self {
+        Self { ptr }
+    }
+
+    /// Returns the wrapped pointer.
+    pub fn get(self) -> P {
+        self.ptr
+    }
+}
@@ -0,0 +1,105 @@
+use crate::MagicBuffer;
+use serde::{
+    de::{self, SeqAccess, Visitor},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+use std::fmt;
+
+/// Serializes the `len` bytes of the buffer, starting at offset 0, as a byte array.
+/// The mirrored region is the same memory and is not serialized.
+impl Serialize for MagicBuffer {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(self)
+    }
+}
+
+/// Deserializes a byte array into a new buffer with the smallest valid capacity that
+/// fits it, like [`MagicBuffer::from_slice`]. The `len` of the buffer is the number of
+/// deserialized bytes. Failing to allocate the buffer is reported as a custom error.
+impl<'de> Deserialize<'de> for MagicBuffer {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_bytes(MagicBufferVisitor)
+    }
+}
+
+struct MagicBufferVisitor;
+
+impl MagicBufferVisitor {
+    fn from_slice<E: de::Error>(data: &[u8]) -> Result<MagicBuffer, E> {
+        let mut buf = MagicBuffer::from_slice(data).map_err(E::custom)?;
+        buf.truncate(data.len());
+        Ok(buf)
+    }
+}
+
+impl<'de> Visitor<'de> for MagicBufferVisitor {
+    type Value = MagicBuffer;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a byte array")
+    }
+
+    fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<Self::Value, E> {
+        Self::from_slice(v)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        // text formats like JSON represent bytes as a sequence of numbers
+        let mut data = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(b) = seq.next_element::<u8>()? {
+            data.push(b);
+        }
+
+        Self::from_slice(&data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip_bincode() {
+        let mut buf = MagicBuffer::from_slice(b"hello").expect("should allocate buffer");
+        buf.truncate(5);
+
+        let bytes = bincode::serialize(&buf).expect("should serialize buffer");
+        assert_eq!(8 + 5, bytes.len());
+
+        let de: MagicBuffer = bincode::deserialize(&bytes).expect("should deserialize buffer");
+        assert_eq!(b"hello", &*de);
+        assert_eq!(MagicBuffer::min_len(), de.capacity());
+    }
+
+    #[test]
+    fn round_trip_json() {
+        let mut buf = MagicBuffer::new(MagicBuffer::min_len()).expect("should allocate buffer");
+        buf.truncate(0);
+        buf.extend_from_slice(&[1, 2, 3]);
+
+        let json = serde_json::to_string(&buf).expect("should serialize buffer");
+        assert_eq!("[1,2,3]", json);
+
+        let de: MagicBuffer = serde_json::from_str(&json).expect("should deserialize buffer");
+        assert_eq!(buf, de);
+    }
+
+    #[test]
+    fn round_trip_full_buffer() {
+        let mut buf = MagicBuffer::new(2 * MagicBuffer::min_len()).expect("should allocate buffer");
+        buf.write_all_wrapping(7, b"abcd");
+
+        let bytes = bincode::serialize(&buf).expect("should serialize buffer");
+        let de: MagicBuffer = bincode::deserialize(&bytes).expect("should deserialize buffer");
+        assert_eq!(buf, de);
+        assert_eq!(buf.capacity(), de.capacity());
+    }
+
+    #[test]
+    fn deserialize_invalid_input() {
+        let err = serde_json::from_str::<MagicBuffer>("[1,256]").expect_err("should reject input");
+        assert!(err.is_data());
+        let err = serde_json::from_str::<MagicBuffer>("{}").expect_err("should reject input");
+        assert!(err.to_string().contains("a byte array"));
+    }
+}
@@ -0,0 +1,176 @@
+use crate::{magic_buf_sync_mirror, magic_buf_sync_written, MagicBuffer};
+use std::{
+    ptr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+/// A [`SpscBuffer`] turns a [`MagicBuffer`] into a lock-free single-producer
+/// single-consumer byte queue.
+///
+/// The buffer is split into a [`Producer`] and a [`Consumer`] half, which can be moved
+/// to different threads. Thanks to the mirrored mapping, the producer always writes
+/// and the consumer always reads a single contiguous region, no matter where the
+/// queue wraps around. The read and write positions are published with release
+/// stores and observed with acquire loads, so bytes are visible to the consumer
+/// before the write position that covers them.
+///
+/// # Examples
+/// ```
+/// # use magic_buffer::*;
+/// # fn main() -> Result<(), MagicBufferError> {
+/// let (mut producer, mut consumer) = SpscBuffer::split(MagicBuffer::new(MagicBuffer::min_len())?);
+/// std::thread::spawn(move || producer.write_slice(b"hello"))
+///     .join()
+///     .unwrap();
+///
+/// let mut out = [0u8; 8];
+/// assert_eq!(5, consumer.read_slice(&mut out));
+/// assert_eq!(b"hello", &out[..5]);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct SpscBuffer {
+    buf: MagicBuffer,
+    read_pos: AtomicUsize,
+    write_pos: AtomicUsize,
+}
+
+impl SpscBuffer {
+    /// Splits `buf` into the [`Producer`] and [`Consumer`] halves of a queue. The queue
+    /// starts out empty and can hold up to `buf.capacity()` bytes.
+    pub fn split(buf: MagicBuffer) -> (Producer, Consumer) {
+        // the halves copy concurrently, so pending writes are mirrored up front
+        unsafe { magic_buf_sync_written(buf.addr, buf.capacity) };
+        let inner = Arc::new(Self {
+            buf,
+            read_pos: AtomicUsize::new(0),
+            write_pos: AtomicUsize::new(0),
+        });
+
+        (
+            Producer {
+                inner: inner.clone(),
+            },
+            Consumer { inner },
+        )
+    }
+}
+
+/// The writing half of a [`SpscBuffer`].
+#[derive(Debug)]
+pub struct Producer {
+    inner: Arc<SpscBuffer>,
+}
+
+impl Producer {
+    /// Writes as much of `src` into the queue as there is free space, and returns the
+    /// number of bytes written.
+    pub fn write_slice(&mut self, src: &[u8]) -> usize {
+        let buf = &self.inner.buf;
+        let write_pos = self.inner.write_pos.load(Ordering::Relaxed);
+        let read_pos = self.inner.read_pos.load(Ordering::Acquire);
+
+        let free = buf.capacity - write_pos.wrapping_sub(read_pos);
+        let len = src.len().min(free);
+
+        // SAFETY: the consumer never touches the free region of the queue
+        let offset = buf.view_offset(write_pos);
+        unsafe {
+            ptr::copy_nonoverlapping(src.as_ptr(), buf.view_ptr(offset), len);
+            magic_buf_sync_mirror(buf.addr, buf.capacity, offset, len);
+        }
+
+        self.inner
+            .write_pos
+            .store(write_pos.wrapping_add(len), Ordering::Release);
+        len
+    }
+}
+
+/// The reading half of a [`SpscBuffer`].
+#[derive(Debug)]
+pub struct Consumer {
+    inner: Arc<SpscBuffer>,
+}
+
+impl Consumer {
+    /// Reads as many bytes from the queue into `dst` as are available and fit, and
+    /// returns the number of bytes read.
+    pub fn read_slice(&mut self, dst: &mut [u8]) -> usize {
+        let buf = &self.inner.buf;
+        let read_pos = self.inner.read_pos.load(Ordering::Relaxed);
+        let write_pos = self.inner.write_pos.load(Ordering::Acquire);
+
+        let available = write_pos.wrapping_sub(read_pos);
+        let len = dst.len().min(available);
+
+        // SAFETY: the producer never touches the filled region of the queue
+        unsafe {
+            ptr::copy_nonoverlapping(
+                buf.view_ptr(buf.view_offset(read_pos)),
+                dst.as_mut_ptr(),
+                len,
+            );
+        }
+
+        self.inner
+            .read_pos
+            .store(read_pos.wrapping_add(len), Ordering::Release);
+        len
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VALID_BUF_LEN: usize = 1 << 16;
+
+    #[test]
+    fn write_until_full() {
+        let buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        let (mut producer, mut consumer) = SpscBuffer::split(buf);
+
+        let src = vec![1u8; VALID_BUF_LEN + 1];
+        assert_eq!(VALID_BUF_LEN, producer.write_slice(&src));
+        assert_eq!(0, producer.write_slice(&src));
+
+        let mut dst = vec![0u8; 10];
+        assert_eq!(10, consumer.read_slice(&mut dst));
+        assert_eq!(10, producer.write_slice(&src));
+    }
+
+    #[test]
+    fn transfer_across_threads() {
+        const TOTAL: usize = 8 << 20;
+
+        let buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        let (mut producer, mut consumer) = SpscBuffer::split(buf);
+
+        let writer = std::thread::spawn(move || {
+            let src = (0..TOTAL).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+            let mut written = 0;
+            while written < TOTAL {
+                let end = (written + 3001).min(TOTAL);
+                written += producer.write_slice(&src[written..end]);
+            }
+        });
+
+        let mut dst = vec![0u8; 4999];
+        let mut read = 0;
+        while read < TOTAL {
+            let len = consumer.read_slice(&mut dst);
+            for (i, b) in dst[..len].iter().enumerate() {
+                assert_eq!(((read + i) % 251) as u8, *b);
+            }
+            read += len;
+        }
+
+        writer.join().unwrap();
+        assert_eq!(0, consumer.read_slice(&mut dst));
+    }
+}
@@ -0,0 +1,250 @@
+use crate::{len_mask, MagicBuffer, Pod, NO_MASK};
+use std::{
+    marker::PhantomData,
+    mem::{align_of, size_of},
+    ops::{Deref, Index},
+    ptr::{slice_from_raw_parts, NonNull},
+};
+
+/// A [`TypedView`] provides typed access to the contents of a [`MagicBuffer`]
+/// without consuming or converting it.
+///
+/// The view derefs into a slice of `T`, and supports wrap-around indexing and
+/// slicing at element granularity, just like the [`MagicBuffer`] it borrows from
+/// does for bytes. Multiple views of different types can borrow the same buffer.
+/// The view covers the filled `len` bytes of the buffer, so it is empty if fewer than
+/// `size_of::<T>()` bytes are filled.
+///
+/// # Examples
+/// ```
+/// # use magic_buffer::*;
+/// # fn main() -> Result<(), MagicBufferError> {
+/// let len = MagicBuffer::min_len();
+/// let mut buf = MagicBuffer::new(len)?;
+/// buf[0] = 1u8;
+///
+/// let view = buf.view::<u32>();
+/// assert_eq!(len / 4, view.len());
+/// assert_eq!(u32::from_ne_bytes([1, 0, 0, 0]), view[len / 4]);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct TypedView<'a, T> {
+    addr: *const T,
+    len: usize,
+    /// The number of elements after which indices wrap around.
+    capacity: usize,
+    mask: usize,
+    /// The element that index 0 maps to, following the rotation of the buffer.
+    base: usize,
+    _marker: PhantomData<&'a [T]>,
+}
+
+impl<'a, T: Pod> TypedView<'a, T> {
+    pub(crate) fn new(buf: &'a MagicBuffer) -> Self {
+        assert!(
+            size_of::<T>().is_power_of_two(),
+            "element size must be a power of two"
+        );
+        assert!(
+            align_of::<T>() <= MagicBuffer::min_len(),
+            "element alignment must not exceed the page size"
+        );
+
+        if buf.len() < size_of::<T>() {
+            return Self {
+                addr: NonNull::dangling().as_ptr(),
+                len: 0,
+                capacity: 0,
+                mask: NO_MASK,
+                base: 0,
+                _marker: PhantomData,
+            };
+        }
+
+        let base = buf.view_offset(0);
+        assert!(
+            base % size_of::<T>() == 0,
+            "rotation must be a multiple of the element size"
+        );
+
+        let capacity = buf.capacity() / size_of::<T>();
+        Self {
+            addr: buf.view_ptr(0).cast_const().cast(),
+            len: buf.len() / size_of::<T>(),
+            capacity,
+            mask: len_mask(capacity),
+            base: base / size_of::<T>(),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Returns a slice of `len` elements starting at element `offset`. The slice
+    /// may wrap around the end of the buffer.
+    ///
+    /// ## Panics
+    /// Will panic if `len` is larger than the number of elements in the view, or if the
+    /// slice extends beyond the `len` of a view over a truncated buffer.
+    pub fn slice(&self, offset: usize, len: usize) -> &'a [T] {
+        if len > self.len || !self.in_bounds(offset, len) {
+            panic!("out of bounds")
+        }
+
+        if len == 0 {
+            return &[];
+        }
+
+        unsafe { &*(slice_from_raw_parts(self.addr.add(self.wrap(offset)), len)) }
+    }
+
+    /// Returns whether the `len` elements starting at `offset` are in bounds. Offsets
+    /// wrap around a full buffer, while the elements beyond the `len` of a truncated
+    /// buffer are out of bounds.
+    #[inline(always)]
+    fn in_bounds(&self, offset: usize, len: usize) -> bool {
+        self.len == self.capacity || (self.len != 0 && offset % self.capacity + len <= self.len)
+    }
+
+    /// Returns the element in the first view that `offset` maps to.
+    #[inline(always)]
+    fn wrap(&self, offset: usize) -> usize {
+        if self.mask != NO_MASK {
+            self.base.wrapping_add(offset) & self.mask
+        } else {
+            (self.base + offset % self.capacity) % self.capacity
+        }
+    }
+}
+
+impl<'a, T: Pod> Deref for TypedView<'a, T> {
+    type Target = [T];
+
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*(slice_from_raw_parts(self.addr.add(self.base), self.len)) }
+    }
+}
+
+impl<'a, T: Pod> Index<usize> for TypedView<'a, T> {
+    type Output = T;
+
+    fn index(&self, index: usize) -> &Self::Output {
+        if self.len == 0 || !self.in_bounds(index, 1) {
+            panic!("out of bounds")
+        }
+
+        unsafe { &*self.addr.add(self.wrap(index)) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VALID_BUF_LEN: usize = 1 << 16;
+
+    #[test]
+    fn view_len() {
+        let buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        assert_eq!(VALID_BUF_LEN / 8, buf.view::<u64>().len());
+        assert_eq!(VALID_BUF_LEN / 4, buf.view::<[u8; 4]>().len());
+    }
+
+    #[test]
+    fn view_index_wrap_around() {
+        let mut buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        buf.write_all_wrapping(VALID_BUF_LEN - 4, &7u32.to_ne_bytes());
+
+        let view = buf.view::<u32>();
+        let len = view.len();
+        assert_eq!(7, view[len - 1]);
+        assert_eq!(7, view[2 * len - 1]);
+    }
+
+    #[test]
+    fn view_slice_wrap_around() {
+        let mut buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        let bytes = [1u16, 2, 3, 4]
+            .iter()
+            .flat_map(|v| v.to_ne_bytes())
+            .collect::<Vec<_>>();
+        buf.write_all_wrapping(VALID_BUF_LEN - 4, &bytes);
+
+        let view = buf.view::<u16>();
+        assert_eq!(&[1, 2, 3, 4], view.slice(view.len() - 2, 4));
+    }
+
+    #[test]
+    fn view_follows_rotation() {
+        let mut buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        buf.write_all_wrapping(4, &7u32.to_ne_bytes());
+        buf.rotate_left(4);
+
+        let view = buf.view::<u32>();
+        assert_eq!(7, view[0]);
+        assert_eq!(7, view.slice(view.len(), 1)[0]);
+        assert_eq!(7, *view.first().expect("should not be empty"));
+    }
+
+    #[test]
+    #[should_panic(expected = "rotation must be a multiple of the element size")]
+    fn view_requires_aligned_rotation() {
+        let mut buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        buf.rotate_left(2);
+        buf.view::<u32>();
+    }
+
+    #[test]
+    fn multiple_views() {
+        let mut buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        buf.fill(0xFF);
+        let a = buf.view::<u32>();
+        let b = buf.view::<i8>();
+        assert_eq!(u32::MAX, a[0]);
+        assert_eq!(-1, b[0]);
+    }
+
+    #[test]
+    fn view_of_empty_buffer() {
+        let buf = MagicBuffer::default();
+        let view = buf.view::<u64>();
+        assert!(view.is_empty());
+        assert!(view.slice(0, 0).is_empty());
+
+        let mut buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        buf.truncate(3);
+        assert!(buf.view::<u32>().is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "out of bounds")]
+    fn view_of_empty_buffer_index() {
+        let buf = MagicBuffer::default();
+        let _ = buf.view::<u32>()[0];
+    }
+
+    #[test]
+    fn view_of_truncated_buffer() {
+        let mut buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        buf.truncate(10);
+        let view = buf.view::<u32>();
+        assert_eq!(2, view.len());
+        assert_eq!(&[0, 0], view.slice(0, 2));
+        assert_eq!(0, view[1]);
+    }
+
+    #[test]
+    #[should_panic(expected = "out of bounds")]
+    fn view_of_truncated_buffer_index() {
+        let mut buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        buf.truncate(8);
+        let _ = buf.view::<u32>()[2];
+    }
+
+    #[test]
+    #[should_panic]
+    fn view_requires_power_of_two_size() {
+        let buf = MagicBuffer::new(VALID_BUF_LEN).expect("should allocate buffer");
+        buf.view::<[u8; 3]>();
+    }
+}
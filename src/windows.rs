@@ -1,23 +1,33 @@
 // This implementation is based on
 // https://learn.microsoft.com/en-us/windows/win32/api/memoryapi/nf-memoryapi-virtualalloc2
 
-use crate::MagicBufferError;
+use crate::{HugePageSize, MagicBufferError, MagicBufferOptions};
 
 use std::cmp::max;
-use std::{mem::MaybeUninit, ptr};
+use std::ffi::CStr;
+use std::fs::File;
+use std::os::windows::io::{AsRawHandle, FromRawHandle, OwnedHandle, RawHandle};
+use std::{io, mem::MaybeUninit, ptr};
 
 use windows_sys::Win32::{
-    Foundation::{CloseHandle, FALSE, INVALID_HANDLE_VALUE},
+    Foundation::{CloseHandle, ERROR_PRIVILEGE_NOT_HELD, FALSE, HANDLE, INVALID_HANDLE_VALUE},
     System::{
         Memory::{
-            CreateFileMappingA, MapViewOfFile3, UnmapViewOfFile, VirtualAlloc2, VirtualFree,
-            MEM_PRESERVE_PLACEHOLDER, MEM_RELEASE, MEM_REPLACE_PLACEHOLDER, MEM_RESERVE,
-            MEM_RESERVE_PLACEHOLDER, PAGE_NOACCESS, PAGE_READWRITE,
+            CreateFileMappingA, FlushViewOfFile, GetLargePageMinimum, MapViewOfFile3,
+            MemExtendedParameterAddressRequirements, UnmapViewOfFile, VirtualAlloc2, VirtualFree,
+            VirtualLock, VirtualProtect, VirtualUnlock, MEM_ADDRESS_REQUIREMENTS,
+            MEM_EXTENDED_PARAMETER, MEM_EXTENDED_PARAMETER_0, MEM_EXTENDED_PARAMETER_1,
+            MEM_LARGE_PAGES, MEM_PRESERVE_PLACEHOLDER, MEM_RELEASE, MEM_REPLACE_PLACEHOLDER,
+            MEM_RESERVE, MEM_RESERVE_PLACEHOLDER, PAGE_NOACCESS, PAGE_PROTECTION_FLAGS,
+            PAGE_READONLY, PAGE_READWRITE, SEC_COMMIT, SEC_LARGE_PAGES,
         },
         SystemInformation::{self, SYSTEM_INFO},
     },
 };
 
+/// The buffer keeps its file mapping open, so that it can be handed off to another process.
+pub(super) type MagicBufBacking = OwnedHandle;
+
 pub(super) unsafe fn magic_buf_min_len() -> usize {
     let mut sys_info = MaybeUninit::<SYSTEM_INFO>::zeroed();
     SystemInformation::GetSystemInfo(sys_info.as_mut_ptr());
@@ -26,37 +36,211 @@ pub(super) unsafe fn magic_buf_min_len() -> usize {
     max(sys_info.dwPageSize, sys_info.dwAllocationGranularity) as usize
 }
 
-pub(super) unsafe fn magic_buf_alloc(len: usize) -> Result<*mut u8, MagicBufferError> {
-    let placeholder1 = VirtualAlloc2(
+/// Returns the len of the inaccessible guard region after the views. Placeholders can
+/// only be split at the allocation granularity, so the guard is that large if enabled.
+unsafe fn magic_buf_guard_len(guard: bool) -> usize {
+    if guard {
+        magic_buf_min_len()
+    } else {
+        0
+    }
+}
+
+pub(super) unsafe fn magic_buf_alloc(
+    options: &MagicBufferOptions,
+) -> Result<(*mut u8, MagicBufBacking, Option<usize>), MagicBufferError> {
+    if options.mirror_count != 2 {
+        return Err(MagicBufferError::Unsupported {
+            msg: "more than two mirrors are not supported on windows".to_string(),
+        });
+    }
+
+    if let Some(huge_pages) = options.huge_pages {
+        match magic_buf_alloc_large(options, huge_pages) {
+            Ok((addr, backing, page_len)) => return Ok((addr, backing, Some(page_len))),
+            Err(err) if options.strict => return Err(err),
+            // large pages may not be available (or exhausted), fall back to regular pages
+            Err(_) => {}
+        }
+    }
+
+    let (addr, backing) = magic_buf_alloc_hinted(options, 0)?;
+    Ok((addr, backing, None))
+}
+
+/// Allocates the buffer with large pages, which requires the `SeLockMemoryPrivilege`
+/// to be enabled for the process. Returns the len of the large pages on success.
+unsafe fn magic_buf_alloc_large(
+    options: &MagicBufferOptions,
+    huge_pages: HugePageSize,
+) -> Result<(*mut u8, MagicBufBacking, usize), MagicBufferError> {
+    // the large page size is fixed by the hardware, the requested size must be a multiple
+    let page_len = GetLargePageMinimum();
+    if page_len == 0 {
+        return Err(MagicBufferError::Unsupported {
+            msg: "large pages are not supported by the processor".to_string(),
+        });
+    }
+
+    if huge_pages.bytes() & (page_len - 1) != 0 {
+        return Err(MagicBufferError::Unsupported {
+            msg: format!("huge pages must be a multiple of the large page size, {page_len}"),
+        });
+    }
+
+    let (addr, backing) = magic_buf_alloc_hinted(options, page_len)?;
+    Ok((addr, backing, page_len))
+}
+
+/// Allocates the buffer at the address hint of `options`, if any. Views are backed by
+/// large pages and aligned to `large_page_len`, unless it is 0.
+unsafe fn magic_buf_alloc_hinted(
+    options: &MagicBufferOptions,
+    large_page_len: usize,
+) -> Result<(*mut u8, MagicBufBacking), MagicBufferError> {
+    let guard_len = magic_buf_guard_len(options.guard_page);
+    let alloc = |hint| {
+        magic_buf_alloc_named(
+            options.len,
+            hint,
+            ptr::null(),
+            INVALID_HANDLE_VALUE,
+            guard_len,
+            large_page_len,
+        )
+    };
+
+    // VirtualAlloc2 fails if the hinted range is in use, retry anywhere unless fixed
+    let hint = options.address_hint.unwrap_or(0) as *mut u8;
+    match alloc(hint) {
+        Err(MagicBufferError::AddressUnavailable) if !options.fixed_address => {
+            alloc(ptr::null_mut())
+        }
+        result => result,
+    }
+}
+
+pub(super) unsafe fn magic_buf_open_shared(
+    name: &CStr,
+    len: usize,
+) -> Result<(*mut u8, MagicBufBacking), MagicBufferError> {
+    // opens the existing file mapping if another process created it already
+    magic_buf_alloc_named(
+        len,
+        ptr::null_mut(),
+        name.as_ptr() as _,
+        INVALID_HANDLE_VALUE,
+        0,
         0,
+    )
+}
+
+pub(super) unsafe fn magic_buf_map_file(
+    file: File,
+    len: usize,
+) -> Result<(*mut u8, MagicBufBacking), MagicBufferError> {
+    // the file mapping keeps the file open, it is closed when `file` is dropped
+    magic_buf_alloc_named(
+        len,
+        ptr::null_mut(),
         ptr::null(),
-        2 * len,
+        file.as_raw_handle() as HANDLE,
+        0,
+        0,
+    )
+}
+
+pub(super) fn magic_buf_unlink_shared(_name: &CStr) -> io::Result<()> {
+    // named file mappings are removed when the last handle to them is closed
+    Ok(())
+}
+
+unsafe fn magic_buf_alloc_named(
+    len: usize,
+    hint: *mut u8,
+    name: *const u8,
+    file: HANDLE,
+    guard_len: usize,
+    large_page_len: usize,
+) -> Result<(*mut u8, MagicBufBacking), MagicBufferError> {
+    // large page views must be aligned to the large page size, an explicit hint has to
+    // be aligned by the caller
+    let mut requirements = MEM_ADDRESS_REQUIREMENTS {
+        LowestStartingAddress: ptr::null_mut(),
+        HighestEndingAddress: ptr::null_mut(),
+        Alignment: large_page_len,
+    };
+    let mut param = MEM_EXTENDED_PARAMETER {
+        Anonymous1: MEM_EXTENDED_PARAMETER_0 {
+            _bitfield: MemExtendedParameterAddressRequirements as u64,
+        },
+        Anonymous2: MEM_EXTENDED_PARAMETER_1 {
+            Pointer: &mut requirements as *mut _ as _,
+        },
+    };
+    let (params, param_count) = if large_page_len > 0 && hint.is_null() {
+        (&mut param as *mut _, 1)
+    } else {
+        (ptr::null_mut(), 0)
+    };
+
+    let placeholder1 = VirtualAlloc2(
+        0,
+        hint as _,
+        2 * len + guard_len,
         MEM_RESERVE | MEM_RESERVE_PLACEHOLDER,
         PAGE_NOACCESS,
-        ptr::null_mut(),
-        0,
+        params,
+        param_count,
     );
 
     if placeholder1.is_null() {
-        return Err(MagicBufferError::OOM);
+        if !hint.is_null() {
+            return Err(MagicBufferError::AddressUnavailable);
+        }
+        return Err(MagicBufferError::last_os_oom());
     }
 
     if VirtualFree(placeholder1, len, MEM_RELEASE | MEM_PRESERVE_PLACEHOLDER) == FALSE {
-        return Err(MagicBufferError::OOM);
+        return Err(MagicBufferError::last_os_oom());
     }
 
-    let handle = CreateFileMappingA(
-        INVALID_HANDLE_VALUE,
-        ptr::null(),
-        PAGE_READWRITE,
-        0,
-        len as u32,
-        ptr::null(),
-    );
+    // split the guard off the second placeholder, it stays reserved but inaccessible
+    if guard_len > 0
+        && VirtualFree(
+            placeholder1.add(len),
+            len,
+            MEM_RELEASE | MEM_PRESERVE_PLACEHOLDER,
+        ) == FALSE
+    {
+        return Err(MagicBufferError::last_os_oom());
+    }
+
+    let (protect, view_flags) = if large_page_len > 0 {
+        // large page sections must be committed up front
+        (
+            PAGE_READWRITE | SEC_COMMIT | SEC_LARGE_PAGES,
+            MEM_REPLACE_PLACEHOLDER | MEM_LARGE_PAGES,
+        )
+    } else {
+        (PAGE_READWRITE, MEM_REPLACE_PLACEHOLDER)
+    };
+
+    let handle = CreateFileMappingA(file, ptr::null(), protect, 0, len as u32, name);
 
     if handle == 0 {
+        let source = io::Error::last_os_error();
+        let err = if source.raw_os_error() == Some(ERROR_PRIVILEGE_NOT_HELD as i32) {
+            MagicBufferError::Unsupported {
+                msg: "large pages require the SeLockMemoryPrivilege".to_string(),
+            }
+        } else {
+            MagicBufferError::OOM {
+                code: source.raw_os_error(),
+            }
+        };
         VirtualFree(placeholder1, 0, MEM_RELEASE);
-        return Err(MagicBufferError::OOM);
+        return Err(err);
     }
 
     let view1 = MapViewOfFile3(
@@ -65,15 +249,17 @@ pub(super) unsafe fn magic_buf_alloc(len: usize) -> Result<*mut u8, MagicBufferE
         placeholder1,
         0,
         len,
-        MEM_REPLACE_PLACEHOLDER,
+        view_flags,
         PAGE_READWRITE,
         ptr::null_mut(),
         0,
     );
 
     if view1 == 0 {
+        let err = MagicBufferError::last_os_oom();
         VirtualFree(placeholder1, 0, MEM_RELEASE);
-        return Err(MagicBufferError::OOM);
+        CloseHandle(handle);
+        return Err(err);
     }
 
     let placeholder2 = placeholder1.add(len);
@@ -83,19 +269,77 @@ pub(super) unsafe fn magic_buf_alloc(len: usize) -> Result<*mut u8, MagicBufferE
         placeholder2,
         0,
         len,
-        MEM_REPLACE_PLACEHOLDER,
+        view_flags,
         PAGE_READWRITE,
         ptr::null_mut(),
         0,
     );
 
     assert_ne!(0, view2);
-    CloseHandle(handle);
 
-    Ok(view1 as *mut _)
+    Ok((
+        view1 as *mut _,
+        OwnedHandle::from_raw_handle(handle as RawHandle),
+    ))
 }
 
-pub(super) unsafe fn magic_buf_free(addr: *mut u8, len: usize) {
+pub(super) unsafe fn magic_buf_free(addr: *mut u8, len: usize, _mirrors: usize, guard: bool) {
     UnmapViewOfFile(addr.add(len) as _);
     UnmapViewOfFile(addr as _);
+    if guard {
+        VirtualFree(addr.add(2 * len) as _, 0, MEM_RELEASE);
+    }
+}
+
+pub(super) unsafe fn magic_buf_flush(addr: *mut u8, len: usize, _sync: bool) -> io::Result<()> {
+    // this only starts writing the dirty pages, the file itself is flushed by the caller
+    if FlushViewOfFile(addr as _, len) == FALSE {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+/// Makes both views of the buffer read-only, or readable and writable again. The views
+/// are separate allocations, so each of them needs to be protected on its own.
+pub(super) unsafe fn magic_buf_protect(
+    addr: *mut u8,
+    len: usize,
+    mirrors: usize,
+    writable: bool,
+) -> Result<(), MagicBufferError> {
+    let protect = if writable {
+        PAGE_READWRITE
+    } else {
+        PAGE_READONLY
+    };
+
+    for view in (0..mirrors).map(|i| addr.add(i * len)) {
+        let mut old: PAGE_PROTECTION_FLAGS = 0;
+        if VirtualProtect(view as _, len, protect, &mut old) == FALSE {
+            return Err(MagicBufferError::last_os_oom());
+        }
+    }
+
+    Ok(())
+}
+
+pub(super) unsafe fn magic_buf_lock(addr: *const u8, len: usize) -> Result<(), MagicBufferError> {
+    if VirtualLock(addr as _, len) == FALSE {
+        return Err(MagicBufferError::LockFailed {
+            source: io::Error::last_os_error(),
+        });
+    }
+
+    Ok(())
+}
+
+pub(super) unsafe fn magic_buf_unlock(addr: *const u8, len: usize) -> Result<(), MagicBufferError> {
+    if VirtualUnlock(addr as _, len) == FALSE {
+        return Err(MagicBufferError::LockFailed {
+            source: io::Error::last_os_error(),
+        });
+    }
+
+    Ok(())
 }